@@ -0,0 +1,115 @@
+//! Encode and decode throughput per message type.
+//!
+//! Criterion reports the time and the throughput of every benchmark and compares them to the previous run.
+//! To compare a change against a fixed baseline, save one first:
+//! ```text
+//! cargo bench --bench codec -- --save-baseline main
+//! cargo bench --bench codec -- --baseline main
+//! ```
+use apple_ble::advertisement::{AdvertisementType, APPLE_MAGIC};
+use bluer::Address;
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+const ADDRESS: Address = Address([0xd1, 0x22, 0x33, 0x44, 0x55, 0x66]);
+
+/// One captured or constructed message per supported type, skipping the ones whose feature is disabled.
+const MESSAGES: &[(&str, &[u8])] = &[
+    #[cfg(feature = "airdrop")]
+    (
+        "AirDrop",
+        &[
+            0x05, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xfe, 0xdc, 0x76,
+            0x54, 0xba, 0x98, 0xba, 0x98, 0x00,
+        ],
+    ),
+    #[cfg(feature = "airplay")]
+    ("AirPlaySource", &[0x0a, 0x01, 0x00]),
+    #[cfg(feature = "airplay")]
+    (
+        "AirPlayTarget",
+        &[0x09, 0x06, 0x03, 0x07, 0xc0, 0xa8, 0x01, 0x02],
+    ),
+    #[cfg(feature = "airprint")]
+    (
+        "AirPrint",
+        &[
+            0x03, 0x16, 0x74, 0x07, 0x6f, 0x02, 0x77, 0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xc5,
+        ],
+    ),
+    #[cfg(feature = "findmy")]
+    (
+        "FindMy",
+        &[
+            0x12, 0x19, 0x00, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11,
+            0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x02, 0x00,
+        ],
+    ),
+    #[cfg(feature = "findmy")]
+    ("FindMyNearby", &[0x12, 0x02, 0x04, 0x02]),
+    #[cfg(feature = "nearby")]
+    ("NearbyInfo", &[0x10, 0x05, 0x17, 0x1c, 0xaa, 0xbb, 0xcc]),
+    #[cfg(feature = "handoff")]
+    (
+        "Handoff",
+        &[
+            0x0c, 0x0e, 0x08, 0x12, 0x34, 0xab, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07,
+            0x08, 0x09,
+        ],
+    ),
+    #[cfg(feature = "tethering")]
+    (
+        "TetheringSource",
+        &[0x0e, 0x06, 0x01, 0x11, 0x64, 0x00, 0x07, 0x03],
+    ),
+    #[cfg(feature = "watch")]
+    ("WatchConnection", &[0x0b, 0x04, 0x13, 0x00, 0x39, 0x2e]),
+];
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for &(name, message) in MESSAGES {
+        group.throughput(Throughput::Bytes(message.len() as u64));
+        group.bench_function(name, |b| {
+            b.iter(|| AdvertisementType::from_manufacturer_data(ADDRESS, black_box(message)))
+        });
+    }
+    group.finish();
+}
+
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for &(name, message) in MESSAGES {
+        let advertisement = AdvertisementType::from_manufacturer_data(ADDRESS, message)
+            .unwrap_or_else(|| panic!("The {name} message doesn't decode."));
+        group.throughput(Throughput::Bytes(message.len() as u64));
+        group.bench_function(name, |b| b.iter(|| black_box(&advertisement).octets()));
+    }
+    group.finish();
+}
+
+/// Decoding raw advertising data includes parsing the AD structures, as a scanner would.
+///
+/// The AirPlay Target message is wrapped, or the first enabled one, since the parsing doesn't depend on the type.
+fn decode_advertising_data(c: &mut Criterion) {
+    let Some(&(name, message)) = MESSAGES
+        .iter()
+        .find(|(name, _)| *name == "AirPlayTarget")
+        .or(MESSAGES.first())
+    else {
+        return;
+    };
+    // The length covers the AD type, the company id and the message.
+    let mut advertising_data = vec![0x02, 0x01, 0x06, 3 + message.len() as u8, 0xff];
+    advertising_data.extend(APPLE_MAGIC.to_le_bytes());
+    advertising_data.extend(message);
+    let mut group = c.benchmark_group("decode_advertising_data");
+    group.throughput(Throughput::Bytes(advertising_data.len() as u64));
+    group.bench_function(name, |b| {
+        b.iter(|| AdvertisementType::from_advertising_data(ADDRESS, black_box(&advertising_data)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, decode, encode, decode_advertising_data);
+criterion_main!(benches);
@@ -1,233 +1,1952 @@
 use std::fmt::Debug;
-use std::net::{Ipv4Addr, Ipv6Addr};
-use std::{collections::BTreeMap, error::Error, time::Duration};
+use std::marker::PhantomData;
+#[cfg(feature = "airplay")]
+use std::net::Ipv4Addr;
+#[cfg(feature = "airprint")]
+use std::net::Ipv6Addr;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    error::Error,
+    time::Duration,
+};
 
 #[cfg(feature = "disable_afit")]
 use async_trait::async_trait;
-use bluer::adv::{Advertisement, AdvertisementHandle, Type};
-use bluer::{Device, Address};
+use bluer::adv::{Advertisement, AdvertisementHandle, SecondaryChannel, Type};
+#[cfg(all(feature = "gatt", any(feature = "airplay", feature = "airprint")))]
+use bluer::gatt::local::Application;
+use bluer::{Address, Device};
 use futures::executor;
+use tokio::task::JoinHandle;
 
-use crate::session::Session;
-use crate::util::set_device_addr;
+use crate::error::{AppleBleError, DecodeError, Operation};
+#[cfg(all(feature = "gatt", feature = "airprint"))]
+use crate::gatt::airprint_application;
+#[cfg(all(feature = "gatt", feature = "airplay"))]
+use crate::gatt::device_information_application;
+#[cfg(all(feature = "gatt", any(feature = "airplay", feature = "airprint")))]
+use crate::gatt::ConnectableAdvertisementHandle;
+use crate::registry::DecoderRegistry;
+use crate::session::{AdvertiserPermit, Session};
+#[cfg(feature = "airplay")]
+use crate::util::host_name;
+#[cfg(any(feature = "serde", feature = "openhaystack"))]
+use crate::util::to_hex_string;
+#[cfg(feature = "airdrop")]
+use crate::util::{hash_normalized, hash_raw, parse_hex_prefix};
+#[cfg(feature = "findmy")]
+use crate::util::{
+    restore_device_addr, run_blocking, set_device_addr, spawn_set_static_random_addr,
+};
 
-const APPLE_MAGIC: u16 = 0x4c;
+/// Company id assigned to Apple by the Bluetooth SIG.
+pub const APPLE_MAGIC: u16 = 0x4c;
+/// Company id reserved by the Bluetooth SIG for internal and test use.
+pub const TEST_COMPANY_ID: u16 = 0xffff;
 
-pub trait AdvertisableData: Clone + PartialEq + Debug {
+/// Revision of the Continuity protocol a message layout belongs to.
+///
+/// Apple changes the layout of some messages between iOS releases.
+/// Decoders, which are given a revision, reject messages using the layout of another one.
+/// It defaults to the latest known revision.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ContinuityVersion {
+    /// iOS 12 and earlier.
+    Ios12,
+    /// iOS 13 and later.
+    #[default]
+    Ios13,
+}
+
+/// Data carried by an advertisement.
+///
+/// The data is required to be [Send] and [Sync], so the futures returned by [Advertisable::register] are [Send] and can be spawned on a multi-threaded runtime.
+pub trait AdvertisableData: Clone + PartialEq + Debug + Send + Sync {
+    /// The Continuity message type byte, which [octets](Self::octets) starts with.
+    const MESSAGE_TYPE: u8;
     fn octets(&self) -> Vec<u8>;
 }
 
 // If the user opted out of using "async_fn_in_trait", use the crate async-trait instead.
 #[cfg_attr(feature = "disable_afit", async_trait)]
 /// Any kind of advertisement.
-pub trait Advertisable<T: AdvertisableData> {
+///
+/// All implementations in this crate are [Send] and [Sync], as are the futures they return.
+/// Note that [FindMyAdvertisement] changes the device address while registering, see [prepare_adapter](Advertisable::prepare_adapter), which blocks the executing thread until the bluetooth service restarted.
+pub trait Advertisable<T: AdvertisableData>: Send + Sync {
     /// Advertisement-specific: validate user supplied data.
     fn validate_user_data(_user_data: &T) -> Result<(), Box<dyn Error>> {
         Ok(())
     }
+    /// The local name advertised by the [default advertisement](Self::default_advertisement).
+    ///
+    /// Most Apple devices don't name themselves in Continuity advertisements, so it defaults to none.
+    fn default_local_name() -> Option<String> {
+        None
+    }
+    /// The advertisement [assemble_advertisement](Self::assemble_advertisement) starts from.
+    ///
+    /// Override it to change how a type is advertised, e.g. the advertising intervals.
+    fn default_advertisement(_session: &Session) -> Advertisement {
+        Advertisement {
+            advertisement_type: Type::Broadcast,
+            local_name: Self::default_local_name(),
+            timeout: Some(Duration::from_millis(0)),
+            min_interval: Some(Duration::from_millis(100)),
+            max_interval: Some(Duration::from_millis(200)),
+            ..Default::default()
+        }
+    }
     /// Advertisement-specific: assemble user supplied data to advertisement.
+    ///
+    /// By default this adds the user supplied data as manufacturer data to the [default advertisement](Self::default_advertisement).
     fn assemble_advertisement(
         session: &mut Session,
         user_data: &T,
-    ) -> Result<Advertisement, Box<dyn Error>>;
+    ) -> Result<Advertisement, Box<dyn Error>> {
+        Ok(Advertisement {
+            manufacturer_data: BTreeMap::from([(session.company_id, user_data.octets())]),
+            ..Self::default_advertisement(session)
+        })
+    }
+    /// Advertisement-specific: change the adapter before the advertisement is registered, e.g. the device address.
+    ///
+    /// Only registering calls it, assembling and [preflight](Self::preflight) leave the adapter untouched.
+    fn prepare_adapter(_session: &mut Session, _user_data: &T) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+    /// Assembles the advertisement and applies `params` on top, see [AdvertisementParams] for the precedence.
+    fn assemble_with_params(
+        session: &mut Session,
+        user_data: &T,
+        params: &AdvertisementParams,
+    ) -> Result<Advertisement, Box<dyn Error>> {
+        let advertisement = Self::assemble_advertisement(session, user_data)?;
+        Ok(params.merge(&session.default_params).apply(advertisement))
+    }
+    /// Runs the checks, which would otherwise only fail inside BlueZ, without registering the advertisement.
+    ///
+    /// Every problem found is reported at once.
+    async fn preflight(session: &mut Session, user_data: &T) -> Result<(), Vec<AppleBleError>> {
+        Self::validate_user_data(user_data)
+            .map_err(|err| vec![AppleBleError::InvalidUserData(err.to_string())])?;
+        let advertisement =
+            Self::assemble_with_params(session, user_data, &AdvertisementParams::default())
+                .map_err(|err| vec![AppleBleError::InvalidUserData(err.to_string())])?;
+        preflight_advertisement(session, &advertisement).await
+    }
     /// Register any advertisement.
     async fn register(
         session: &mut Session,
         user_data: &T,
     ) -> Result<AdvertisementHandle, Box<dyn Error>> {
-        Self::validate_user_data(user_data)?;
-        let advertisement = Self::assemble_advertisement(session, user_data)?;
-        Ok(session.adapter.advertise(advertisement).await?)
+        Self::register_with_params(session, user_data, &AdvertisementParams::default()).await
+    }
+    /// Register any advertisement with `params` overriding the defaults.
+    async fn register_with_params(
+        session: &mut Session,
+        user_data: &T,
+        params: &AdvertisementParams,
+    ) -> Result<AdvertisementHandle, Box<dyn Error>> {
+        Self::validate_user_data(user_data)
+            .map_err(|err| registration_error::<Self>(Operation::Assemble, err))?;
+        Self::prepare_adapter(session, user_data)
+            .map_err(|err| registration_error::<Self>(Operation::PrepareAdapter, err))?;
+        let advertisement = Self::assemble_with_params(session, user_data, params)
+            .map_err(|err| registration_error::<Self>(Operation::Assemble, err))?;
+        let advertisement = fit_to_controller(session, advertisement).await?;
+        let handle = session
+            .adapter
+            .advertise(advertisement)
+            .await
+            .map_err(|err| registration_error::<Self>(Operation::Advertise, err.into()))?;
+        session.record_registration();
+        Ok(handle)
+    }
+    /// Register the advertisement, if the controller has a free advertising instance.
+    ///
+    /// Fails with [AppleBleError::TooManyAdvertisers] otherwise, see [Session::advertiser_limit].
+    async fn register_limited(
+        session: &mut Session,
+        user_data: &T,
+    ) -> Result<LimitedAdvertisementHandle, Box<dyn Error>> {
+        let permit = session.advertiser_limit.try_acquire()?;
+        Ok(LimitedAdvertisementHandle {
+            handle: Self::register(session, user_data).await?,
+            permit,
+        })
+    }
+    /// Like [register_limited](Self::register_limited), but waits for an advertising instance to become free.
+    async fn register_queued(
+        session: &mut Session,
+        user_data: &T,
+    ) -> Result<LimitedAdvertisementHandle, Box<dyn Error>> {
+        let permit = session.advertiser_limit.acquire().await;
+        Ok(LimitedAdvertisementHandle {
+            handle: Self::register(session, user_data).await?,
+            permit,
+        })
+    }
+    /// Register an advertisement, whose payload is recomputed by calling `update_fn` every `interval`.
+    ///
+    /// Every update is validated and checked against the controller like in [register_with_params](Self::register_with_params).
+    /// The updater runs on its own task and stops, together with the advertisement, once the returned handle is dropped.
+    /// It also stops on the first update failing, which [error](DynamicAdvertisementHandle::error) returns.
+    /// This isn't suitable for FindMy, since the device address can only be changed once.
+    async fn register_dynamic<F>(
+        session: &mut Session,
+        initial: &T,
+        mut update_fn: F,
+        interval: Duration,
+    ) -> Result<DynamicAdvertisementHandle, Box<dyn Error>>
+    where
+        Self: 'static,
+        T: Send + 'static,
+        F: FnMut() -> T + Send + 'static,
+    {
+        let mut handle = Self::register(session, initial).await?;
+        let mut session = session.clone();
+        let updater = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let user_data = update_fn();
+                let advertisement = match Self::validate_user_data(&user_data).and_then(|_| {
+                    Self::assemble_with_params(
+                        &mut session,
+                        &user_data,
+                        &AdvertisementParams::default(),
+                    )
+                }) {
+                    Ok(advertisement) => advertisement,
+                    Err(err) => return registration_error::<Self>(Operation::Assemble, err),
+                };
+                let advertisement = match fit_to_controller(&session, advertisement).await {
+                    Ok(advertisement) => advertisement,
+                    Err(err) => return registration_error::<Self>(Operation::Assemble, err),
+                };
+                // Only one advertisement should be live at a time.
+                drop(handle);
+                handle = match session.adapter.advertise(advertisement).await {
+                    Ok(handle) => handle,
+                    Err(err) => {
+                        return registration_error::<Self>(Operation::Advertise, err.into())
+                    }
+                };
+            }
+        });
+        Ok(DynamicAdvertisementHandle { updater })
+    }
+}
+
+/// The name of an advertisement type, e.g. `FindMy` for [FindMyAdvertisement].
+pub fn advertisement_name<A: ?Sized>() -> String {
+    let name = std::any::type_name::<A>();
+    let name = name.rsplit("::").next().unwrap_or(name);
+    name.strip_suffix("Advertisement")
+        .unwrap_or(name)
+        .to_string()
+}
+/// Fits the interval of an assembled advertisement to the controller and checks it against the limits, which BlueZ would reject it for.
+async fn fit_to_controller(
+    session: &Session,
+    advertisement: Advertisement,
+) -> Result<Advertisement, Box<dyn Error>> {
+    let min_allowed = session
+        .min_advertising_interval(advertisement.advertisement_type)
+        .await;
+    let (advertisement, warning) =
+        fit_interval(advertisement, min_allowed, session.clamp_intervals)?;
+    if warning.is_some() {
+        session.record_clamped_interval();
+    }
+    check_length(&advertisement, session.max_advertisement_length().await)?;
+    check_advertising_data(&advertisement)?;
+    check_secondary_channel(
+        &advertisement,
+        &session.supported_secondary_channels().await,
+    )?;
+    check_tx_power(&advertisement, session.tx_power_range().await)?;
+    Ok(advertisement)
+}
+/// Adds the advertisement type and the failed `operation` to `err`, unless it already carries them.
+fn registration_error<A: ?Sized>(operation: Operation, err: Box<dyn Error>) -> AppleBleError {
+    if let Some(err @ AppleBleError::Registration { .. }) = err.downcast_ref::<AppleBleError>() {
+        return err.clone();
+    }
+    AppleBleError::Registration {
+        advertisement: advertisement_name::<A>(),
+        operation,
+        reason: err.to_string(),
+    }
+}
+
+/// Parameters overriding the [default advertisement](Advertisable::default_advertisement) of a type.
+///
+/// Every field, which isn't set for a single registration, falls back to [Session::default_params].
+/// If it isn't set there either, the default of the advertisement type is used.
+///
+/// There are no parameters for the scan response, since bluer doesn't support emitting one.
+/// Fields, like the local name, are always part of the advertisement itself.
+/// Neither is there a peer address, since BlueZ only registers undirected advertisements.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct AdvertisementParams {
+    pub local_name: Option<String>,
+    pub timeout: Option<Duration>,
+    pub min_interval: Option<Duration>,
+    pub max_interval: Option<Duration>,
+    pub tx_power: Option<i16>,
+    /// PHY of the extended advertising data, e.g. [SecondaryChannel::Coded] for long range, see [Session::supported_secondary_channels].
+    pub secondary_channel: Option<SecondaryChannel>,
+    /// Length the manufacturer data, excluding the company id, is padded to with trailing zeros, to match the exact layout of a captured device.
+    ///
+    /// Longer manufacturer data isn't truncated.
+    pub padded_length: Option<usize>,
+    /// Manufacturer data of other companies, advertised alongside the one of [Session::company_id], which takes precedence.
+    ///
+    /// The entries aren't padded to [padded_length](Self::padded_length).
+    pub additional_manufacturer_data: Option<BTreeMap<u16, Vec<u8>>>,
+    /// AD structures of other types, keyed by their AD type, e.g. `0x24` for a URI, which BlueZ advertises through the `Data` property.
+    ///
+    /// Types, which BlueZ derives from other properties, are rejected, see [check_advertising_data].
+    /// Set service data through [Advertisement::service_data] and the discoverable flag through [Advertisement::discoverable] instead.
+    pub additional_advertising_data: Option<BTreeMap<u8, Vec<u8>>>,
+}
+impl AdvertisementParams {
+    /// Fills the fields, which aren't set, from `base`.
+    pub fn merge(&self, base: &Self) -> Self {
+        AdvertisementParams {
+            local_name: self.local_name.clone().or_else(|| base.local_name.clone()),
+            timeout: self.timeout.or(base.timeout),
+            min_interval: self.min_interval.or(base.min_interval),
+            max_interval: self.max_interval.or(base.max_interval),
+            tx_power: self.tx_power.or(base.tx_power),
+            secondary_channel: self.secondary_channel.or(base.secondary_channel),
+            padded_length: self.padded_length.or(base.padded_length),
+            additional_manufacturer_data: self
+                .additional_manufacturer_data
+                .clone()
+                .or_else(|| base.additional_manufacturer_data.clone()),
+            additional_advertising_data: self
+                .additional_advertising_data
+                .clone()
+                .or_else(|| base.additional_advertising_data.clone()),
+        }
+    }
+    /// Overrides the fields of `advertisement`, which are set.
+    pub fn apply(&self, mut advertisement: Advertisement) -> Advertisement {
+        if let Some(padded_length) = self.padded_length {
+            for data in advertisement.manufacturer_data.values_mut() {
+                if data.len() < padded_length {
+                    data.resize(padded_length, 0x00);
+                }
+            }
+        }
+        for (company_id, data) in self.additional_manufacturer_data.iter().flatten() {
+            advertisement
+                .manufacturer_data
+                .entry(*company_id)
+                .or_insert_with(|| data.clone());
+        }
+        for (ad_type, data) in self.additional_advertising_data.iter().flatten() {
+            advertisement
+                .advertisting_data
+                .entry(*ad_type)
+                .or_insert_with(|| data.clone());
+        }
+        Advertisement {
+            local_name: self.local_name.clone().or(advertisement.local_name),
+            timeout: self.timeout.or(advertisement.timeout),
+            min_interval: self.min_interval.or(advertisement.min_interval),
+            max_interval: self.max_interval.or(advertisement.max_interval),
+            tx_power: self.tx_power.or(advertisement.tx_power),
+            secondary_channel: self.secondary_channel.or(advertisement.secondary_channel),
+            ..advertisement
+        }
+    }
+}
+
+/// Shortest advertising interval allowed by the Bluetooth specification.
+pub const MIN_ADVERTISING_INTERVAL: Duration = Duration::from_millis(20);
+/// Longest advertising interval allowed by the Bluetooth specification.
+pub const MAX_ADVERTISING_INTERVAL: Duration = Duration::from_millis(10240);
+
+/// Shortest interval for non-connectable advertising on controllers predating Bluetooth 5.0.
+pub const LEGACY_MIN_NONCONNECTABLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Checks the minimum interval of `advertisement` against `min_allowed`, see [Session::min_advertising_interval].
+pub fn check_interval(
+    advertisement: &Advertisement,
+    min_allowed: Duration,
+) -> Result<(), AppleBleError> {
+    match advertisement.min_interval {
+        Some(requested) if requested < min_allowed => Err(AppleBleError::IntervalUnsupported {
+            requested,
+            min_allowed,
+        }),
+        _ => Ok(()),
+    }
+}
+/// Like [check_interval], but if `clamp` is set, a too short interval is raised to `min_allowed` instead.
+///
+/// The error the check would have failed with is returned next to the raised advertisement as a warning.
+pub fn fit_interval(
+    advertisement: Advertisement,
+    min_allowed: Duration,
+    clamp: bool,
+) -> Result<(Advertisement, Option<AppleBleError>), AppleBleError> {
+    match check_interval(&advertisement, min_allowed) {
+        Err(warning @ AppleBleError::IntervalUnsupported { .. }) if clamp => Ok((
+            Advertisement {
+                min_interval: Some(min_allowed),
+                max_interval: advertisement
+                    .max_interval
+                    .map(|max_interval| max_interval.max(min_allowed)),
+                ..advertisement
+            },
+            Some(warning),
+        )),
+        Err(err) => Err(err),
+        Ok(()) => Ok((advertisement, None)),
+    }
+}
+
+/// Checks that the controller supports the [secondary channel](Advertisement::secondary_channel) of `advertisement`.
+pub fn check_secondary_channel(
+    advertisement: &Advertisement,
+    supported: &BTreeSet<SecondaryChannel>,
+) -> Result<(), AppleBleError> {
+    match advertisement.secondary_channel {
+        Some(phy) if !supported.contains(&phy) => Err(AppleBleError::PhyUnsupported { phy }),
+        _ => Ok(()),
     }
 }
+
+/// Checks that the [transmit power](Advertisement::tx_power) of `advertisement` lies within `range`, see [Session::tx_power_range].
+pub fn check_tx_power(
+    advertisement: &Advertisement,
+    (min, max): (i16, i16),
+) -> Result<(), AppleBleError> {
+    match advertisement.tx_power {
+        Some(requested) if !(min..=max).contains(&requested) => {
+            Err(AppleBleError::TxPowerUnsupported {
+                requested,
+                min,
+                max,
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Checks whether the advertising data of `advertisement` fits into `max_length` bytes, see [Session::max_advertisement_length].
+///
+/// Besides the manufacturer and the [additional advertising data](AdvertisementParams::additional_advertising_data) this counts the local name, the appearance and the flags, which BlueZ adds to peripheral and discoverable advertisements.
+pub fn check_length(advertisement: &Advertisement, max_length: usize) -> Result<(), AppleBleError> {
+    // Every entry is framed by a length and the AD type, manufacturer data also by the company id.
+    let flags = advertisement.advertisement_type == Type::Peripheral
+        || advertisement.discoverable == Some(true);
+    let length: usize = advertisement
+        .manufacturer_data
+        .values()
+        .map(|data| 4 + data.len())
+        .chain(
+            advertisement
+                .advertisting_data
+                .values()
+                .map(|data| 2 + data.len()),
+        )
+        .chain(advertisement.local_name.iter().map(|name| 2 + name.len()))
+        .chain(advertisement.appearance.map(|_| 4))
+        .chain(flags.then_some(3))
+        .sum();
+    if length > max_length {
+        return Err(AppleBleError::PayloadTooLong {
+            length,
+            max: max_length,
+        });
+    }
+    Ok(())
+}
+/// AD types, which BlueZ rejects in the `Data` property of an advertisement.
+///
+/// BlueZ derives them from other properties or manages them itself, like the flags, the service UUIDs and data, the local name, the appearance and the manufacturer data.
+/// Those are set through the matching fields of [Advertisement], e.g. [service_data](Advertisement::service_data) for service data (`0x16`) and [discoverable](Advertisement::discoverable) for the flags (`0x01`).
+pub const RESERVED_AD_TYPES: [u8; 39] = [
+    0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12,
+    0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d, 0x1e, 0x1f, 0x20, 0x21, 0x22, 0x23,
+    0x27, 0x28, 0x29, 0x2a, 0x2b, 0x3d, 0xff,
+];
+/// Checks that the [additional advertising data](AdvertisementParams::additional_advertising_data) of `advertisement` avoids the [RESERVED_AD_TYPES].
+pub fn check_advertising_data(advertisement: &Advertisement) -> Result<(), AppleBleError> {
+    match advertisement
+        .advertisting_data
+        .keys()
+        .find(|ad_type| RESERVED_AD_TYPES.contains(ad_type))
+    {
+        Some(ad_type) => Err(AppleBleError::InvalidUserData(format!(
+            "The AD type {ad_type:#04x} is set by BlueZ and can't be advertised as additional data, set the matching property of the advertisement instead."
+        ))),
+        None => Ok(()),
+    }
+}
+/// Checks an assembled advertisement against the limits enforced by BlueZ.
+///
+/// `max_length` is the maximum length of the advertising data, see [Session::max_advertisement_length].
+pub fn validate_advertisement(
+    advertisement: &Advertisement,
+    max_length: usize,
+) -> Vec<AppleBleError> {
+    let mut problems = Vec::new();
+    if advertisement.manufacturer_data.is_empty() {
+        problems.push(AppleBleError::MissingField("manufacturer_data"));
+    }
+    if let Err(problem) = check_length(advertisement, max_length) {
+        problems.push(problem);
+    }
+    if let Err(problem) = check_advertising_data(advertisement) {
+        problems.push(problem);
+    }
+    let min = advertisement
+        .min_interval
+        .unwrap_or(MIN_ADVERTISING_INTERVAL);
+    let max = advertisement.max_interval.unwrap_or(min);
+    if min < MIN_ADVERTISING_INTERVAL || max > MAX_ADVERTISING_INTERVAL || min > max {
+        problems.push(AppleBleError::InvalidInterval { min, max });
+    }
+    problems
+}
+async fn preflight_advertisement(
+    session: &Session,
+    advertisement: &Advertisement,
+) -> Result<(), Vec<AppleBleError>> {
+    let mut problems =
+        validate_advertisement(advertisement, session.max_advertisement_length().await);
+    if !session.clamp_intervals {
+        let min_allowed = session
+            .min_advertising_interval(advertisement.advertisement_type)
+            .await;
+        if let Err(problem) = check_interval(advertisement, min_allowed) {
+            problems.push(problem);
+        }
+    }
+    if let Err(problem) =
+        check_secondary_channel(advertisement, &session.supported_secondary_channels().await)
+    {
+        problems.push(problem);
+    }
+    if let Err(problem) = check_tx_power(advertisement, session.tx_power_range().await) {
+        problems.push(problem);
+    }
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(problems)
+    }
+}
+
+/// An advertisement, which can be paused and resumed without losing its configuration.
+///
+/// Resuming assembles the advertisement again from the stored user data and parameters.
+/// This isn't suitable for FindMy, since the device address can only be changed once.
+pub struct PausableAdvertisement<T: AdvertisableData, A: Advertisable<T>> {
+    session: Session,
+    user_data: T,
+    params: AdvertisementParams,
+    handle: Option<AdvertisementHandle>,
+    advertisable: PhantomData<A>,
+}
+impl<T: AdvertisableData, A: Advertisable<T>> PausableAdvertisement<T, A> {
+    /// Registers the advertisement, see [register_with_params](Advertisable::register_with_params).
+    pub async fn register(
+        session: &Session,
+        user_data: T,
+        params: AdvertisementParams,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut advertisement = PausableAdvertisement {
+            session: session.clone(),
+            user_data,
+            params,
+            handle: None,
+            advertisable: PhantomData,
+        };
+        advertisement.resume().await?;
+        Ok(advertisement)
+    }
+    /// Whether the advertisement is currently registered.
+    pub fn is_active(&self) -> bool {
+        self.handle.is_some()
+    }
+    /// Stops advertising until [resume](Self::resume) is called.
+    pub fn pause(&mut self) {
+        drop(self.handle.take());
+    }
+    /// Registers the advertisement again, if it's paused.
+    pub async fn resume(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.handle.is_none() {
+            self.handle = Some(
+                A::register_with_params(&mut self.session, &self.user_data, &self.params).await?,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Handle to an advertisement occupying one of the advertising instances of the [Session].
+///
+/// The instance is released, together with the advertisement, once it is dropped.
+pub struct LimitedAdvertisementHandle {
+    pub handle: AdvertisementHandle,
+    pub permit: AdvertiserPermit,
+}
+
+/// Handle to an advertisement, which is updated in the background.
+/// It is returned by [register_dynamic](Advertisable::register_dynamic) and [register_round_robin](FindMyAdvertisement::register_round_robin).
+///
+/// Dropping it stops the updater and the advertisement.
+pub struct DynamicAdvertisementHandle {
+    updater: JoinHandle<AppleBleError>,
+}
+impl DynamicAdvertisementHandle {
+    /// Returns true if the updater stopped, because assembling or registering an updated advertisement failed.
+    pub fn is_finished(&self) -> bool {
+        self.updater.is_finished()
+    }
+    /// Waits for the updater to stop and returns the error it stopped on.
+    ///
+    /// The updater never stops on its own otherwise, so this only returns once an update failed.
+    pub async fn error(mut self) -> AppleBleError {
+        match (&mut self.updater).await {
+            Ok(err) => err,
+            // The updater is only aborted when the handle is dropped, so it panicked.
+            Err(err) => std::panic::resume_unwind(err.into_panic()),
+        }
+    }
+}
+impl Drop for DynamicAdvertisementHandle {
+    fn drop(&mut self) {
+        self.updater.abort();
+    }
+}
+
+/// A decoded Apple advertisement.
+///
+/// It owns its data and [registers](Self::register) it with the matching advertiser,
+/// so constructing one, e.g. through [air_print](Self::air_print), spares pairing an advertiser with its data.
+#[derive(Clone, PartialEq, Debug)]
 pub enum AdvertisementType {
+    #[cfg(feature = "airdrop")]
     AirDrop(AirDropAdvertisementData),
-    AirPlaySource, // Carries no dynamic data.
+    #[cfg(feature = "airplay")]
+    AirPlaySource(AirPlaySourceAdvertisementData),
+    #[cfg(feature = "airplay")]
     AirPlayTarget(AirPlayTargetAdvertisementData),
+    #[cfg(feature = "airprint")]
     AirPrint(AirPrintAdvertisementData),
+    #[cfg(feature = "findmy")]
     FindMy(FindMyAdvertisementData),
+    /// The short FindMy message, which shares the message type of [FindMy](Self::FindMy).
+    #[cfg(feature = "findmy")]
+    FindMyNearby(FindMyNearbyAdvertisementData),
+    #[cfg(feature = "nearby")]
+    NearbyInfo(NearbyInfoAdvertisementData),
+    #[cfg(feature = "handoff")]
+    Handoff(HandoffAdvertisementData),
+    #[cfg(feature = "tethering")]
+    TetheringSource(TetheringSourceAdvertisementData),
+    #[cfg(feature = "watch")]
+    WatchConnection(WatchConnectionAdvertisementData),
+    /// A message without a decoder, see [DecoderRegistry](crate::registry::DecoderRegistry).
+    ///
+    /// `data` is the complete message, including the type and length bytes.
+    Unknown { message_type: u8, data: Vec<u8> },
 }
+impl AdvertisementType {
+    /// Decodes Apple manufacturer data, starting with the message type byte, e.g. from a capture instead of a [Device].
+    ///
+    /// The decoders are looked up in the [global registry](DecoderRegistry::global).
+    pub fn from_manufacturer_data(address: Address, manufacturer_data: &[u8]) -> Option<Self> {
+        DecoderRegistry::global().decode(address, manufacturer_data)
+    }
+    /// Decodes the Apple manufacturer data of raw advertising data, consisting of AD structures, see [parse_manufacturer_data].
+    pub fn from_advertising_data(address: Address, advertising_data: &[u8]) -> Option<Self> {
+        Self::from_manufacturer_data(
+            address,
+            parse_manufacturer_data(advertising_data).get(&APPLE_MAGIC)?,
+        )
+    }
+    /// Decodes a single manufacturer specific AD structure, including its length, AD type and company id, e.g. `0b ff 4c 00 09 06 ..`.
+    ///
+    /// Some scan backends hand out the framed structure instead of the bare manufacturer data, which [from_manufacturer_data](Self::from_manufacturer_data) expects.
+    /// [None] is returned, unless the length covers exactly the structure and it carries [APPLE_MAGIC].
+    pub fn from_ad_structure(address: Address, ad_structure: &[u8]) -> Option<Self> {
+        let (&length, structure) = ad_structure.split_first()?;
+        if structure.len() != length as usize {
+            return None;
+        }
+        match structure {
+            [0xff, company_id_low, company_id_high, data @ ..]
+                if u16::from_le_bytes([*company_id_low, *company_id_high]) == APPLE_MAGIC =>
+            {
+                Self::from_manufacturer_data(address, data)
+            }
+            _ => None,
+        }
+    }
+    /// An AirDrop advertisement, see [AirDropAdvertisementData::new].
+    #[cfg(feature = "airdrop")]
+    pub fn air_drop(
+        apple_id: &str,
+        phone: &str,
+        email: &str,
+        mode: AirDropMode,
+        hashing: ContactHashing,
+    ) -> Result<Self, AppleBleError> {
+        Self::validated::<_, AirDropAdvertisement>(
+            AirDropAdvertisementData::new(apple_id, phone, email, mode, hashing),
+            AdvertisementType::AirDrop,
+        )
+    }
+    /// An AirPlay target advertisement with the initial [ConfigSeed].
+    #[cfg(feature = "airplay")]
+    pub fn air_play_target(ip_address: Ipv4Addr) -> Result<Self, AppleBleError> {
+        Self::validated::<_, AirPlayTargetAdvertisement>(
+            AirPlayTargetAdvertisementData::new(ip_address),
+            AdvertisementType::AirPlayTarget,
+        )
+    }
+    /// An AirPrint advertisement with the usual [address type](AIRPRINT_ADDRESS_TYPE), [resource path](AIRPRINT_RESOURCE_PATH) and [security](AirPrintSecurity::Ipps).
+    ///
+    /// Like [AirPrintAdvertisement], a port, which isn't one of the [PRINTING_PORTS], only causes a warning.
+    #[cfg(feature = "airprint")]
+    pub fn air_print(port: u16, ip_addr: Ipv6Addr, power: u8) -> Result<Self, AppleBleError> {
+        Self::validated::<_, AirPrintAdvertisement>(
+            AirPrintAdvertisementData {
+                address_type: AIRPRINT_ADDRESS_TYPE,
+                resource_path: AIRPRINT_RESOURCE_PATH,
+                security: AirPrintSecurity::default(),
+                port,
+                ip_addr,
+                power,
+            },
+            AdvertisementType::AirPrint,
+        )
+    }
+    /// A FindMy advertisement of `public_key`, see [FindMyAdvertisementData::new].
+    #[cfg(feature = "findmy")]
+    pub fn find_my(public_key: [u8; 28]) -> Result<Self, AppleBleError> {
+        Self::validated::<_, FindMyAdvertisement>(
+            FindMyAdvertisementData::new(public_key),
+            AdvertisementType::FindMy,
+        )
+    }
+    /// Wraps `user_data` after validating it with the advertiser, which [register](Self::register) dispatches to.
+    #[cfg(any(
+        feature = "airdrop",
+        feature = "airplay",
+        feature = "airprint",
+        feature = "findmy"
+    ))]
+    fn validated<T: AdvertisableData, A: Advertisable<T>>(
+        user_data: T,
+        wrap: fn(T) -> Self,
+    ) -> Result<Self, AppleBleError> {
+        A::validate_user_data(&user_data)
+            .map_err(|err| AppleBleError::InvalidUserData(err.to_string()))?;
+        Ok(wrap(user_data))
+    }
+    /// The Continuity message type byte.
+    pub fn message_type(&self) -> u8 {
+        match self {
+            #[cfg(feature = "airdrop")]
+            AdvertisementType::AirDrop(_) => AirDropAdvertisementData::MESSAGE_TYPE,
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlaySource(_) => AirPlaySourceAdvertisementData::MESSAGE_TYPE,
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlayTarget(_) => AirPlayTargetAdvertisementData::MESSAGE_TYPE,
+            #[cfg(feature = "airprint")]
+            AdvertisementType::AirPrint(_) => AirPrintAdvertisementData::MESSAGE_TYPE,
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMy(_) => FindMyAdvertisementData::MESSAGE_TYPE,
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMyNearby(_) => FindMyNearbyAdvertisementData::MESSAGE_TYPE,
+            #[cfg(feature = "nearby")]
+            AdvertisementType::NearbyInfo(_) => NearbyInfoAdvertisementData::MESSAGE_TYPE,
+            #[cfg(feature = "handoff")]
+            AdvertisementType::Handoff(_) => HandoffAdvertisementData::MESSAGE_TYPE,
+            #[cfg(feature = "tethering")]
+            AdvertisementType::TetheringSource(_) => TetheringSourceAdvertisementData::MESSAGE_TYPE,
+            #[cfg(feature = "watch")]
+            AdvertisementType::WatchConnection(_) => WatchConnectionAdvertisementData::MESSAGE_TYPE,
+            AdvertisementType::Unknown { message_type, .. } => *message_type,
+        }
+    }
+    /// Encodes the message again, starting with its type byte.
+    pub fn octets(&self) -> Vec<u8> {
+        match self {
+            #[cfg(feature = "airdrop")]
+            AdvertisementType::AirDrop(data) => data.octets(),
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlaySource(data) => data.octets(),
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlayTarget(data) => data.octets(),
+            #[cfg(feature = "airprint")]
+            AdvertisementType::AirPrint(data) => data.octets(),
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMy(data) => data.octets(),
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMyNearby(data) => data.octets(),
+            #[cfg(feature = "nearby")]
+            AdvertisementType::NearbyInfo(data) => data.octets(),
+            #[cfg(feature = "handoff")]
+            AdvertisementType::Handoff(data) => data.octets(),
+            #[cfg(feature = "tethering")]
+            AdvertisementType::TetheringSource(data) => data.octets(),
+            #[cfg(feature = "watch")]
+            AdvertisementType::WatchConnection(data) => data.octets(),
+            AdvertisementType::Unknown { data, .. } => data.clone(),
+        }
+    }
+    /// Applies `modify` to the advertisement, e.g. to change a single field of a decoded one before registering it again.
+    ///
+    /// ```ignore
+    /// let modified = decoded.with_modified(|advertisement| {
+    ///     if let AdvertisementType::NearbyInfo(data) = advertisement {
+    ///         data.action_code = 0x0b;
+    ///     }
+    /// });
+    /// let handle = modified.register(&mut session).await?;
+    /// ```
+    pub fn with_modified(mut self, modify: impl FnOnce(&mut AdvertisementType)) -> Self {
+        modify(&mut self);
+        self
+    }
+    /// Assembles the advertisement with the matching advertiser and `params`, without registering it.
+    ///
+    /// Unlike registering, this doesn't change the device address, see [Advertisable::prepare_adapter].
+    /// [AdvertisementType::Unknown] can't be assembled, since there is no advertiser for it.
+    pub fn to_advertisement(
+        &self,
+        session: &mut Session,
+        params: &AdvertisementParams,
+    ) -> Result<Advertisement, AppleBleError> {
+        fn assemble<T: AdvertisableData, A: Advertisable<T>>(
+            session: &mut Session,
+            user_data: &T,
+            params: &AdvertisementParams,
+        ) -> Result<Advertisement, AppleBleError> {
+            A::validate_user_data(user_data)
+                .and_then(|_| A::assemble_with_params(session, user_data, params))
+                .map_err(|err| registration_error::<A>(Operation::Assemble, err))
+        }
+        match self {
+            #[cfg(feature = "airdrop")]
+            AdvertisementType::AirDrop(data) => {
+                assemble::<_, AirDropAdvertisement>(session, data, params)
+            }
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlaySource(data) => {
+                assemble::<_, AirPlaySourceAdvertisement>(session, data, params)
+            }
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlayTarget(data) => {
+                assemble::<_, AirPlayTargetAdvertisement>(session, data, params)
+            }
+            #[cfg(feature = "airprint")]
+            AdvertisementType::AirPrint(data) => {
+                assemble::<_, AirPrintAdvertisement>(session, data, params)
+            }
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMy(data) => {
+                assemble::<_, FindMyAdvertisement>(session, data, params)
+            }
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMyNearby(data) => {
+                assemble::<_, FindMyNearbyAdvertisement>(session, data, params)
+            }
+            #[cfg(feature = "nearby")]
+            AdvertisementType::NearbyInfo(data) => {
+                assemble::<_, NearbyInfoAdvertisement>(session, data, params)
+            }
+            #[cfg(feature = "handoff")]
+            AdvertisementType::Handoff(data) => {
+                assemble::<_, HandoffAdvertisement>(session, data, params)
+            }
+            #[cfg(feature = "tethering")]
+            AdvertisementType::TetheringSource(data) => {
+                assemble::<_, TetheringSourceAdvertisement>(session, data, params)
+            }
+            #[cfg(feature = "watch")]
+            AdvertisementType::WatchConnection(data) => {
+                assemble::<_, WatchConnectionAdvertisement>(session, data, params)
+            }
+            AdvertisementType::Unknown { message_type, .. } => Err(AppleBleError::InvalidUserData(
+                format!("There is no advertiser for message type {message_type:#04x}."),
+            )),
+        }
+    }
+    /// Registers the advertisement with the matching advertiser.
+    ///
+    /// [AdvertisementType::Unknown] can't be registered, since there is no advertiser for it.
+    pub async fn register(
+        &self,
+        session: &mut Session,
+    ) -> Result<AdvertisementHandle, Box<dyn Error>> {
+        match self {
+            #[cfg(feature = "airdrop")]
+            AdvertisementType::AirDrop(data) => AirDropAdvertisement::register(session, data).await,
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlaySource(data) => {
+                AirPlaySourceAdvertisement::register(session, data).await
+            }
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlayTarget(data) => {
+                AirPlayTargetAdvertisement::register(session, data).await
+            }
+            #[cfg(feature = "airprint")]
+            AdvertisementType::AirPrint(data) => {
+                AirPrintAdvertisement::register(session, data).await
+            }
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMy(data) => FindMyAdvertisement::register(session, data).await,
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMyNearby(data) => {
+                FindMyNearbyAdvertisement::register(session, data).await
+            }
+            #[cfg(feature = "nearby")]
+            AdvertisementType::NearbyInfo(data) => {
+                NearbyInfoAdvertisement::register(session, data).await
+            }
+            #[cfg(feature = "handoff")]
+            AdvertisementType::Handoff(data) => HandoffAdvertisement::register(session, data).await,
+            #[cfg(feature = "tethering")]
+            AdvertisementType::TetheringSource(data) => {
+                TetheringSourceAdvertisement::register(session, data).await
+            }
+            #[cfg(feature = "watch")]
+            AdvertisementType::WatchConnection(data) => {
+                WatchConnectionAdvertisement::register(session, data).await
+            }
+            AdvertisementType::Unknown { message_type, .. } => Err(AppleBleError::InvalidUserData(
+                format!("There is no advertiser for message type {message_type:#04x}."),
+            )
+            .into()),
+        }
+    }
+    /// Whether both advertisements identify the same device, ignoring the fields, which change frequently.
+    ///
+    /// | type              | compared fields                                                   |
+    /// |-------------------|-------------------------------------------------------------------|
+    /// | `AirDrop`         | the contact hashes                                                |
+    /// | `AirPlaySource`   | `flags`                                                           |
+    /// | `AirPlayTarget`   | `ip_address`                                                      |
+    /// | `AirPrint`        | `port`, `ip_addr`                                                 |
+    /// | `FindMy`          | `public_key`                                                      |
+    /// | `FindMyNearby`    | `key_prefix`                                                      |
+    /// | `NearbyInfo`      | `status_flags`, `data_flags`, `auth_tag`                          |
+    /// | `Handoff`         | all but `sequence_number`                                         |
+    /// | `TetheringSource` | `version`, `credentials_available`, `flags`, `cell_service_type`  |
+    /// | `WatchConnection` | `status`, `data`                                                  |
+    ///
+    /// The action of a Nearby Info message, the counter of a Handoff message and the battery and signal levels of a hotspot change over time, so they're ignored.
+    /// The types without an identifier, like Nearby Info or Watch Connection, are compared by all remaining fields, which many devices may share, so a match only hints at the same device.
+    /// `Unknown` advertisements never match, not even an identical copy, just like advertisements of different types.
+    pub fn same_identity(&self, other: &AdvertisementType) -> bool {
+        match (self, other) {
+            #[cfg(feature = "airdrop")]
+            (AdvertisementType::AirDrop(a), AdvertisementType::AirDrop(b)) => {
+                (a.apple_id, a.phone, a.email, a.email2) == (b.apple_id, b.phone, b.email, b.email2)
+            }
+            #[cfg(feature = "airplay")]
+            (AdvertisementType::AirPlaySource(a), AdvertisementType::AirPlaySource(b)) => {
+                a.flags == b.flags
+            }
+            #[cfg(feature = "airplay")]
+            (AdvertisementType::AirPlayTarget(a), AdvertisementType::AirPlayTarget(b)) => {
+                a.ip_address == b.ip_address
+            }
+            #[cfg(feature = "airprint")]
+            (AdvertisementType::AirPrint(a), AdvertisementType::AirPrint(b)) => {
+                (a.port, a.ip_addr) == (b.port, b.ip_addr)
+            }
+            #[cfg(feature = "findmy")]
+            (AdvertisementType::FindMy(a), AdvertisementType::FindMy(b)) => {
+                a.public_key == b.public_key
+            }
+            #[cfg(feature = "findmy")]
+            (AdvertisementType::FindMyNearby(a), AdvertisementType::FindMyNearby(b)) => {
+                a.key_prefix == b.key_prefix
+            }
+            #[cfg(feature = "nearby")]
+            (AdvertisementType::NearbyInfo(a), AdvertisementType::NearbyInfo(b)) => {
+                (a.status_flags, a.data_flags, a.auth_tag)
+                    == (b.status_flags, b.data_flags, b.auth_tag)
+            }
+            #[cfg(feature = "handoff")]
+            (AdvertisementType::Handoff(a), AdvertisementType::Handoff(b)) => {
+                (a.clipboard, a.apple_id, a.auth_tag, a.encrypted_data)
+                    == (b.clipboard, b.apple_id, b.auth_tag, b.encrypted_data)
+            }
+            #[cfg(feature = "tethering")]
+            (AdvertisementType::TetheringSource(a), AdvertisementType::TetheringSource(b)) => {
+                (
+                    a.version,
+                    a.credentials_available,
+                    a.flags,
+                    a.cell_service_type,
+                ) == (
+                    b.version,
+                    b.credentials_available,
+                    b.flags,
+                    b.cell_service_type,
+                )
+            }
+            #[cfg(feature = "watch")]
+            (AdvertisementType::WatchConnection(a), AdvertisementType::WatchConnection(b)) => {
+                a == b
+            }
+            _ => false,
+        }
+    }
+    /// The fields, which differ from `other`, e.g. to find out why a re-encoded advertisement doesn't match the original.
+    ///
+    /// The values are compared in their [Debug] representation.
+    /// Advertisements of different types only differ in the field `type`, holding the names of the variants.
+    pub fn diff(&self, other: &AdvertisementType) -> Vec<FieldDiff> {
+        let mut diffs = Vec::new();
+        let mut field = |field: &'static str, old: &dyn Debug, new: &dyn Debug| {
+            let (old, new) = (format!("{old:?}"), format!("{new:?}"));
+            if old != new {
+                diffs.push(FieldDiff { field, old, new });
+            }
+        };
+        match (self, other) {
+            #[cfg(feature = "airdrop")]
+            (AdvertisementType::AirDrop(a), AdvertisementType::AirDrop(b)) => {
+                field("apple_id", &a.apple_id, &b.apple_id);
+                field("phone", &a.phone, &b.phone);
+                field("email", &a.email, &b.email);
+                field("email2", &a.email2, &b.email2);
+                field("mode", &a.mode, &b.mode);
+            }
+            #[cfg(feature = "airplay")]
+            (AdvertisementType::AirPlaySource(a), AdvertisementType::AirPlaySource(b)) => {
+                field("flags", &a.flags, &b.flags);
+            }
+            #[cfg(feature = "airplay")]
+            (AdvertisementType::AirPlayTarget(a), AdvertisementType::AirPlayTarget(b)) => {
+                field("ip_address", &a.ip_address, &b.ip_address);
+                field("config_seed", &a.config_seed, &b.config_seed);
+            }
+            #[cfg(feature = "airprint")]
+            (AdvertisementType::AirPrint(a), AdvertisementType::AirPrint(b)) => {
+                field("address_type", &a.address_type, &b.address_type);
+                field("resource_path", &a.resource_path, &b.resource_path);
+                field("security", &a.security, &b.security);
+                field("port", &a.port, &b.port);
+                field("ip_addr", &a.ip_addr, &b.ip_addr);
+                field("power", &a.power, &b.power);
+            }
+            #[cfg(feature = "findmy")]
+            (AdvertisementType::FindMy(a), AdvertisementType::FindMy(b)) => {
+                field("public_key", &a.public_key, &b.public_key);
+                field("status", &a.status, &b.status);
+            }
+            #[cfg(feature = "findmy")]
+            (AdvertisementType::FindMyNearby(a), AdvertisementType::FindMyNearby(b)) => {
+                field("key_prefix", &a.key_prefix, &b.key_prefix);
+                field("status", &a.status, &b.status);
+            }
+            #[cfg(feature = "nearby")]
+            (AdvertisementType::NearbyInfo(a), AdvertisementType::NearbyInfo(b)) => {
+                field("status_flags", &a.status_flags, &b.status_flags);
+                field("action_code", &a.action_code, &b.action_code);
+                field("data_flags", &a.data_flags, &b.data_flags);
+                field("auth_tag", &a.auth_tag, &b.auth_tag);
+                field("action_data", &a.action_data, &b.action_data);
+            }
+            #[cfg(feature = "handoff")]
+            (AdvertisementType::Handoff(a), AdvertisementType::Handoff(b)) => {
+                field("clipboard", &a.clipboard, &b.clipboard);
+                field("apple_id", &a.apple_id, &b.apple_id);
+                field("sequence_number", &a.sequence_number, &b.sequence_number);
+                field("auth_tag", &a.auth_tag, &b.auth_tag);
+                field("encrypted_data", &a.encrypted_data, &b.encrypted_data);
+            }
+            #[cfg(feature = "tethering")]
+            (AdvertisementType::TetheringSource(a), AdvertisementType::TetheringSource(b)) => {
+                field("version", &a.version, &b.version);
+                field(
+                    "credentials_available",
+                    &a.credentials_available,
+                    &b.credentials_available,
+                );
+                field("flags", &a.flags, &b.flags);
+                field("battery_life", &a.battery_life, &b.battery_life);
+                field(
+                    "cell_service_type",
+                    &a.cell_service_type,
+                    &b.cell_service_type,
+                );
+                field(
+                    "cell_service_strength",
+                    &a.cell_service_strength,
+                    &b.cell_service_strength,
+                );
+            }
+            #[cfg(feature = "watch")]
+            (AdvertisementType::WatchConnection(a), AdvertisementType::WatchConnection(b)) => {
+                field("status", &a.status, &b.status);
+                field("data", &a.data, &b.data);
+            }
+            (
+                AdvertisementType::Unknown {
+                    message_type: a_type,
+                    data: a_data,
+                },
+                AdvertisementType::Unknown {
+                    message_type: b_type,
+                    data: b_data,
+                },
+            ) => {
+                field("message_type", a_type, b_type);
+                field("data", a_data, b_data);
+            }
+            _ => diffs.push(FieldDiff {
+                field: "type",
+                old: self.variant_name().to_string(),
+                new: other.variant_name().to_string(),
+            }),
+        }
+        diffs
+    }
+    fn variant_name(&self) -> &'static str {
+        match self {
+            #[cfg(feature = "airdrop")]
+            AdvertisementType::AirDrop(_) => "AirDrop",
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlaySource(_) => "AirPlaySource",
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlayTarget(_) => "AirPlayTarget",
+            #[cfg(feature = "airprint")]
+            AdvertisementType::AirPrint(_) => "AirPrint",
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMy(_) => "FindMy",
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMyNearby(_) => "FindMyNearby",
+            #[cfg(feature = "nearby")]
+            AdvertisementType::NearbyInfo(_) => "NearbyInfo",
+            #[cfg(feature = "handoff")]
+            AdvertisementType::Handoff(_) => "Handoff",
+            #[cfg(feature = "tethering")]
+            AdvertisementType::TetheringSource(_) => "TetheringSource",
+            #[cfg(feature = "watch")]
+            AdvertisementType::WatchConnection(_) => "WatchConnection",
+            AdvertisementType::Unknown { .. } => "Unknown",
+        }
+    }
+}
+
+/// A field, which differs between two advertisements, see [AdvertisementType::diff].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FieldDiff {
+    pub field: &'static str,
+    /// The [Debug] representation of the field in the advertisement `diff` is called on.
+    pub old: String,
+    /// The [Debug] representation of the field in the other advertisement.
+    pub new: String,
+}
+
+/// Version of the JSON schema produced by [AdvertisementType::to_json].
+///
+/// It is bumped whenever a field is removed, renamed or changes its representation.
+#[cfg(feature = "serde")]
+pub const JSON_SCHEMA_VERSION: u32 = 2;
+
+#[cfg(feature = "serde")]
+impl AdvertisementType {
+    /// Converts the decoded advertisement to a JSON object with a stable schema.
+    ///
+    /// Every object carries a `type` tag, naming the variant, and the `version` of the schema ([JSON_SCHEMA_VERSION]).
+    /// Byte arrays are encoded as lowercase hex strings, addresses in their usual textual representation.
+    /// Missing optional fields, like the `auth_tag` of a short Nearby Info message, are `null`.
+    ///
+    /// | type            | fields                                  |
+    /// |-----------------|-----------------------------------------|
+    /// | `AirDrop`       | `apple_id`, `phone`, `email`, `email2`, `mode` |
+    /// | `AirPlaySource` | `flags`                                 |
+    /// | `AirPlayTarget` | `ip_address`, `config_seed`             |
+    /// | `AirPrint`      | `address_type`, `resource_path`, `security`, `port`, `ip_addr`, `power` |
+    /// | `FindMy`        | `public_key`, `address`, `status`       |
+    /// | `FindMyNearby`  | `key_prefix`, `address`, `status`       |
+    /// | `NearbyInfo`    | `status_flags`, `action_code`, `data_flags`, `auth_tag`, `action_data` |
+    /// | `Handoff`       | `clipboard`, `apple_id`, `sequence_number`, `auth_tag`, `encrypted_data` |
+    /// | `TetheringSource` | `credentials_available`, `flags`, `battery_life`, `cell_service_type`, `cell_service_strength` |
+    /// | `WatchConnection` | `status`, `data`                      |
+    /// | `Unknown`       | `message_type`, `data`                  |
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = match self {
+            #[cfg(feature = "airdrop")]
+            AdvertisementType::AirDrop(data) => serde_json::json!({
+                "type": "AirDrop",
+                "apple_id": to_hex_string(data.apple_id),
+                "phone": to_hex_string(data.phone),
+                "email": to_hex_string(data.email),
+                "email2": data.email2.map(to_hex_string),
+                "mode": format!("{:?}", data.mode),
+            }),
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlaySource(data) => serde_json::json!({
+                "type": "AirPlaySource",
+                "flags": data.flags,
+            }),
+            #[cfg(feature = "airplay")]
+            AdvertisementType::AirPlayTarget(data) => serde_json::json!({
+                "type": "AirPlayTarget",
+                "ip_address": data.ip_address.to_string(),
+                "config_seed": data.config_seed.0,
+            }),
+            #[cfg(feature = "airprint")]
+            AdvertisementType::AirPrint(data) => serde_json::json!({
+                "type": "AirPrint",
+                "address_type": data.address_type,
+                "resource_path": data.resource_path,
+                "security": format!("{:?}", data.security),
+                "port": data.port,
+                "ip_addr": data.ip_addr.to_string(),
+                "power": data.power,
+            }),
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMy(data) => serde_json::json!({
+                "type": "FindMy",
+                "public_key": to_hex_string(data.public_key),
+                "address": data.address().to_string(),
+                "status": format!("{:?}", data.status),
+            }),
+            #[cfg(feature = "findmy")]
+            AdvertisementType::FindMyNearby(data) => serde_json::json!({
+                "type": "FindMyNearby",
+                "key_prefix": to_hex_string(data.key_prefix),
+                "address": data.address().to_string(),
+                "status": format!("{:?}", data.status),
+            }),
+            #[cfg(feature = "nearby")]
+            AdvertisementType::NearbyInfo(data) => serde_json::json!({
+                "type": "NearbyInfo",
+                "status_flags": data.status_flags,
+                "action_code": data.action_code,
+                "data_flags": data.data_flags,
+                "auth_tag": data.auth_tag.map(to_hex_string),
+                "action_data": to_hex_string(&data.action_data),
+            }),
+            #[cfg(feature = "handoff")]
+            AdvertisementType::Handoff(data) => serde_json::json!({
+                "type": "Handoff",
+                "clipboard": data.clipboard,
+                "apple_id": data.apple_id,
+                "sequence_number": data.sequence_number,
+                "auth_tag": data.auth_tag,
+                "encrypted_data": to_hex_string(data.encrypted_data),
+            }),
+            #[cfg(feature = "tethering")]
+            AdvertisementType::TetheringSource(data) => serde_json::json!({
+                "type": "TetheringSource",
+                "credentials_available": data.credentials_available,
+                "flags": data.flags,
+                "battery_life": data.battery_life,
+                "cell_service_type": data.cell_service_type,
+                "cell_service_strength": data.cell_service_strength,
+            }),
+            #[cfg(feature = "watch")]
+            AdvertisementType::WatchConnection(data) => serde_json::json!({
+                "type": "WatchConnection",
+                "status": data.status,
+                "data": to_hex_string(&data.data),
+            }),
+            AdvertisementType::Unknown { message_type, data } => serde_json::json!({
+                "type": "Unknown",
+                "message_type": message_type,
+                "data": to_hex_string(data),
+            }),
+        };
+        value["version"] = JSON_SCHEMA_VERSION.into();
+        value
+    }
+}
+
+/// Declarative description of an advertisement, e.g. loaded from a config file.
+///
+/// The variants mirror [AdvertisementType] and are tagged by the field `type`:
+/// ```toml
+/// [[advertisements]]
+/// type = "AirPlayTarget"
+/// ip_address = "192.168.1.2"
+/// ```
+///
+/// It's only available, if at least one message type is enabled.
+#[cfg(all(
+    feature = "serde",
+    any(
+        feature = "airdrop",
+        feature = "airplay",
+        feature = "airprint",
+        feature = "findmy",
+        feature = "nearby",
+        feature = "handoff",
+        feature = "tethering",
+        feature = "watch"
+    )
+))]
+#[derive(Clone, PartialEq, Debug, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type")]
+pub enum AdvertisementSpec {
+    #[cfg(feature = "airdrop")]
+    AirDrop(AirDropAdvertisementData),
+    #[cfg(feature = "airplay")]
+    AirPlaySource(AirPlaySourceAdvertisementData),
+    #[cfg(feature = "airplay")]
+    AirPlayTarget(AirPlayTargetAdvertisementData),
+    #[cfg(feature = "airprint")]
+    AirPrint(AirPrintAdvertisementData),
+    #[cfg(feature = "findmy")]
+    FindMy(FindMyAdvertisementData),
+    #[cfg(feature = "findmy")]
+    FindMyNearby(FindMyNearbyAdvertisementData),
+    #[cfg(feature = "nearby")]
+    NearbyInfo(NearbyInfoAdvertisementData),
+    #[cfg(feature = "handoff")]
+    Handoff(HandoffAdvertisementData),
+    #[cfg(feature = "tethering")]
+    TetheringSource(TetheringSourceAdvertisementData),
+    #[cfg(feature = "watch")]
+    WatchConnection(WatchConnectionAdvertisementData),
+}
+#[cfg(all(
+    feature = "serde",
+    any(
+        feature = "airdrop",
+        feature = "airplay",
+        feature = "airprint",
+        feature = "findmy",
+        feature = "nearby",
+        feature = "handoff",
+        feature = "tethering",
+        feature = "watch"
+    )
+))]
+impl AdvertisementSpec {
+    /// Registers the advertisement with the matching advertiser, see [AdvertisementType::register].
+    pub async fn register(
+        &self,
+        session: &mut Session,
+    ) -> Result<AdvertisementHandle, Box<dyn Error>> {
+        AdvertisementType::from(self.clone())
+            .register(session)
+            .await
+    }
+}
+#[cfg(all(
+    feature = "serde",
+    any(
+        feature = "airdrop",
+        feature = "airplay",
+        feature = "airprint",
+        feature = "findmy",
+        feature = "nearby",
+        feature = "handoff",
+        feature = "tethering",
+        feature = "watch"
+    )
+))]
+impl From<AdvertisementSpec> for AdvertisementType {
+    fn from(spec: AdvertisementSpec) -> Self {
+        match spec {
+            #[cfg(feature = "airdrop")]
+            AdvertisementSpec::AirDrop(data) => AdvertisementType::AirDrop(data),
+            #[cfg(feature = "airplay")]
+            AdvertisementSpec::AirPlaySource(data) => AdvertisementType::AirPlaySource(data),
+            #[cfg(feature = "airplay")]
+            AdvertisementSpec::AirPlayTarget(data) => AdvertisementType::AirPlayTarget(data),
+            #[cfg(feature = "airprint")]
+            AdvertisementSpec::AirPrint(data) => AdvertisementType::AirPrint(data),
+            #[cfg(feature = "findmy")]
+            AdvertisementSpec::FindMy(data) => AdvertisementType::FindMy(data),
+            #[cfg(feature = "findmy")]
+            AdvertisementSpec::FindMyNearby(data) => AdvertisementType::FindMyNearby(data),
+            #[cfg(feature = "nearby")]
+            AdvertisementSpec::NearbyInfo(data) => AdvertisementType::NearbyInfo(data),
+            #[cfg(feature = "handoff")]
+            AdvertisementSpec::Handoff(data) => AdvertisementType::Handoff(data),
+            #[cfg(feature = "tethering")]
+            AdvertisementSpec::TetheringSource(data) => AdvertisementType::TetheringSource(data),
+            #[cfg(feature = "watch")]
+            AdvertisementSpec::WatchConnection(data) => AdvertisementType::WatchConnection(data),
+        }
+    }
+}
+
 pub fn get_adv_data_from_device(device: Device) -> Option<AdvertisementType> {
-    let binding = executor::block_on(device.manufacturer_data()).ok()??;
-    let manufacturer_data = binding.get(&APPLE_MAGIC)?;
-    match manufacturer_data[0] {
-        0x05 => Some(AdvertisementType::AirDrop(
-            AirDropAdvertisementData::try_from(manufacturer_data.clone()).ok()?,
+    get_adv_data_from_device_with_company_id(device, APPLE_MAGIC)
+}
+/// Like [get_adv_data_from_device], but decodes the manufacturer data of another company id, e.g. [TEST_COMPANY_ID].
+pub fn get_adv_data_from_device_with_company_id(
+    device: Device,
+    company_id: u16,
+) -> Option<AdvertisementType> {
+    executor::block_on(decode_device(&device, company_id))
+        .ok()
+        .flatten()
+}
+/// Decodes the manufacturer data of `company_id` advertised by the device, without blocking.
+///
+/// See [decode_manufacturer_data] for why decoding fails.
+pub async fn decode_device(
+    device: &Device,
+    company_id: u16,
+) -> Result<Option<AdvertisementType>, DecodeError> {
+    let manufacturer_data = device.manufacturer_data().await?;
+    decode_manufacturer_data(device.address(), manufacturer_data.as_ref(), company_id)
+}
+/// Decodes the manufacturer data of `company_id` out of all the manufacturer data a device advertises.
+///
+/// A device without any manufacturer data decodes to [None].
+/// The decoders are looked up in the [global registry](DecoderRegistry::global).
+pub fn decode_manufacturer_data(
+    address: Address,
+    manufacturer_data: Option<&HashMap<u16, Vec<u8>>>,
+    company_id: u16,
+) -> Result<Option<AdvertisementType>, DecodeError> {
+    let Some(manufacturer_data) = manufacturer_data.filter(|data| !data.is_empty()) else {
+        return Ok(None);
+    };
+    let Some(data) = manufacturer_data.get(&company_id) else {
+        let mut company_ids: Vec<u16> = manufacturer_data.keys().copied().collect();
+        company_ids.sort();
+        return Err(DecodeError::OtherCompanies { company_ids });
+    };
+    match DecoderRegistry::global().decode(address, data) {
+        Some(advertisement) => Ok(Some(advertisement)),
+        None => Err(DecodeError::Undecodable { data: data.clone() }),
+    }
+}
+/// Decodes the Apple manufacturer data advertised by the device with `address`.
+///
+/// The address is only used by message types, which encode part of their data in it, like FindMy.
+pub fn decode_apple(address: Address, manufacturer_data: &[u8]) -> Option<AdvertisementType> {
+    match *manufacturer_data.first()? {
+        #[cfg(feature = "airdrop")]
+        AirDropAdvertisementData::MESSAGE_TYPE => Some(AdvertisementType::AirDrop(
+            AirDropAdvertisementData::try_from(manufacturer_data.to_vec()).ok()?,
+        )),
+        #[cfg(feature = "airplay")]
+        AirPlaySourceAdvertisementData::MESSAGE_TYPE => Some(AdvertisementType::AirPlaySource(
+            AirPlaySourceAdvertisementData::try_from(manufacturer_data.to_vec()).ok()?,
+        )),
+        #[cfg(feature = "airplay")]
+        AirPlayTargetAdvertisementData::MESSAGE_TYPE => Some(AdvertisementType::AirPlayTarget(
+            AirPlayTargetAdvertisementData::try_from(manufacturer_data.to_vec()).ok()?,
+        )),
+        #[cfg(feature = "airprint")]
+        AirPrintAdvertisementData::MESSAGE_TYPE => Some(AdvertisementType::AirPrint(
+            AirPrintAdvertisementData::try_from(manufacturer_data.to_vec()).ok()?,
+        )),
+        // The short message is sent while the device is with its owner, the long one once it's separated.
+        #[cfg(feature = "findmy")]
+        FindMyAdvertisementData::MESSAGE_TYPE
+            if manufacturer_data.get(1) == Some(&FindMyNearbyAdvertisementData::PAYLOAD_LEN) =>
+        {
+            Some(AdvertisementType::FindMyNearby(
+                FindMyNearbyAdvertisementData::try_from((address, manufacturer_data.to_vec()))
+                    .ok()?,
+            ))
+        }
+        #[cfg(feature = "findmy")]
+        FindMyAdvertisementData::MESSAGE_TYPE => Some(AdvertisementType::FindMy(
+            FindMyAdvertisementData::try_from((address, manufacturer_data.to_vec())).ok()?,
         )),
-        0x0a => Some(AdvertisementType::AirPlaySource),
-        0x09 => Some(AdvertisementType::AirPlayTarget(
-            AirPlayTargetAdvertisementData::try_from(manufacturer_data.clone()).ok()?,
+        #[cfg(feature = "nearby")]
+        NearbyInfoAdvertisementData::MESSAGE_TYPE => Some(AdvertisementType::NearbyInfo(
+            NearbyInfoAdvertisementData::try_from(manufacturer_data.to_vec()).ok()?,
         )),
-        0x03 => Some(AdvertisementType::AirPrint(
-            AirPrintAdvertisementData::try_from(manufacturer_data.clone()).ok()?,
+        #[cfg(feature = "handoff")]
+        HandoffAdvertisementData::MESSAGE_TYPE => Some(AdvertisementType::Handoff(
+            HandoffAdvertisementData::try_from(manufacturer_data.to_vec()).ok()?,
         )),
-        0x12 => Some(AdvertisementType::FindMy(
-            FindMyAdvertisementData::try_from((device.address(), manufacturer_data.clone())).ok()?,
+        #[cfg(feature = "tethering")]
+        TetheringSourceAdvertisementData::MESSAGE_TYPE => Some(AdvertisementType::TetheringSource(
+            TetheringSourceAdvertisementData::try_from(manufacturer_data.to_vec()).ok()?,
+        )),
+        #[cfg(feature = "watch")]
+        WatchConnectionAdvertisementData::MESSAGE_TYPE => Some(AdvertisementType::WatchConnection(
+            WatchConnectionAdvertisementData::try_from(manufacturer_data.to_vec()).ok()?,
         )),
         _ => None,
     }
 }
 
+/// Extracts the manufacturer specific data from raw advertising data, keyed by the company id.
+///
+/// The AD structures are parsed until the data ends, a zero length ends it early, like the specification's padding does.
+/// The company id is transmitted little-endian, so Apple's data starts with `ff 4c 00`, which is keyed by [APPLE_MAGIC].
+pub fn parse_manufacturer_data(advertising_data: &[u8]) -> BTreeMap<u16, Vec<u8>> {
+    let mut manufacturer_data = BTreeMap::new();
+    let mut remaining = advertising_data;
+    while let Some(&length) = remaining.first() {
+        let Some(structure) = remaining.get(1..1 + length as usize) else {
+            break;
+        };
+        if length == 0 {
+            break;
+        }
+        if let [0xff, company_id_low, company_id_high, data @ ..] = structure {
+            manufacturer_data.insert(
+                u16::from_le_bytes([*company_id_low, *company_id_high]),
+                data.to_vec(),
+            );
+        }
+        remaining = &remaining[1 + length as usize..];
+    }
+    manufacturer_data
+}
+
+/// Who an AirDrop device is discoverable by.
+///
+/// It is encoded in the byte after the hashes, which furiousMAC documents as zero.
+/// The non-zero values aren't documented, so any byte besides the listed ones is kept as [Other](Self::Other).
+#[cfg(feature = "airdrop")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AirDropMode {
+    /// Receiving is off.
+    Off,
+    /// Only discoverable by contacts.
+    #[default]
+    ContactsOnly,
+    /// Discoverable by everyone.
+    Everyone,
+    /// A value none of the above are encoded as, kept so the message still decodes and re-encodes unchanged.
+    Other(u8),
+}
+#[cfg(feature = "airdrop")]
+impl From<AirDropMode> for u8 {
+    fn from(value: AirDropMode) -> Self {
+        match value {
+            AirDropMode::Off => 0x00,
+            AirDropMode::ContactsOnly => 0x01,
+            AirDropMode::Everyone => 0x02,
+            AirDropMode::Other(value) => value,
+        }
+    }
+}
+#[cfg(feature = "airdrop")]
+impl From<u8> for AirDropMode {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => AirDropMode::Off,
+            0x01 => AirDropMode::ContactsOnly,
+            0x02 => AirDropMode::Everyone,
+            value => AirDropMode::Other(value),
+        }
+    }
+}
+
+/// How [AirDropAdvertisementData::new] hashes the contact identifiers.
+///
+/// Every identifier is hashed on its own, there is no hash over a combined representation, as documented by furiousMAC and Celosia and Cunche ("Discontinued Privacy", PETS 2020).
+/// Published AirDrop sniffers match phone numbers as digits including the country code and lowercase email addresses, which is what [Normalized](Self::Normalized) produces.
+/// This hasn't been validated against an advertisement captured from an Apple device, so [Raw](Self::Raw) remains the default.
+#[cfg(feature = "airdrop")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ContactHashing {
+    /// The leading two bytes of the SHA-256 of the identifier exactly as supplied.
+    #[default]
+    Raw,
+    /// Like [Raw](Self::Raw), but email addresses are trimmed and lowercased, phone numbers are reduced to their digits, so `+1 (555) 010-0199` becomes `15550100199`.
+    ///
+    /// Phone numbers have to include their country code, which isn't added.
+    Normalized,
+}
+#[cfg(feature = "airdrop")]
+impl ContactHashing {
+    pub fn hash(self, input: &str) -> [u8; 2] {
+        match self {
+            ContactHashing::Raw => hash_raw(input),
+            ContactHashing::Normalized => hash_normalized(input),
+        }
+    }
+}
+
 /// Data for an AirDrop advertisement.
+///
+/// The identifiers are the first two bytes of the SHA-256 hash of the Apple ID, phone number and email address.
+/// The message has two email slots, the second one repeats the first email unless [email2](Self::email2) is set.
+#[cfg(feature = "airdrop")]
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AirDropAdvertisementData {
     pub apple_id: [u8; 2],
     pub phone: [u8; 2],
     pub email: [u8; 2],
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub email2: Option<[u8; 2]>,
+    pub mode: AirDropMode,
 }
+#[cfg(feature = "airdrop")]
+impl AirDropAdvertisementData {
+    /// Length of the message, excluding the type and the length byte.
+    pub const PAYLOAD_LEN: u8 = 0x12;
+    /// Hashes the contact identifiers with `hashing`.
+    pub fn new(
+        apple_id: &str,
+        phone: &str,
+        email: &str,
+        mode: AirDropMode,
+        hashing: ContactHashing,
+    ) -> Self {
+        AirDropAdvertisementData {
+            apple_id: hashing.hash(apple_id),
+            phone: hashing.hash(phone),
+            email: hashing.hash(email),
+            email2: None,
+            mode,
+        }
+    }
+    /// Uses already hashed contact identifiers, given as four hex digits each, e.g. `fedc` captured from another device.
+    ///
+    /// The [mode](AirDropMode) is the default one.
+    pub fn from_hex_prefixes(
+        apple_id: &str,
+        phone: &str,
+        email: &str,
+    ) -> Result<Self, AppleBleError> {
+        Ok(AirDropAdvertisementData {
+            apple_id: parse_hex_prefix(apple_id)?,
+            phone: parse_hex_prefix(phone)?,
+            email: parse_hex_prefix(email)?,
+            email2: None,
+            mode: AirDropMode::default(),
+        })
+    }
+}
+#[cfg(feature = "airdrop")]
 impl AdvertisableData for AirDropAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x05;
     fn octets(&self) -> Vec<u8> {
         [
-            vec![
-                0x05, // Message type
-                0x12, // Message length
-            ],
+            vec![Self::MESSAGE_TYPE, Self::PAYLOAD_LEN],
             vec![0; 8], // 8bytes of padding
             vec![0x01], // AirDrop version
             self.apple_id.to_vec(),
             self.phone.to_vec(),
             self.email.to_vec(),
-            self.email.to_vec(),
+            self.email2.unwrap_or(self.email).to_vec(),
+            vec![self.mode.into()],
         ]
         .concat()
     }
 }
+#[cfg(feature = "airdrop")]
 impl TryFrom<Vec<u8>> for AirDropAdvertisementData {
     type Error = Box<dyn Error>;
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let value = value
+            .get(..2 + Self::PAYLOAD_LEN as usize)
+            .ok_or("AirDrop message too short.")?;
+        let email: [u8; 2] = value[15..17].try_into()?;
+        let email2: [u8; 2] = value[17..19].try_into()?;
         Ok(AirDropAdvertisementData {
             apple_id: value[11..13].try_into()?,
             phone: value[13..15].try_into()?,
-            email: value[15..17].try_into()?,
+            email,
+            email2: (email2 != email).then_some(email2),
+            mode: AirDropMode::from(value[19]),
         })
     }
 }
 
 /// https://github.com/furiousMAC/continuity/blob/master/messages/airdrop.md
+#[cfg(feature = "airdrop")]
 pub struct AirDropAdvertisement;
-impl Advertisable<AirDropAdvertisementData> for AirDropAdvertisement {
-    fn assemble_advertisement(
-        session: &mut Session,
-        user_data: &AirDropAdvertisementData,
-    ) -> Result<Advertisement, Box<dyn Error>> {
-        Ok(Advertisement {
-            advertisement_type: Type::Broadcast,
-            local_name: Some(session.adapter.name().to_string()),
-            timeout: Some(Duration::from_millis(0)),
-            min_interval: Some(Duration::from_millis(100)),
-            max_interval: Some(Duration::from_millis(200)),
-            manufacturer_data: BTreeMap::from([(APPLE_MAGIC, user_data.octets())]),
-            ..Default::default()
-        })
-    }
-}
+#[cfg(feature = "airdrop")]
+impl Advertisable<AirDropAdvertisementData> for AirDropAdvertisement {}
 
 /// Data for an AirPlay source message
-#[derive(Clone, PartialEq, Debug)]
-pub struct AirPlaySourceAdvertisementData;
+///
+/// furiousMAC documents a single byte of data, whose meaning is unknown, so it's kept as is.
+/// Real devices vary it, reportedly with whether audio is playing, so a decoded message keeps the received value.
+/// The default is the `0x00` usually observed.
+#[cfg(feature = "airplay")]
+#[derive(Clone, PartialEq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AirPlaySourceAdvertisementData {
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub flags: u8,
+}
+#[cfg(feature = "airplay")]
+impl AirPlaySourceAdvertisementData {
+    /// Length of the message, excluding the type and the length byte.
+    pub const PAYLOAD_LEN: u8 = 0x01;
+}
+#[cfg(feature = "airplay")]
 impl AdvertisableData for AirPlaySourceAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x0a;
     fn octets(&self) -> Vec<u8> {
-        // This is constant.
-        vec![
-            0x0a, // Message type
-            0x01, // Message length
-            0x00,
-        ]
+        vec![Self::MESSAGE_TYPE, Self::PAYLOAD_LEN, self.flags]
     }
 }
+#[cfg(feature = "airplay")]
 impl TryFrom<Vec<u8>> for AirPlaySourceAdvertisementData {
     type Error = Box<dyn Error>;
-    fn try_from(_value: Vec<u8>) -> Result<Self, Self::Error> {
-        Ok(AirPlaySourceAdvertisementData {})
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        Ok(AirPlaySourceAdvertisementData {
+            flags: value
+                .get(2..2 + Self::PAYLOAD_LEN as usize)
+                .ok_or("AirPlay source message too short.")?[0],
+        })
     }
 }
 
 /// AirPlay source message https://github.com/furiousMAC/continuity/blob/master/messages/airplay_source.md
+#[cfg(feature = "airplay")]
 pub struct AirPlaySourceAdvertisement;
+#[cfg(feature = "airplay")]
 impl Advertisable<AirPlaySourceAdvertisementData> for AirPlaySourceAdvertisement {
-    /// The user_data field can be ignored.
-    fn assemble_advertisement(
-        session: &mut Session,
-        user_data: &AirPlaySourceAdvertisementData,
-    ) -> Result<Advertisement, Box<dyn Error>> {
-        Ok(Advertisement {
-            advertisement_type: Type::Broadcast,
-            local_name: Some(session.adapter.name().to_string()),
-            timeout: Some(Duration::from_millis(0)),
-            min_interval: Some(Duration::from_millis(100)),
-            max_interval: Some(Duration::from_millis(200)),
-            manufacturer_data: BTreeMap::from([(APPLE_MAGIC, user_data.octets())]),
-            ..Default::default()
-        })
+    /// AirPlay devices advertise their host name.
+    fn default_local_name() -> Option<String> {
+        host_name()
+    }
+}
+
+/// Config seed of an AirPlay target, which is incremented whenever its configuration changes.
+///
+/// A changed seed signals clients to fetch the device information again.
+/// It defaults to `0x07`, which this crate always advertised before the seed could be changed.
+#[cfg(feature = "airplay")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(transparent)
+)]
+pub struct ConfigSeed(pub u8);
+#[cfg(feature = "airplay")]
+impl Default for ConfigSeed {
+    fn default() -> Self {
+        ConfigSeed(0x07)
+    }
+}
+#[cfg(feature = "airplay")]
+impl ConfigSeed {
+    /// Signals a configuration change, wrapping around after `0xff`.
+    pub fn increment(&mut self) {
+        self.0 = self.0.wrapping_add(1);
     }
 }
 
 /// Data for an AirPlay target message
+#[cfg(feature = "airplay")]
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AirPlayTargetAdvertisementData {
     pub ip_address: Ipv4Addr,
+    /// Defaults to [ConfigSeed::default], also when it's missing while deserializing.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub config_seed: ConfigSeed,
 }
+#[cfg(feature = "airplay")]
+impl AirPlayTargetAdvertisementData {
+    /// Length of the message, excluding the type and the length byte.
+    pub const PAYLOAD_LEN: u8 = 0x06;
+    /// Data with the initial [ConfigSeed].
+    pub fn new(ip_address: Ipv4Addr) -> Self {
+        AirPlayTargetAdvertisementData {
+            ip_address,
+            config_seed: ConfigSeed::default(),
+        }
+    }
+}
+#[cfg(feature = "airplay")]
 impl AdvertisableData for AirPlayTargetAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x09;
     fn octets(&self) -> Vec<u8> {
         let ip_address = self.ip_address.octets();
         [
             vec![
-                0x09, // Message type
-                0x06, // Message length
-                0x03, 0x07,
+                Self::MESSAGE_TYPE,
+                Self::PAYLOAD_LEN,
+                0x03, // Flags
+                self.config_seed.0,
             ],
             ip_address.to_vec(),
         ]
         .concat()
     }
 }
+#[cfg(feature = "airplay")]
 impl TryFrom<Vec<u8>> for AirPlayTargetAdvertisementData {
     type Error = Box<dyn Error>;
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
-        let ip_address: [u8; 4] = value[4..8].try_into()?;
+        let payload = value
+            .get(3..2 + Self::PAYLOAD_LEN as usize)
+            .ok_or("AirPlay target message too short.")?;
+        let ip_address: [u8; 4] = payload[1..5].try_into()?;
         Ok(AirPlayTargetAdvertisementData {
             ip_address: Ipv4Addr::from(ip_address),
+            config_seed: ConfigSeed(payload[0]),
         })
     }
 }
 
 /// AirPlay target message https://github.com/furiousMAC/continuity/blob/master/messages/airplay_target.md
+#[cfg(feature = "airplay")]
 pub struct AirPlayTargetAdvertisement;
+#[cfg(feature = "airplay")]
 impl Advertisable<AirPlayTargetAdvertisementData> for AirPlayTargetAdvertisement {
-    fn assemble_advertisement(
+    /// AirPlay devices advertise their host name.
+    fn default_local_name() -> Option<String> {
+        host_name()
+    }
+}
+
+#[cfg(all(feature = "airplay", feature = "gatt"))]
+impl AirPlayTargetAdvertisement {
+    /// Register a connectable AirPlay target advertisement, serving a minimal GATT application.
+    ///
+    /// The application only exposes the Device Information service, with the manufacturer name "Apple Inc." and the supplied `model` (e.g. "AppleTV6,2") as model number.
+    /// This lets a connecting device proceed past discovery.
+    pub async fn register_connectable(
         session: &mut Session,
         user_data: &AirPlayTargetAdvertisementData,
-    ) -> Result<Advertisement, Box<dyn Error>> {
-        Ok(Advertisement {
-            advertisement_type: Type::Broadcast,
-            local_name: Some(session.adapter.name().to_string()),
-            timeout: Some(Duration::from_millis(0)),
-            min_interval: Some(Duration::from_millis(100)),
-            max_interval: Some(Duration::from_millis(200)),
-            manufacturer_data: BTreeMap::from([(APPLE_MAGIC, user_data.octets())]),
-            ..Default::default()
-        })
+        model: &str,
+    ) -> Result<ConnectableAdvertisementHandle, Box<dyn Error>> {
+        register_connectable::<_, Self>(session, user_data, device_information_application(model))
+            .await
+    }
+}
+/// Registers the advertisement as connectable ([Type::Peripheral]) and serves `application` alongside it.
+///
+/// The advertisement goes through the same checks as in [register_with_params](Advertisable::register_with_params), with the intervals fitted for a connectable one.
+#[cfg(all(feature = "gatt", any(feature = "airplay", feature = "airprint")))]
+async fn register_connectable<T: AdvertisableData, A: Advertisable<T>>(
+    session: &mut Session,
+    user_data: &T,
+    application: Application,
+) -> Result<ConnectableAdvertisementHandle, Box<dyn Error>> {
+    A::validate_user_data(user_data)
+        .map_err(|err| registration_error::<A>(Operation::Assemble, err))?;
+    A::prepare_adapter(session, user_data)
+        .map_err(|err| registration_error::<A>(Operation::PrepareAdapter, err))?;
+    let advertisement =
+        A::assemble_with_params(session, user_data, &AdvertisementParams::default())
+            .map_err(|err| registration_error::<A>(Operation::Assemble, err))?;
+    let advertisement = fit_to_controller(
+        session,
+        Advertisement {
+            advertisement_type: Type::Peripheral,
+            ..advertisement
+        },
+    )
+    .await?;
+    let application = session
+        .adapter
+        .serve_gatt_application(application)
+        .await
+        .map_err(|err| registration_error::<A>(Operation::Advertise, err.into()))?;
+    let advertisement = session
+        .adapter
+        .advertise(advertisement)
+        .await
+        .map_err(|err| registration_error::<A>(Operation::Advertise, err.into()))?;
+    session.record_registration();
+    Ok(ConnectableAdvertisementHandle {
+        advertisement,
+        application,
+    })
+}
+
+/// Security of the IPP service an AirPrint message points to.
+///
+/// furiousMAC documents the byte without its values, so which scheme `0x00` and `0x6f` stand for isn't verified.
+/// Any other byte is kept as [Other](Self::Other).
+#[cfg(feature = "airprint")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum AirPrintSecurity {
+    /// Plain IPP, `0x00`.
+    Ipp,
+    /// IPP over TLS, `0x6f`.
+    #[default]
+    Ipps,
+    /// A byte none of the above are encoded as.
+    Other(u8),
+}
+#[cfg(feature = "airprint")]
+impl From<AirPrintSecurity> for u8 {
+    fn from(value: AirPrintSecurity) -> Self {
+        match value {
+            AirPrintSecurity::Ipp => 0x00,
+            AirPrintSecurity::Ipps => 0x6f,
+            AirPrintSecurity::Other(value) => value,
+        }
     }
 }
+#[cfg(feature = "airprint")]
+impl From<u8> for AirPrintSecurity {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => AirPrintSecurity::Ipp,
+            0x6f => AirPrintSecurity::Ipps,
+            value => AirPrintSecurity::Other(value),
+        }
+    }
+}
+
+/// Address type usually observed in AirPrint messages.
+#[cfg(feature = "airprint")]
+pub const AIRPRINT_ADDRESS_TYPE: u8 = 0x74;
+/// Resource path index usually observed in AirPrint messages.
+#[cfg(feature = "airprint")]
+pub const AIRPRINT_RESOURCE_PATH: u8 = 0x07;
 
 /// Data for an AirPrint message
+#[cfg(feature = "airprint")]
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AirPrintAdvertisementData {
+    /// Defaults to [AIRPRINT_ADDRESS_TYPE].
+    #[cfg_attr(feature = "serde", serde(default = "airprint_address_type"))]
+    pub address_type: u8,
+    /// Index of the resource path of the IPP service, e.g. `/ipp/print`. Defaults to [AIRPRINT_RESOURCE_PATH].
+    #[cfg_attr(feature = "serde", serde(default = "airprint_resource_path"))]
+    pub resource_path: u8,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub security: AirPrintSecurity,
+    /// Encoded big endian.
     pub port: u16,
     pub ip_addr: Ipv6Addr,
     pub power: u8,
 }
+#[cfg(all(feature = "airprint", feature = "serde"))]
+fn airprint_address_type() -> u8 {
+    AIRPRINT_ADDRESS_TYPE
+}
+#[cfg(all(feature = "airprint", feature = "serde"))]
+fn airprint_resource_path() -> u8 {
+    AIRPRINT_RESOURCE_PATH
+}
+#[cfg(feature = "airprint")]
 impl AdvertisableData for AirPrintAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x03;
     fn octets(&self) -> Vec<u8> {
         let port = self.port.to_be_bytes();
         let ip_addr = self.ip_addr.octets();
         [
             vec![
-                0x03, // Message type
-                0x16, // Message length
-                0x74, // Address type
-                0x07, // Resource path
-                0x6f, // Security type
+                Self::MESSAGE_TYPE,
+                Self::PAYLOAD_LEN,
+                self.address_type,
+                self.resource_path,
+                self.security.into(),
             ],
             port.to_vec(),
             ip_addr.to_vec(),
@@ -236,86 +1955,977 @@ impl AdvertisableData for AirPrintAdvertisementData {
         .concat()
     }
 }
+#[cfg(feature = "airprint")]
 impl TryFrom<Vec<u8>> for AirPrintAdvertisementData {
     type Error = Box<dyn Error>;
     fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let value = value
+            .get(..2 + Self::PAYLOAD_LEN as usize)
+            .ok_or("AirPrint message too short.")?;
         let ip_address: [u8; 16] = value[7..23].try_into()?;
         Ok(AirPrintAdvertisementData {
-            port: (value[5] as u16) << 8 | value[6] as u16,
+            address_type: value[2],
+            resource_path: value[3],
+            security: AirPrintSecurity::from(value[4]),
+            port: u16::from_be_bytes([value[5], value[6]]),
             ip_addr: Ipv6Addr::from(ip_address),
             power: value[23],
         })
     }
 }
 
+/// Ports of IPP (631) and IPPS (443), which printing clients connect to.
+#[cfg(feature = "airprint")]
+pub const PRINTING_PORTS: [u16; 2] = [631, 443];
+
+#[cfg(feature = "airprint")]
+impl AirPrintAdvertisementData {
+    /// Length of the message, excluding the type and the length byte.
+    pub const PAYLOAD_LEN: u8 = 0x16;
+    /// Checks that the port is one of the [PRINTING_PORTS].
+    ///
+    /// Unless `strict` is set, an unusual port is accepted, since it may be intended for testing, and the error it would have been rejected with is returned as a warning.
+    pub fn check_port(&self, strict: bool) -> Result<Option<AppleBleError>, AppleBleError> {
+        if PRINTING_PORTS.contains(&self.port) {
+            return Ok(None);
+        }
+        let warning = AppleBleError::InvalidUserData(format!(
+            "Port {} isn't a printing port, clients won't reach the printer.",
+            self.port
+        ));
+        if strict {
+            return Err(warning);
+        }
+        Ok(Some(warning))
+    }
+}
+
 /// AirPrint message https://github.com/furiousMAC/continuity/blob/master/messages/airprint.md
+///
+/// A port, which isn't one of the [PRINTING_PORTS], is advertised anyway, see [StrictAirPrintAdvertisement].
+/// The warning is discarded, call [check_port](AirPrintAdvertisementData::check_port) beforehand to report it.
+#[cfg(feature = "airprint")]
 pub struct AirPrintAdvertisement;
+#[cfg(feature = "airprint")]
 impl Advertisable<AirPrintAdvertisementData> for AirPrintAdvertisement {
-    fn assemble_advertisement(
+    fn validate_user_data(user_data: &AirPrintAdvertisementData) -> Result<(), Box<dyn Error>> {
+        user_data.check_port(false)?;
+        Ok(())
+    }
+}
+#[cfg(all(feature = "airprint", feature = "gatt"))]
+impl AirPrintAdvertisement {
+    /// Register a connectable AirPrint advertisement, serving a stub GATT application, see [airprint_application].
+    ///
+    /// [register](Advertisable::register) only broadcasts, which lets clients discover the printer, but not connect to fetch the print job metadata.
+    /// Here a client discovers the advertisement, connects, reads the printer URI from the [IPP_SERVICE](crate::gatt::IPP_SERVICE) and continues over IPP at the advertised address and port.
+    /// The URI assumes the resource path `/ipp/print`, which [AIRPRINT_RESOURCE_PATH] usually stands for.
+    pub async fn register_connectable(
         session: &mut Session,
         user_data: &AirPrintAdvertisementData,
-    ) -> Result<Advertisement, Box<dyn Error>> {
-        Ok(Advertisement {
-            advertisement_type: Type::Broadcast,
-            local_name: Some(session.adapter.name().to_string()),
-            timeout: Some(Duration::from_millis(0)),
-            min_interval: Some(Duration::from_millis(100)),
-            max_interval: Some(Duration::from_millis(200)),
-            manufacturer_data: BTreeMap::from([(APPLE_MAGIC, user_data.octets())]),
-            ..Default::default()
-        })
+    ) -> Result<ConnectableAdvertisementHandle, Box<dyn Error>> {
+        let scheme = match user_data.security {
+            AirPrintSecurity::Ipp => "ipp",
+            AirPrintSecurity::Ipps => "ipps",
+            AirPrintSecurity::Other(value) => {
+                return Err(AppleBleError::InvalidUserData(format!(
+                    "The security {value:#04x} doesn't name a scheme for the printer URI."
+                ))
+                .into())
+            }
+        };
+        let printer_uri = format!(
+            "{scheme}://[{}]:{}/ipp/print",
+            user_data.ip_addr, user_data.port
+        );
+        register_connectable::<_, Self>(session, user_data, airprint_application(&printer_uri))
+            .await
+    }
+}
+/// Like [AirPrintAdvertisement], but rejects ports, which aren't one of the [PRINTING_PORTS].
+#[cfg(feature = "airprint")]
+pub struct StrictAirPrintAdvertisement;
+#[cfg(feature = "airprint")]
+impl Advertisable<AirPrintAdvertisementData> for StrictAirPrintAdvertisement {
+    fn validate_user_data(user_data: &AirPrintAdvertisementData) -> Result<(), Box<dyn Error>> {
+        user_data.check_port(true)?;
+        Ok(())
+    }
+}
+
+/// The leading six key bytes of a FindMy key, which are carried by the address advertised from.
+///
+/// bluer stores addresses most significant byte first, in the order they are displayed in, e.g. `C1:02:03:04:05:06`.
+/// This is also the order of the key bytes, while HCI and the air interface use the reversed, little endian order.
+/// The two most significant bits are always set in a random static address, so they don't match the key.
+#[cfg(feature = "findmy")]
+pub fn address_to_key_bytes(address: Address) -> [u8; 6] {
+    address.0
+}
+/// The random static address carrying the leading six key bytes of a FindMy key, see [address_to_key_bytes].
+#[cfg(feature = "findmy")]
+pub fn key_bytes_to_address(key_bytes: [u8; 6]) -> Address {
+    let mut address = key_bytes;
+    address[0] |= 0b1100_0000;
+    Address::new(address)
+}
+
+/// Whether a FindMy device is with its owner, encoded in the status byte.
+///
+/// Neither furiousMAC nor OpenHaystack document the bits of the status byte.
+/// So only the two values this crate sends are named, any other byte, e.g. one carrying a battery level, is kept as [Other](Self::Other).
+#[cfg(feature = "findmy")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum FindMyStatus {
+    /// `0x04`, sent in the [short message](FindMyNearbyAdvertisementData).
+    Owned,
+    /// `0x00`, emitted by OpenHaystack's firmware.
+    #[default]
+    Separated,
+    /// A byte none of the above are encoded as.
+    Other(u8),
+}
+#[cfg(feature = "findmy")]
+impl FindMyStatus {
+    fn from_byte(status: u8) -> Self {
+        match status {
+            0x04 => FindMyStatus::Owned,
+            0x00 => FindMyStatus::Separated,
+            status => FindMyStatus::Other(status),
+        }
+    }
+    fn to_byte(self) -> u8 {
+        match self {
+            FindMyStatus::Owned => 0x04,
+            FindMyStatus::Separated => 0x00,
+            FindMyStatus::Other(status) => status,
+        }
     }
 }
 
+#[cfg(feature = "openhaystack")]
+fn decode_base64_key(encoded: &str) -> Result<[u8; 28], String> {
+    use base64::Engine;
+
+    let public_key = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .map_err(|err| err.to_string())?;
+    public_key.as_slice().try_into().map_err(|_| {
+        format!(
+            "the advertisement key is {} bytes long instead of 28",
+            public_key.len()
+        )
+    })
+}
+
 /// Data for a FindMy message
+///
+/// The layout follows the one emitted by OpenHaystack's firmware.
+/// The byte following the key carries its two most significant bits, which the address can't, so it's always derived from the key.
+/// Only the given key is advertised, rotating it like an Apple tag would requires deriving the keys elsewhere.
+#[cfg(feature = "findmy")]
 #[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FindMyAdvertisementData {
     pub public_key: [u8; 28],
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub status: FindMyStatus,
+}
+#[cfg(feature = "findmy")]
+impl FindMyAdvertisementData {
+    /// Length of the message, excluding the type and the length byte.
+    pub const PAYLOAD_LEN: u8 = 0x19;
+    /// Data of a [separated](FindMyStatus::Separated) device.
+    pub fn new(public_key: [u8; 28]) -> Self {
+        FindMyAdvertisementData {
+            public_key,
+            status: FindMyStatus::default(),
+        }
+    }
+    /// Rejects keys consisting of a single repeated byte, like all zeros.
+    ///
+    /// Such keys are almost certainly placeholders, which the Find My network ignores.
+    pub fn check_key(&self) -> Result<(), AppleBleError> {
+        if self
+            .public_key
+            .iter()
+            .all(|&byte| byte == self.public_key[0])
+        {
+            return Err(AppleBleError::InvalidUserData(format!(
+                "The public key only consists of the byte {:#04x}.",
+                self.public_key[0]
+            )));
+        }
+        Ok(())
+    }
+    /// The random static address derived from the first six bytes of the public key.
+    ///
+    /// Random static addresses require the two most significant bits to be set.
+    pub fn address(&self) -> Address {
+        key_bytes_to_address(self.public_key[0..6].try_into().unwrap())
+    }
+    /// Reads the advertisement key from a key file generated by OpenHaystack's `generate_keys.py`.
+    ///
+    /// The file either contains an `Advertisement key: <base64>` line, next to the private and hashed keys, or just the base64 encoded key.
+    /// Accessories exported from the OpenHaystack app only contain the private key and aren't supported.
+    #[cfg(feature = "openhaystack")]
+    pub fn from_openhaystack(mut reader: impl std::io::Read) -> Result<Self, AppleBleError> {
+        let mut contents = String::new();
+        reader
+            .read_to_string(&mut contents)
+            .map_err(|err| AppleBleError::InvalidKeyFile(err.to_string()))?;
+        let encoded = contents
+            .lines()
+            .find_map(|line| line.strip_prefix("Advertisement key:"))
+            .or_else(|| {
+                let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+                lines.next().filter(|_| lines.next().is_none())
+            })
+            .ok_or_else(|| {
+                AppleBleError::InvalidKeyFile("no advertisement key found".to_string())
+            })?;
+        Ok(FindMyAdvertisementData::new(
+            decode_base64_key(encoded).map_err(AppleBleError::InvalidKeyFile)?,
+        ))
+    }
+    /// Decodes a base64 encoded advertisement key, like the ones OpenHaystack hands out.
+    #[cfg(feature = "openhaystack")]
+    pub fn from_base64(encoded: &str) -> Result<Self, AppleBleError> {
+        Ok(FindMyAdvertisementData::new(
+            decode_base64_key(encoded).map_err(AppleBleError::InvalidUserData)?,
+        ))
+    }
+    /// Summarizes the beacon for provisioning, e.g. to save it or encode it into a QR code.
+    #[cfg(feature = "openhaystack")]
+    pub fn summary(&self) -> FindMySummary {
+        use base64::Engine;
+
+        let octets = self.octets();
+        FindMySummary {
+            public_key: base64::engine::general_purpose::STANDARD.encode(self.public_key),
+            address: self.address(),
+            advertising_data: [
+                vec![3 + octets.len() as u8, 0xff],
+                APPLE_MAGIC.to_le_bytes().to_vec(),
+                octets,
+            ]
+            .concat(),
+        }
+    }
+}
+
+/// What's needed to set up a FindMy beacon, see [FindMyAdvertisementData::summary].
+///
+/// It's displayed as one `name: value` line per field, with the key in the `Advertisement key:` line [from_openhaystack](FindMyAdvertisementData::from_openhaystack) reads.
+#[cfg(feature = "openhaystack")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FindMySummary {
+    /// The base64 encoded public key, like OpenHaystack hands it out.
+    pub public_key: String,
+    /// The random static address the beacon advertises from.
+    pub address: Address,
+    /// The manufacturer specific AD structure, including its length, AD type and company id, see [AdvertisementType::from_ad_structure].
+    pub advertising_data: Vec<u8>,
+}
+#[cfg(feature = "openhaystack")]
+impl std::fmt::Display for FindMySummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "Advertisement key: {}", self.public_key)?;
+        writeln!(f, "Address: {}", self.address)?;
+        write!(
+            f,
+            "Advertising data: {}",
+            to_hex_string(&self.advertising_data)
+        )
+    }
+}
+#[cfg(all(feature = "openhaystack", feature = "serde"))]
+impl FindMySummary {
+    /// The summary as a JSON object with the fields `public_key`, `address` and `advertising_data`, encoded like in [AdvertisementType::to_json].
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "public_key": self.public_key,
+            "address": self.address.to_string(),
+            "advertising_data": to_hex_string(&self.advertising_data),
+        })
+    }
 }
+#[cfg(feature = "findmy")]
 impl AdvertisableData for FindMyAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x12;
     fn octets(&self) -> Vec<u8> {
         let public_key = self.public_key.split_at(6);
         [
+            vec![Self::MESSAGE_TYPE, Self::PAYLOAD_LEN, self.status.to_byte()],
+            public_key.1.to_vec(),
             vec![
-                0x12, // Message type
-                0x19, // Message length
-                0x00,
+                public_key.0[0] >> 6, // Most significant bits of the key, which the address can't carry
+                0x00,                 // Hint
             ],
-            public_key.1.to_vec(),
-            vec![public_key.0[0] >> 6],
         ]
         .concat()
     }
 }
+#[cfg(feature = "findmy")]
 impl TryFrom<(Address, Vec<u8>)> for FindMyAdvertisementData {
     type Error = Box<dyn Error>;
     fn try_from(value: (Address, Vec<u8>)) -> Result<Self, Self::Error> {
-        let public_key: [u8; 28] = [&value.0.0, &value.1[2..24]]
+        let (address, value) = value;
+        // The trailing hint isn't decoded, so it may be missing.
+        let payload = value
+            .get(3..1 + Self::PAYLOAD_LEN as usize)
+            .ok_or("FindMy message too short.")?;
+        let mut public_key: [u8; 28] = [&address_to_key_bytes(address), &payload[..22]]
             .concat()
             .as_slice()
             .try_into()?;
+        // The two most significant bits of the key are replaced in the address and carried separately.
+        public_key[0] = (public_key[0] & 0b0011_1111) | (payload[22] << 6);
         Ok(FindMyAdvertisementData {
-            public_key: public_key,
+            public_key,
+            status: FindMyStatus::from_byte(value[2]),
+        })
+    }
+}
+
+/// Data for the short FindMy message, which a device sends instead of the [long one](FindMyAdvertisementData) while it's with its owner.
+///
+/// Besides the status it only carries the two most significant bits of the key, so only the leading six key bytes, which the address carries, are known.
+/// Both share the message type, they are told apart by their length, see [decode_apple].
+#[cfg(feature = "findmy")]
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FindMyNearbyAdvertisementData {
+    /// The leading six bytes of the public key, see [address_to_key_bytes].
+    pub key_prefix: [u8; 6],
+    #[cfg_attr(feature = "serde", serde(default = "findmy_nearby_status"))]
+    pub status: FindMyStatus,
+}
+#[cfg(all(feature = "findmy", feature = "serde"))]
+fn findmy_nearby_status() -> FindMyStatus {
+    FindMyStatus::Owned
+}
+#[cfg(feature = "findmy")]
+impl FindMyNearbyAdvertisementData {
+    /// Length of the message, excluding the type and the length byte.
+    pub const PAYLOAD_LEN: u8 = 0x02;
+    /// Data of an [owned](FindMyStatus::Owned) device.
+    pub fn new(key_prefix: [u8; 6]) -> Self {
+        FindMyNearbyAdvertisementData {
+            key_prefix,
+            status: FindMyStatus::Owned,
+        }
+    }
+    /// The random static address carrying the key prefix, see [FindMyAdvertisementData::address].
+    pub fn address(&self) -> Address {
+        key_bytes_to_address(self.key_prefix)
+    }
+}
+#[cfg(feature = "findmy")]
+impl AdvertisableData for FindMyNearbyAdvertisementData {
+    const MESSAGE_TYPE: u8 = FindMyAdvertisementData::MESSAGE_TYPE;
+    fn octets(&self) -> Vec<u8> {
+        vec![
+            Self::MESSAGE_TYPE,
+            Self::PAYLOAD_LEN,
+            self.status.to_byte(),
+            self.key_prefix[0] >> 6, // Most significant bits of the key, which the address can't carry
+        ]
+    }
+}
+#[cfg(feature = "findmy")]
+impl TryFrom<(Address, Vec<u8>)> for FindMyNearbyAdvertisementData {
+    type Error = Box<dyn Error>;
+    fn try_from(value: (Address, Vec<u8>)) -> Result<Self, Self::Error> {
+        let (address, value) = value;
+        if value.get(1) != Some(&Self::PAYLOAD_LEN) {
+            return Err("Not a short FindMy message.".into());
+        }
+        let payload = value
+            .get(2..2 + Self::PAYLOAD_LEN as usize)
+            .ok_or("FindMy message too short.")?;
+        let mut key_prefix = address_to_key_bytes(address);
+        key_prefix[0] = (key_prefix[0] & 0b0011_1111) | (payload[1] << 6);
+        Ok(FindMyNearbyAdvertisementData {
+            key_prefix,
+            status: FindMyStatus::from_byte(payload[0]),
         })
     }
 }
 
 /// FindMy message https://github.com/furiousMAC/continuity/blob/master/messages/findmy.md
+///
+/// The key isn't checked, see [StrictFindMyAdvertisement].
+#[cfg(feature = "findmy")]
 pub struct FindMyAdvertisement;
+#[cfg(feature = "findmy")]
+impl FindMyAdvertisement {
+    pub(crate) fn findmy_advertisement(
+        session: &Session,
+        user_data: &FindMyAdvertisementData,
+    ) -> Advertisement {
+        session.default_params.apply(Advertisement {
+            manufacturer_data: BTreeMap::from([(session.company_id, user_data.octets())]),
+            ..Self::default_advertisement(session)
+        })
+    }
+    /// Register a FindMy advertisement on a random static address derived from the public key.
+    ///
+    /// The address is programmed through the BlueZ management interface, so neither `bdaddr` nor a service restart is required.
+    /// Since BlueZ only advertises on the static address with BR/EDR disabled, BR/EDR is switched off for the adapter until [restore_bredr](crate::session::restore_bredr) is called.
+    pub async fn register_random(
+        session: &mut Session,
+        user_data: &FindMyAdvertisementData,
+    ) -> Result<AdvertisementHandle, Box<dyn Error>> {
+        Self::validate_user_data(user_data)?;
+        spawn_set_static_random_addr(session, user_data.address().0)
+            .await
+            .map_err(|err| registration_error::<Self>(Operation::SetAddress, err))?;
+        let advertisement = Self::findmy_advertisement(session, user_data);
+        let handle = session
+            .adapter
+            .advertise(advertisement)
+            .await
+            .map_err(|err| registration_error::<Self>(Operation::Advertise, err.into()))?;
+        session.record_registration();
+        Ok(handle)
+    }
+    /// Register a FindMy advertisement, whose device address can be restored by calling [stop](FindMyRegistration::stop).
+    pub async fn register_restorable(
+        session: &mut Session,
+        user_data: &FindMyAdvertisementData,
+    ) -> Result<FindMyRegistration, Box<dyn Error>> {
+        let original_address = session.adapter.address().await?;
+        let handle = Self::register(session, user_data).await?;
+        Ok(FindMyRegistration {
+            handle: Some(handle),
+            session: session.clone(),
+            original_address,
+        })
+    }
+    /// Cycle through several FindMy keys, advertising each one for `dwell` on its random static address.
+    ///
+    /// Since every key needs its own device address, only one key is live at a time.
+    /// The cycle stops, once the returned handle is dropped or an address can't be programmed, see [error](DynamicAdvertisementHandle::error).
+    pub async fn register_round_robin(
+        session: &mut Session,
+        keys: Vec<[u8; 28]>,
+        dwell: Duration,
+    ) -> Result<DynamicAdvertisementHandle, Box<dyn Error>> {
+        let keys: Vec<FindMyAdvertisementData> =
+            keys.into_iter().map(FindMyAdvertisementData::new).collect();
+        let mut handle =
+            Self::register_random(session, keys.first().ok_or("No keys supplied.")?).await?;
+        let session = session.clone();
+        let updater = tokio::spawn(async move {
+            for user_data in keys.iter().cycle().skip(1) {
+                tokio::time::sleep(dwell).await;
+                drop(handle);
+                if let Err(err) =
+                    spawn_set_static_random_addr(&session, user_data.address().0).await
+                {
+                    return registration_error::<Self>(Operation::SetAddress, err);
+                }
+                let advertisement = Self::findmy_advertisement(&session, user_data);
+                handle = match session.adapter.advertise(advertisement).await {
+                    Ok(handle) => handle,
+                    Err(err) => {
+                        return registration_error::<Self>(Operation::Advertise, err.into())
+                    }
+                };
+            }
+            unreachable!("The keys are cycled endlessly.")
+        });
+        Ok(DynamicAdvertisementHandle { updater })
+    }
+}
+/// A FindMy advertisement, which restores the original device address once stopped.
+///
+/// The restoration restarts the bluetooth service, so it can't happen in [Drop].
+/// Call [stop](Self::stop) instead, otherwise only the advertisement is stopped, the spoofed address remains and a warning is logged.
+#[cfg(feature = "findmy")]
+pub struct FindMyRegistration {
+    handle: Option<AdvertisementHandle>,
+    session: Session,
+    original_address: Address,
+}
+#[cfg(feature = "findmy")]
+impl FindMyRegistration {
+    /// The device address, which will be restored.
+    pub fn original_address(&self) -> Address {
+        self.original_address
+    }
+    /// Stops the advertisement and restores the original device address.
+    ///
+    /// The session is a clone of the one registered on, which shares its [AddressState](crate::session::AddressState), so the address can be changed again through the original one afterwards.
+    /// The address is written and the bluetooth service restarted on a thread for blocking work, so the runtime isn't stalled meanwhile.
+    pub async fn stop(mut self) -> Result<(), Box<dyn Error>> {
+        drop(self.handle.take());
+        let mut session = self.session.clone();
+        let original_address = self.original_address;
+        run_blocking(move || restore_device_addr(&mut session, &original_address.0)).await
+    }
+}
+#[cfg(feature = "findmy")]
+impl Drop for FindMyRegistration {
+    fn drop(&mut self) {
+        // stop takes the handle, so it's only left if the registration wasn't stopped.
+        if self.handle.take().is_some() {
+            log::warn!(
+                "A FindMy registration was dropped without calling stop, the original device address {} wasn't restored.",
+                self.original_address
+            );
+        }
+    }
+}
+
+#[cfg(feature = "findmy")]
+#[cfg_attr(feature = "disable_afit", async_trait)]
 impl Advertisable<FindMyAdvertisementData> for FindMyAdvertisement {
-    fn assemble_advertisement(
+    fn prepare_adapter(
         session: &mut Session,
         user_data: &FindMyAdvertisementData,
-    ) -> Result<Advertisement, Box<dyn Error>> {
-        set_device_addr(session, &user_data.public_key[0..6])?;
-        Ok(Advertisement {
-            advertisement_type: Type::Broadcast,
-            local_name: Some(session.adapter.name().to_string()),
-            timeout: Some(Duration::from_millis(0)),
-            min_interval: Some(Duration::from_millis(100)),
-            max_interval: Some(Duration::from_millis(200)),
-            manufacturer_data: BTreeMap::from([(APPLE_MAGIC, user_data.octets())]),
-            ..Default::default()
+    ) -> Result<(), Box<dyn Error>> {
+        set_device_addr(session, &user_data.public_key[0..6])
+            .map_err(|err| registration_error::<Self>(Operation::SetAddress, err).into())
+    }
+}
+/// Like [FindMyAdvertisement], but rejects trivially invalid keys, see [check_key](FindMyAdvertisementData::check_key).
+#[cfg(feature = "findmy")]
+pub struct StrictFindMyAdvertisement;
+#[cfg(feature = "findmy")]
+#[cfg_attr(feature = "disable_afit", async_trait)]
+impl Advertisable<FindMyAdvertisementData> for StrictFindMyAdvertisement {
+    fn validate_user_data(user_data: &FindMyAdvertisementData) -> Result<(), Box<dyn Error>> {
+        Ok(user_data.check_key()?)
+    }
+    fn prepare_adapter(
+        session: &mut Session,
+        user_data: &FindMyAdvertisementData,
+    ) -> Result<(), Box<dyn Error>> {
+        FindMyAdvertisement::prepare_adapter(session, user_data)
+    }
+}
+/// Short FindMy message of a device, which is with its owner, see [FindMyNearbyAdvertisementData].
+///
+/// Like [FindMyAdvertisement], the device address is changed to the one carrying the key prefix.
+#[cfg(feature = "findmy")]
+pub struct FindMyNearbyAdvertisement;
+#[cfg(feature = "findmy")]
+impl Advertisable<FindMyNearbyAdvertisementData> for FindMyNearbyAdvertisement {
+    fn prepare_adapter(
+        session: &mut Session,
+        user_data: &FindMyNearbyAdvertisementData,
+    ) -> Result<(), Box<dyn Error>> {
+        set_device_addr(session, &user_data.key_prefix)
+            .map_err(|err| registration_error::<Self>(Operation::SetAddress, err).into())
+    }
+}
+
+/// Data flag signalling that a Nearby Info message carries an authentication tag.
+///
+/// The bit is inferred from observed traffic, the message length byte remains the authoritative source.
+#[cfg(feature = "nearby")]
+pub const NEARBY_INFO_AUTH_TAG_FLAG: u8 = 0x20;
+
+/// Rough class of the device sending a Nearby Info message, see [NearbyInfoAdvertisementData::device_class].
+#[cfg(feature = "nearby")]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum DeviceClass {
+    IPhone,
+    IPad,
+    Mac,
+    Watch,
+    Unknown,
+}
+
+/// Data for a Nearby Info message
+///
+/// Up to iOS 12 the message only consists of the status flags, the action code and the data flags (short form).
+/// iOS 13 appended a three byte authentication tag (long form), which is emitted if [auth_tag](Self::auth_tag) is set.
+#[cfg(feature = "nearby")]
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct NearbyInfoAdvertisementData {
+    /// Upper nibble of the first byte.
+    pub status_flags: u8,
+    /// Lower nibble of the first byte.
+    pub action_code: u8,
+    pub data_flags: u8,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub auth_tag: Option<[u8; 3]>,
+    /// Bytes following the authentication tag, which some actions append.
+    ///
+    /// Their meaning per action code isn't documented, so they're kept as is to encode the message again unchanged.
+    /// They can only be emitted together with an authentication tag.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub action_data: Vec<u8>,
+}
+#[cfg(feature = "nearby")]
+impl NearbyInfoAdvertisementData {
+    /// The revision of the layout, derived from the presence of the authentication tag.
+    pub fn version(&self) -> ContinuityVersion {
+        match self.auth_tag {
+            Some(_) => ContinuityVersion::Ios13,
+            None => ContinuityVersion::Ios12,
+        }
+    }
+    /// The class of the device, derived from bits 2 to 4 of the data flags.
+    ///
+    /// | bits 2 to 4 | class    |
+    /// |-------------|----------|
+    /// | `0b001`     | iPhone   |
+    /// | `0b010`     | iPad     |
+    /// | `0b011`     | Mac      |
+    /// | `0b100`     | Watch    |
+    /// | other       | unknown  |
+    ///
+    /// The mapping is inferred from a small number of captures and isn't documented by furiousMAC, so only use it for labelling devices.
+    pub fn device_class(&self) -> DeviceClass {
+        match (self.data_flags >> 2) & 0b111 {
+            0b001 => DeviceClass::IPhone,
+            0b010 => DeviceClass::IPad,
+            0b011 => DeviceClass::Mac,
+            0b100 => DeviceClass::Watch,
+            _ => DeviceClass::Unknown,
+        }
+    }
+}
+#[cfg(feature = "nearby")]
+impl AdvertisableData for NearbyInfoAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x10;
+    /// Panics if the action data is longer than the 250 bytes [validate_user_data](NearbyInfoAdvertisement::validate_user_data) accepts, instead of wrapping the length byte.
+    fn octets(&self) -> Vec<u8> {
+        let trailer = match self.auth_tag {
+            Some(auth_tag) => [&auth_tag[..], &self.action_data].concat(),
+            None => vec![],
+        };
+        [
+            vec![
+                Self::MESSAGE_TYPE,
+                u8::try_from(2 + trailer.len()).expect("The action data is too long."), // Message length
+                (self.status_flags << 4) | (self.action_code & 0x0f),
+                self.data_flags,
+            ],
+            trailer,
+        ]
+        .concat()
+    }
+}
+#[cfg(feature = "nearby")]
+impl TryFrom<Vec<u8>> for NearbyInfoAdvertisementData {
+    type Error = Box<dyn Error>;
+    /// Decodes either form, depending on the message length byte.
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let length = *value.get(1).ok_or("Nearby Info message too short.")?;
+        let header = value.get(2..4).ok_or("Nearby Info message too short.")?;
+        let auth_tag = match length {
+            2 => None,
+            5.. => Some(
+                value
+                    .get(4..7)
+                    .ok_or("Nearby Info message too short.")?
+                    .try_into()?,
+            ),
+            _ => return Err(format!("Invalid Nearby Info message length {length}.").into()),
+        };
+        if auth_tag.is_none() && header[1] & NEARBY_INFO_AUTH_TAG_FLAG != 0 {
+            return Err(
+                "Nearby Info data flags signal an authentication tag, which is missing.".into(),
+            );
+        }
+        let action_data = match auth_tag {
+            Some(_) => value
+                .get(7..2 + length as usize)
+                .ok_or("Nearby Info message too short.")?
+                .to_vec(),
+            None => vec![],
+        };
+        Ok(NearbyInfoAdvertisementData {
+            status_flags: header[0] >> 4,
+            action_code: header[0] & 0x0f,
+            data_flags: header[1],
+            auth_tag,
+            action_data,
         })
     }
 }
+#[cfg(feature = "nearby")]
+impl TryFrom<(Vec<u8>, ContinuityVersion)> for NearbyInfoAdvertisementData {
+    type Error = Box<dyn Error>;
+    /// Decodes the message, rejecting it if it doesn't use the layout of `version`.
+    fn try_from(value: (Vec<u8>, ContinuityVersion)) -> Result<Self, Self::Error> {
+        let (value, version) = value;
+        let data = Self::try_from(value)?;
+        if data.version() != version {
+            return Err(format!("Nearby Info message doesn't use the {version:?} layout.").into());
+        }
+        Ok(data)
+    }
+}
+
+/// Nearby Info message https://github.com/furiousMAC/continuity/blob/master/messages/nearby_info.md
+#[cfg(feature = "nearby")]
+pub struct NearbyInfoAdvertisement;
+#[cfg(feature = "nearby")]
+impl Advertisable<NearbyInfoAdvertisementData> for NearbyInfoAdvertisement {
+    fn validate_user_data(user_data: &NearbyInfoAdvertisementData) -> Result<(), Box<dyn Error>> {
+        if user_data.status_flags > 0x0f || user_data.action_code > 0x0f {
+            return Err("Status flags and action code are limited to four bits.".into());
+        }
+        if user_data.auth_tag.is_none() && user_data.data_flags & NEARBY_INFO_AUTH_TAG_FLAG != 0 {
+            return Err("Data flags signal an authentication tag, but none is set.".into());
+        }
+        if user_data.auth_tag.is_none() && !user_data.action_data.is_empty() {
+            return Err("Action data can only be emitted with an authentication tag.".into());
+        }
+        if 5 + user_data.action_data.len() > u8::MAX as usize {
+            return Err("The action data of a Nearby Info message is limited to 250 bytes.".into());
+        }
+        Ok(())
+    }
+}
+
+/// Data for a Handoff message
+///
+/// The status byte carries the clipboard flag and the Apple ID flag, which marks messages tied to an authenticated Apple ID session.
+/// furiousMAC only documents the clipboard flag, the meaning of the Apple ID flag is inferred from observed traffic and no capture in this crate backs it.
+/// Either way furiousMAC documents a single layout, so the offsets of the following fields don't depend on the flag.
+#[cfg(feature = "handoff")]
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HandoffAdvertisementData {
+    pub clipboard: bool,
+    pub apple_id: bool,
+    /// Incremented with every new Handoff activity, encoded big endian.
+    pub sequence_number: u16,
+    /// First byte of the AES-GCM authentication tag.
+    pub auth_tag: u8,
+    pub encrypted_data: [u8; 10],
+}
+#[cfg(feature = "handoff")]
+impl HandoffAdvertisementData {
+    /// Length of the message, excluding the type and the length byte.
+    pub const PAYLOAD_LEN: u8 = 0x0e;
+    const CLIPBOARD: u8 = 0x08;
+    const APPLE_ID: u8 = 0x01;
+}
+#[cfg(feature = "handoff")]
+impl AdvertisableData for HandoffAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x0c;
+    fn octets(&self) -> Vec<u8> {
+        let mut status = 0x00;
+        if self.clipboard {
+            status |= Self::CLIPBOARD;
+        }
+        if self.apple_id {
+            status |= Self::APPLE_ID;
+        }
+        [
+            vec![Self::MESSAGE_TYPE, Self::PAYLOAD_LEN, status],
+            self.sequence_number.to_be_bytes().to_vec(),
+            vec![self.auth_tag],
+            self.encrypted_data.to_vec(),
+        ]
+        .concat()
+    }
+}
+#[cfg(feature = "handoff")]
+impl TryFrom<Vec<u8>> for HandoffAdvertisementData {
+    type Error = Box<dyn Error>;
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let payload = value
+            .get(2..2 + Self::PAYLOAD_LEN as usize)
+            .ok_or("Handoff message too short.")?;
+        Ok(HandoffAdvertisementData {
+            clipboard: payload[0] & Self::CLIPBOARD != 0,
+            apple_id: payload[0] & Self::APPLE_ID != 0,
+            sequence_number: u16::from_be_bytes([payload[1], payload[2]]),
+            auth_tag: payload[3],
+            encrypted_data: payload[4..14].try_into()?,
+        })
+    }
+}
+
+/// Handoff message https://github.com/furiousMAC/continuity/blob/master/messages/handoff.md
+#[cfg(feature = "handoff")]
+pub struct HandoffAdvertisement;
+#[cfg(feature = "handoff")]
+impl Advertisable<HandoffAdvertisementData> for HandoffAdvertisement {}
+
+/// Data for a Tethering Source (Instant Hotspot) message
+///
+/// The advertisement only announces the hotspot and whether Wi-Fi credentials are available.
+/// A device, which wants to join, connects afterwards and the credentials are exchanged over an encrypted GATT session, which isn't covered by this crate.
+#[cfg(feature = "tethering")]
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TetheringSourceAdvertisementData {
+    pub version: u8,
+    /// Wi-Fi credentials can be requested over GATT.
+    pub credentials_available: bool,
+    /// The remaining flag bits, the credentials bit is ignored.
+    pub flags: u8,
+    /// Battery level in percent.
+    pub battery_life: u8,
+    /// Encoded big endian.
+    pub cell_service_type: u16,
+    /// Signal strength in bars.
+    pub cell_service_strength: u8,
+}
+#[cfg(feature = "tethering")]
+impl TetheringSourceAdvertisementData {
+    /// Length of the message, excluding the type and the length byte.
+    pub const PAYLOAD_LEN: u8 = 0x06;
+    const CREDENTIALS_AVAILABLE: u8 = 0x01;
+}
+#[cfg(feature = "tethering")]
+impl AdvertisableData for TetheringSourceAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x0e;
+    fn octets(&self) -> Vec<u8> {
+        let mut flags = self.flags & !Self::CREDENTIALS_AVAILABLE;
+        if self.credentials_available {
+            flags |= Self::CREDENTIALS_AVAILABLE;
+        }
+        [
+            vec![
+                Self::MESSAGE_TYPE,
+                Self::PAYLOAD_LEN,
+                self.version,
+                flags,
+                self.battery_life,
+            ],
+            self.cell_service_type.to_be_bytes().to_vec(),
+            vec![self.cell_service_strength],
+        ]
+        .concat()
+    }
+}
+#[cfg(feature = "tethering")]
+impl TryFrom<Vec<u8>> for TetheringSourceAdvertisementData {
+    type Error = Box<dyn Error>;
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let payload = value
+            .get(2..2 + Self::PAYLOAD_LEN as usize)
+            .ok_or("Tethering source message too short.")?;
+        Ok(TetheringSourceAdvertisementData {
+            version: payload[0],
+            credentials_available: payload[1] & Self::CREDENTIALS_AVAILABLE != 0,
+            flags: payload[1] & !Self::CREDENTIALS_AVAILABLE,
+            battery_life: payload[2],
+            cell_service_type: u16::from_be_bytes([payload[3], payload[4]]),
+            cell_service_strength: payload[5],
+        })
+    }
+}
+
+/// Tethering source message https://github.com/furiousMAC/continuity/blob/master/messages/tethering_source.md
+#[cfg(feature = "tethering")]
+pub struct TetheringSourceAdvertisement;
+#[cfg(feature = "tethering")]
+impl Advertisable<TetheringSourceAdvertisementData> for TetheringSourceAdvertisement {}
+
+/// Data for a Watch Connection message, which an Apple Watch advertises to its paired iPhone
+///
+/// furiousMAC lists the message type, but the contents are undocumented.
+/// So only the first byte is split off as `status`, without interpreting it.
+/// The remaining bytes are kept as is in `data`.
+#[cfg(feature = "watch")]
+#[derive(Clone, PartialEq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct WatchConnectionAdvertisementData {
+    pub status: u8,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub data: Vec<u8>,
+}
+#[cfg(feature = "watch")]
+impl AdvertisableData for WatchConnectionAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x0b;
+    /// Panics if the data is longer than the 254 bytes [validate_user_data](WatchConnectionAdvertisement::validate_user_data) accepts, instead of wrapping the length byte.
+    fn octets(&self) -> Vec<u8> {
+        [
+            vec![
+                Self::MESSAGE_TYPE,
+                u8::try_from(1 + self.data.len()).expect("The data is too long."), // Message length
+                self.status,
+            ],
+            self.data.clone(),
+        ]
+        .concat()
+    }
+}
+#[cfg(feature = "watch")]
+impl TryFrom<Vec<u8>> for WatchConnectionAdvertisementData {
+    type Error = Box<dyn Error>;
+    fn try_from(value: Vec<u8>) -> Result<Self, Self::Error> {
+        let length = *value.get(1).ok_or("Watch Connection message too short.")?;
+        let payload = value
+            .get(2..2 + length as usize)
+            .filter(|payload| !payload.is_empty())
+            .ok_or("Watch Connection message too short.")?;
+        Ok(WatchConnectionAdvertisementData {
+            status: payload[0],
+            data: payload[1..].to_vec(),
+        })
+    }
+}
+
+/// Watch Connection message https://github.com/furiousMAC/continuity/blob/master/messages/watch_c.md
+#[cfg(feature = "watch")]
+pub struct WatchConnectionAdvertisement;
+#[cfg(feature = "watch")]
+impl Advertisable<WatchConnectionAdvertisementData> for WatchConnectionAdvertisement {
+    fn validate_user_data(
+        user_data: &WatchConnectionAdvertisementData,
+    ) -> Result<(), Box<dyn Error>> {
+        if user_data.data.len() >= u8::MAX as usize {
+            return Err("The data of a Watch Connection message is limited to 254 bytes.".into());
+        }
+        Ok(())
+    }
+}
+
+/// Manufacturer data advertised verbatim, see [RawAppleAdvertisement].
+///
+/// It may hold any number of messages, each starting with its type and length byte, or no valid message at all.
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct RawAppleAdvertisementData(pub Vec<u8>);
+impl RawAppleAdvertisementData {
+    /// The most manufacturer data fitting into an AD structure, whose length byte also covers the AD type and the company id.
+    pub const MAX_LEN: usize = u8::MAX as usize - 3;
+    /// The type of the first message, if there is any data.
+    pub fn message_type(&self) -> Option<u8> {
+        self.0.first().copied()
+    }
+}
+/// The data isn't tied to a message type, so [MESSAGE_TYPE](AdvertisableData::MESSAGE_TYPE) is only a placeholder, see [message_type](RawAppleAdvertisementData::message_type).
+impl AdvertisableData for RawAppleAdvertisementData {
+    const MESSAGE_TYPE: u8 = 0x00;
+    fn octets(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+}
+
+/// Advertises user supplied manufacturer data under the company id of the session, without modelling a message type.
+///
+/// Besides the length, nothing is checked, so this is the lowest-level way of advertising anything.
+/// Whether the data fits into the advertising data of the adapter is checked while registering, like for every advertisement.
+pub struct RawAppleAdvertisement;
+impl Advertisable<RawAppleAdvertisementData> for RawAppleAdvertisement {
+    fn validate_user_data(user_data: &RawAppleAdvertisementData) -> Result<(), Box<dyn Error>> {
+        if user_data.0.is_empty() {
+            return Err("Raw manufacturer data can't be empty.".into());
+        }
+        if user_data.0.len() > RawAppleAdvertisementData::MAX_LEN {
+            return Err(format!(
+                "Raw manufacturer data is limited to {} bytes.",
+                RawAppleAdvertisementData::MAX_LEN
+            )
+            .into());
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,144 @@
+use std::io::Read;
+
+use bluer::Address;
+
+use crate::advertisement::AdvertisementType;
+use crate::error::AppleBleError;
+
+/// Identification pattern at the start of every btsnoop file.
+const MAGIC: &[u8; 8] = b"btsnoop\0";
+/// Datalink type of HCI packets without a packet type, which is derived from the record flags instead.
+const DATALINK_HCI: u32 = 1001;
+/// Datalink type of HCI packets prefixed by their H4 (UART) packet type, which Android uses.
+const DATALINK_H4: u32 = 1002;
+const H4_EVENT: u8 = 0x04;
+/// Record flags of an event received from the controller.
+const FLAGS_RECEIVED_EVENT: u32 = 0x03;
+const EVENT_LE_META: u8 = 0x3e;
+const LE_ADVERTISING_REPORT: u8 = 0x02;
+const LE_EXTENDED_ADVERTISING_REPORT: u8 = 0x0d;
+
+/// An advertising report the controller received, as logged in a capture.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AdvertisingReport {
+    pub address: Address,
+    pub rssi: i8,
+    /// The raw advertising data, consisting of AD structures.
+    pub data: Vec<u8>,
+}
+impl AdvertisingReport {
+    /// Decodes the Apple manufacturer data of the report, see [AdvertisementType::from_advertising_data].
+    pub fn decode(&self) -> Option<AdvertisementType> {
+        AdvertisementType::from_advertising_data(self.address, &self.data)
+    }
+}
+
+/// Reads the LE advertising reports from a btsnoop capture, e.g. Android's HCI snoop log or one written by `btmon -w`.
+///
+/// Both the un-encapsulated HCI and the H4 datalink are supported, legacy as well as extended advertising reports are extracted.
+/// Malformed events are skipped, a truncated last record ends the capture.
+pub fn read_advertising_reports(
+    mut reader: impl Read,
+) -> Result<Vec<AdvertisingReport>, AppleBleError> {
+    let mut capture = Vec::new();
+    reader
+        .read_to_end(&mut capture)
+        .map_err(|err| AppleBleError::InvalidCapture(err.to_string()))?;
+    let header = capture
+        .get(..16)
+        .filter(|header| header.starts_with(MAGIC))
+        .ok_or_else(|| AppleBleError::InvalidCapture("not a btsnoop file".to_string()))?;
+    let datalink = u32::from_be_bytes(header[12..16].try_into().unwrap());
+    if datalink != DATALINK_HCI && datalink != DATALINK_H4 {
+        return Err(AppleBleError::InvalidCapture(format!(
+            "unsupported datalink {datalink}"
+        )));
+    }
+
+    let mut reports = Vec::new();
+    let mut records = &capture[16..];
+    // Every record starts with its original length, included length, flags, cumulative drops and timestamp.
+    while let Some(record_header) = records.get(..24) {
+        let included_length = u32::from_be_bytes(record_header[4..8].try_into().unwrap()) as usize;
+        let flags = u32::from_be_bytes(record_header[8..12].try_into().unwrap());
+        let Some(packet) = records.get(24..24 + included_length) else {
+            break;
+        };
+        records = &records[24 + included_length..];
+        let event = match datalink {
+            DATALINK_H4 => match packet.split_first() {
+                Some((&H4_EVENT, event)) => event,
+                _ => continue,
+            },
+            _ if flags & FLAGS_RECEIVED_EVENT == FLAGS_RECEIVED_EVENT => packet,
+            _ => continue,
+        };
+        reports.extend(parse_le_meta_event(event).into_iter().flatten());
+    }
+    Ok(reports)
+}
+
+/// Reads the advertising reports of a capture and decodes their Apple manufacturer data, skipping every other report.
+pub fn decode_capture(
+    reader: impl Read,
+) -> Result<Vec<(Address, AdvertisementType)>, AppleBleError> {
+    Ok(read_advertising_reports(reader)?
+        .into_iter()
+        .filter_map(|report| Some((report.address, report.decode()?)))
+        .collect())
+}
+
+/// Parses the reports of an LE advertising report event, or [None] for any other or a malformed event.
+///
+/// The reports are laid out one after another, like BlueZ does, even though the specification arranges the fields of multiple reports as arrays.
+/// Controllers almost always send a single report per event, where both layouts match.
+fn parse_le_meta_event(event: &[u8]) -> Option<Vec<AdvertisingReport>> {
+    let (&code, event) = event.split_first()?;
+    let (&length, event) = event.split_first()?;
+    if code != EVENT_LE_META {
+        return None;
+    }
+    let parameters = event.get(..length as usize)?;
+    let (&subevent, parameters) = parameters.split_first()?;
+    let (&count, mut remaining) = parameters.split_first()?;
+    // Bytes preceding the address and the data of a report.
+    let (address_offset, data_length_offset) = match subevent {
+        LE_ADVERTISING_REPORT => (2, 8),
+        LE_EXTENDED_ADVERTISING_REPORT => (3, 23),
+        _ => return None,
+    };
+    let mut reports = Vec::new();
+    for _ in 0..count {
+        let address = hci_address(remaining.get(address_offset..address_offset + 6)?);
+        let data_length = *remaining.get(data_length_offset)? as usize;
+        let data_start = data_length_offset + 1;
+        let data = remaining
+            .get(data_start..data_start + data_length)?
+            .to_vec();
+        let rssi = match subevent {
+            LE_ADVERTISING_REPORT => {
+                let rssi = *remaining.get(data_start + data_length)?;
+                remaining = &remaining[data_start + data_length + 1..];
+                rssi
+            }
+            _ => {
+                let rssi = *remaining.get(13)?;
+                remaining = &remaining[data_start + data_length..];
+                rssi
+            }
+        };
+        reports.push(AdvertisingReport {
+            address,
+            rssi: rssi as i8,
+            data,
+        });
+    }
+    Some(reports)
+}
+
+/// HCI transmits addresses least significant byte first, while bluer stores them most significant byte first.
+fn hci_address(bytes: &[u8]) -> Address {
+    let mut address: [u8; 6] = bytes.try_into().expect("The slice is six bytes long.");
+    address.reverse();
+    Address::new(address)
+}
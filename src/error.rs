@@ -0,0 +1,211 @@
+use std::{error::Error, fmt::Display, time::Duration};
+
+use bluer::adv::SecondaryChannel;
+
+use crate::util::to_hex_string;
+
+/// A step of registering an advertisement, see [AppleBleError::Registration].
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Operation {
+    /// Assembling the advertisement from the user data.
+    Assemble,
+    /// Changing the device address the advertisement is sent from.
+    SetAddress,
+    /// Changing the adapter in another way before the advertisement is registered, see [prepare_adapter](crate::advertisement::Advertisable::prepare_adapter).
+    PrepareAdapter,
+    /// Handing the advertisement to BlueZ.
+    Advertise,
+}
+impl Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Operation::Assemble => "assemble",
+            Operation::SetAddress => "set the device address for",
+            Operation::PrepareAdapter => "prepare the adapter for",
+            Operation::Advertise => "register",
+        })
+    }
+}
+
+/// Errors specific to this crate.
+#[derive(Clone, PartialEq, Debug)]
+pub enum AppleBleError {
+    /// The process lacks the privileges to reconfigure the controller and can't escalate them.
+    PermissionDenied(String),
+    /// The user supplied data was rejected by [validate_user_data](crate::advertisement::Advertisable::validate_user_data).
+    InvalidUserData(String),
+    /// A field required by BlueZ isn't set.
+    MissingField(&'static str),
+    /// The advertising data doesn't fit into a single advertisement.
+    PayloadTooLong { length: usize, max: usize },
+    /// The advertising interval is outside of 20ms to 10.24s or the minimum exceeds the maximum.
+    InvalidInterval { min: Duration, max: Duration },
+    /// The controller doesn't support advertising as fast as requested.
+    IntervalUnsupported {
+        requested: Duration,
+        min_allowed: Duration,
+    },
+    /// A key file couldn't be parsed, e.g. by [from_openhaystack](crate::advertisement::FindMyAdvertisementData::from_openhaystack).
+    InvalidKeyFile(String),
+    /// A device address wasn't six bytes long.
+    InvalidAddress { len: usize },
+    /// The controller doesn't support transmitting with the requested power, see [Session::tx_power_range](crate::session::Session::tx_power_range).
+    TxPowerUnsupported { requested: i16, min: i16, max: i16 },
+    /// The controller doesn't support extended advertising on the PHY.
+    PhyUnsupported { phy: SecondaryChannel },
+    /// All advertising instances of the controller are in use.
+    TooManyAdvertisers { max: usize },
+    /// Registering the advertisement named `advertisement`, e.g. `FindMy`, failed during `operation`.
+    Registration {
+        advertisement: String,
+        operation: Operation,
+        reason: String,
+    },
+    /// BlueZ or the D-Bus connection to it reported an error.
+    Bluetooth(String),
+    /// The advertisement wasn't received within the timeout.
+    NotReceived { timeout: Duration },
+    /// The operation was cancelled before it finished.
+    Cancelled,
+    /// A capture couldn't be read or isn't in a supported format.
+    InvalidCapture(String),
+    /// There is no Bluetooth adapter, e.g. in a container or on CI.
+    NoAdapter,
+    /// The device address was changed too recently, see [AddressChangeLimit](crate::session::AddressChangeLimit).
+    RateLimited { retry_after: Duration },
+    /// The device address was already changed and has to be restored first, see [AddressState](crate::session::AddressState).
+    AddressAlreadyChanged,
+    /// The adapter couldn't be reset, see [Session::reset_adapter](crate::session::Session::reset_adapter).
+    ResetFailed(String),
+    /// Connecting to BlueZ didn't complete in time, e.g. because `bluetoothd` is still starting, see [Session::new_with_timeout](crate::session::Session::new_with_timeout).
+    Timeout { timeout: Duration },
+}
+impl Display for AppleBleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppleBleError::PermissionDenied(reason) => write!(
+                f,
+                "Permission denied: {reason}. Run as root, grant the binary CAP_NET_ADMIN (setcap cap_net_admin+ep <binary>) or configure passwordless sudo."
+            ),
+            AppleBleError::InvalidUserData(reason) => write!(f, "Invalid user data: {reason}"),
+            AppleBleError::MissingField(field) => write!(f, "The field {field} is required."),
+            AppleBleError::PayloadTooLong { length, max } => write!(
+                f,
+                "The advertising data is {length} bytes long, but at most {max} bytes are supported."
+            ),
+            AppleBleError::InvalidInterval { min, max } => write!(
+                f,
+                "The advertising interval {min:?} to {max:?} is invalid, it must be within 20ms to 10.24s."
+            ),
+            AppleBleError::IntervalUnsupported {
+                requested,
+                min_allowed,
+            } => write!(
+                f,
+                "The advertising interval {requested:?} isn't supported by the controller, the minimum is {min_allowed:?}."
+            ),
+            AppleBleError::InvalidKeyFile(reason) => write!(f, "Invalid key file: {reason}"),
+            AppleBleError::InvalidAddress { len } => write!(
+                f,
+                "A device address is six bytes long, but {len} bytes were supplied."
+            ),
+            AppleBleError::TxPowerUnsupported {
+                requested,
+                min,
+                max,
+            } => write!(
+                f,
+                "A transmit power of {requested}dBm is outside of the supported range of {min}dBm to {max}dBm."
+            ),
+            AppleBleError::PhyUnsupported { phy } => write!(
+                f,
+                "The controller doesn't support advertising on the {phy:?} PHY."
+            ),
+            AppleBleError::TooManyAdvertisers { max } => write!(
+                f,
+                "All {max} advertising instances of the controller are in use."
+            ),
+            AppleBleError::Registration {
+                advertisement,
+                operation,
+                reason,
+            } => write!(f, "Failed to {operation} {advertisement} advertisement: {reason}"),
+            AppleBleError::Bluetooth(reason) => write!(f, "Bluetooth error: {reason}"),
+            AppleBleError::NotReceived { timeout } => {
+                write!(f, "The advertisement wasn't received within {timeout:?}.")
+            }
+            AppleBleError::Cancelled => write!(f, "The operation was cancelled."),
+            AppleBleError::InvalidCapture(reason) => write!(f, "Invalid capture: {reason}"),
+            AppleBleError::RateLimited { retry_after } => write!(
+                f,
+                "The device address was changed too recently, retry after {retry_after:?}."
+            ),
+            AppleBleError::AddressAlreadyChanged => write!(
+                f,
+                "The device address was already changed, restore it before changing it again."
+            ),
+            AppleBleError::ResetFailed(reason) => {
+                write!(f, "Resetting the adapter failed: {reason}")
+            }
+            AppleBleError::Timeout { timeout } => write!(
+                f,
+                "Connecting to BlueZ didn't complete within {timeout:?}. Check that bluetoothd is running."
+            ),
+            AppleBleError::NoAdapter => write!(
+                f,
+                "No Bluetooth adapter was found. Check that it isn't blocked with `rfkill list` and that `bluetoothctl list` shows it."
+            ),
+        }
+    }
+}
+impl Error for AppleBleError {}
+impl AppleBleError {
+    /// Converts an error of looking up the default adapter, reporting a missing one as [NoAdapter](AppleBleError::NoAdapter).
+    pub fn from_adapter_lookup(error: bluer::Error) -> Self {
+        match error.kind {
+            bluer::ErrorKind::NotFound => AppleBleError::NoAdapter,
+            _ => error.into(),
+        }
+    }
+}
+impl From<bluer::Error> for AppleBleError {
+    fn from(value: bluer::Error) -> Self {
+        AppleBleError::Bluetooth(value.to_string())
+    }
+}
+
+/// Why the advertisement of a device wasn't decoded, see [decode_device](crate::advertisement::decode_device).
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum DecodeError {
+    /// The device advertises manufacturer data, but only of other companies.
+    OtherCompanies { company_ids: Vec<u16> },
+    /// The decoder rejected the manufacturer data, e.g. because it's truncated.
+    Undecodable { data: Vec<u8> },
+    /// BlueZ or the D-Bus connection to it reported an error.
+    Bluetooth(String),
+}
+impl Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::OtherCompanies { company_ids } => {
+                write!(f, "The device only advertises manufacturer data of")?;
+                for company_id in company_ids {
+                    write!(f, " {company_id:#06x}")?;
+                }
+                write!(f, ".")
+            }
+            DecodeError::Undecodable { data } => write!(
+                f,
+                "The manufacturer data {} couldn't be decoded.",
+                to_hex_string(data)
+            ),
+            DecodeError::Bluetooth(reason) => write!(f, "Bluetooth error: {reason}"),
+        }
+    }
+}
+impl Error for DecodeError {}
+impl From<bluer::Error> for DecodeError {
+    fn from(value: bluer::Error) -> Self {
+        DecodeError::Bluetooth(value.to_string())
+    }
+}
@@ -0,0 +1,82 @@
+use bluer::adv::AdvertisementHandle;
+use bluer::gatt::local::{
+    Application, ApplicationHandle, Characteristic, CharacteristicRead, Service,
+};
+use futures::FutureExt;
+use uuid::Uuid;
+
+/// Device Information service, see the Bluetooth SIG assigned numbers.
+pub const DEVICE_INFORMATION_SERVICE: Uuid =
+    Uuid::from_u128(0x0000180a_0000_1000_8000_00805f9b34fb);
+/// Manufacturer Name String characteristic of the Device Information service.
+pub const MANUFACTURER_NAME_CHARACTERISTIC: Uuid =
+    Uuid::from_u128(0x00002a29_0000_1000_8000_00805f9b34fb);
+/// Model Number String characteristic of the Device Information service.
+pub const MODEL_NUMBER_CHARACTERISTIC: Uuid =
+    Uuid::from_u128(0x00002a24_0000_1000_8000_00805f9b34fb);
+
+/// Stub service, which a connectable AirPrint advertisement serves the printer URI through.
+///
+/// Apple's service for printing over BLE isn't documented, so this UUID isn't assigned by anyone.
+pub const IPP_SERVICE: Uuid = Uuid::from_u128(0x6e3b0001_5c1a_4d0f_9c2e_7a1f4b8d0c11);
+/// Printer URI characteristic of the [IPP_SERVICE], e.g. `ipps://[::1]:631/ipp/print`.
+pub const PRINTER_URI_CHARACTERISTIC: Uuid =
+    Uuid::from_u128(0x6e3b0002_5c1a_4d0f_9c2e_7a1f4b8d0c11);
+
+/// Handles of a connectable advertisement and the GATT application served alongside it.
+///
+/// Dropping it stops both.
+pub struct ConnectableAdvertisementHandle {
+    pub advertisement: AdvertisementHandle,
+    pub application: ApplicationHandle,
+}
+
+/// A characteristic, which always reads as `value`.
+pub fn read_only_characteristic(uuid: Uuid, value: Vec<u8>) -> Characteristic {
+    Characteristic {
+        uuid,
+        read: Some(CharacteristicRead {
+            read: true,
+            fun: Box::new(move |_request| {
+                let value = value.clone();
+                async move { Ok(value) }.boxed()
+            }),
+            ..Default::default()
+        }),
+        ..Default::default()
+    }
+}
+
+/// A GATT application exposing the Device Information service, like Apple devices do.
+///
+/// The manufacturer name is always "Apple Inc.", the model number e.g. "AppleTV6,2".
+pub fn device_information_application(model: &str) -> Application {
+    Application {
+        services: vec![Service {
+            uuid: DEVICE_INFORMATION_SERVICE,
+            primary: true,
+            characteristics: vec![
+                read_only_characteristic(MANUFACTURER_NAME_CHARACTERISTIC, b"Apple Inc.".to_vec()),
+                read_only_characteristic(MODEL_NUMBER_CHARACTERISTIC, model.as_bytes().to_vec()),
+            ],
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
+
+/// A GATT application exposing the [IPP_SERVICE], whose only characteristic reads as `printer_uri`.
+pub fn airprint_application(printer_uri: &str) -> Application {
+    Application {
+        services: vec![Service {
+            uuid: IPP_SERVICE,
+            primary: true,
+            characteristics: vec![read_only_characteristic(
+                PRINTER_URI_CHARACTERISTIC,
+                printer_uri.as_bytes().to_vec(),
+            )],
+            ..Default::default()
+        }],
+        ..Default::default()
+    }
+}
@@ -0,0 +1,187 @@
+use std::error::Error;
+
+#[cfg(feature = "disable_afit")]
+use async_trait::async_trait;
+use bluer::adv::{Advertisement, AdvertisementHandle};
+use bluer::Address;
+
+use crate::session::Session;
+use crate::util::{restore_device_addr, set_device_addr};
+
+/// The identity of a device to impersonate, see [Session::impersonate].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct DeviceIdentity {
+    /// Set as the adapter alias, which other devices display.
+    pub name: String,
+    /// Set as the device address, if present.
+    pub address: Option<Address>,
+    /// GAP appearance advertised with the advertisement, e.g. `0x0040` for a phone.
+    pub appearance: Option<u16>,
+}
+impl DeviceIdentity {
+    /// An identity, which only changes the name.
+    pub fn new(name: &str) -> Self {
+        DeviceIdentity {
+            name: name.to_string(),
+            address: None,
+            appearance: None,
+        }
+    }
+    /// Adds the appearance of the identity to `advertisement`, keeping its own if the identity has none.
+    pub fn apply(&self, advertisement: Advertisement) -> Advertisement {
+        Advertisement {
+            appearance: self.appearance.or(advertisement.appearance),
+            ..advertisement
+        }
+    }
+}
+
+// If the user opted out of using "async_fn_in_trait", use the crate async-trait instead.
+#[cfg_attr(feature = "disable_afit", async_trait)]
+/// The adapter settings changed by an [Impersonation], so they can be replaced in tests.
+///
+/// Backends are `'static`, so a dropped impersonation can take its backend along to revert the changes in the background.
+pub trait IdentityBackend: Send + Sync + 'static {
+    async fn alias(&self) -> Result<String, Box<dyn Error>>;
+    async fn set_alias(&mut self, alias: &str) -> Result<(), Box<dyn Error>>;
+    async fn address(&self) -> Result<Address, Box<dyn Error>>;
+    /// Changes the device address, which is reverted through [restore_address](Self::restore_address).
+    async fn set_address(&mut self, address: Address) -> Result<(), Box<dyn Error>>;
+    async fn restore_address(&mut self, address: Address) -> Result<(), Box<dyn Error>>;
+}
+/// Changing the address restarts the bluetooth service, which blocks the executing thread until it's done.
+///
+/// Clones of a session share its address state, so the impersonation and the session agree on whether the address was changed.
+#[cfg_attr(feature = "disable_afit", async_trait)]
+impl IdentityBackend for Session {
+    async fn alias(&self) -> Result<String, Box<dyn Error>> {
+        Ok(self.adapter.alias().await?)
+    }
+    async fn set_alias(&mut self, alias: &str) -> Result<(), Box<dyn Error>> {
+        Ok(self.adapter.set_alias(alias.to_string()).await?)
+    }
+    async fn address(&self) -> Result<Address, Box<dyn Error>> {
+        Ok(self.adapter.address().await?)
+    }
+    async fn set_address(&mut self, address: Address) -> Result<(), Box<dyn Error>> {
+        set_device_addr(self, &address.0)
+    }
+    async fn restore_address(&mut self, address: Address) -> Result<(), Box<dyn Error>> {
+        restore_device_addr(self, &address.0)
+    }
+}
+
+/// An impersonated identity, whose changes are reverted through [revert](Self::revert).
+///
+/// Dropping it stops the advertisement and reverts the name and the address on a separate thread, driven by the tokio runtime the impersonation is dropped on.
+/// Failures are only logged then, and outside of a runtime nothing can be reverted, so the changes stay and a warning is logged.
+/// Call [revert](Self::revert) to wait for the revert and see its errors.
+pub struct Impersonation<B: IdentityBackend> {
+    /// Taken once the changes are reverted.
+    backend: Option<B>,
+    original_alias: Option<String>,
+    original_address: Option<Address>,
+    pub(crate) handle: Option<AdvertisementHandle>,
+}
+impl<B: IdentityBackend> Impersonation<B> {
+    /// Sets the address and the name of `identity` through `backend`, without advertising anything.
+    ///
+    /// The address is changed first, since BlueZ stores the alias per address.
+    /// Should setting the alias fail, the address is restored before the error is returned.
+    pub async fn apply(mut backend: B, identity: &DeviceIdentity) -> Result<Self, Box<dyn Error>> {
+        let original_alias = backend.alias().await?;
+        let original_address = match identity.address {
+            Some(address) => {
+                let original_address = backend.address().await?;
+                backend.set_address(address).await?;
+                Some(original_address)
+            }
+            None => None,
+        };
+        let mut impersonation = Impersonation {
+            backend: Some(backend),
+            original_alias: None,
+            original_address,
+            handle: None,
+        };
+        let backend = impersonation
+            .backend
+            .as_mut()
+            .expect("The backend is only taken once reverted.");
+        if let Err(err) = backend.set_alias(&identity.name).await {
+            // The alias wasn't changed, so only the address is reverted.
+            impersonation.revert().await?;
+            return Err(err);
+        }
+        impersonation.original_alias = Some(original_alias);
+        Ok(impersonation)
+    }
+    /// The device address, which will be restored, if the address was changed.
+    pub fn original_address(&self) -> Option<Address> {
+        self.original_address
+    }
+    /// Stops the advertisement and reverts the name and the address.
+    ///
+    /// Both are attempted, even if reverting the name fails.
+    pub async fn revert(mut self) -> Result<(), Box<dyn Error>> {
+        drop(self.handle.take());
+        match self.backend.take() {
+            Some(backend) => {
+                revert_changes(
+                    backend,
+                    self.original_alias.take(),
+                    self.original_address.take(),
+                )
+                .await
+            }
+            None => Ok(()),
+        }
+    }
+}
+impl<B: IdentityBackend> Drop for Impersonation<B> {
+    fn drop(&mut self) {
+        drop(self.handle.take());
+        let original_alias = self.original_alias.take();
+        let original_address = self.original_address.take();
+        let Some(backend) = self.backend.take() else {
+            return;
+        };
+        if original_alias.is_none() && original_address.is_none() {
+            return;
+        }
+        match tokio::runtime::Handle::try_current() {
+            // Restoring the address blocks, and the backend futures needn't be Send, so they're driven on their own thread.
+            Ok(runtime) => {
+                std::thread::spawn(move || {
+                    if let Err(err) = runtime.block_on(revert_changes(
+                        backend,
+                        original_alias,
+                        original_address,
+                    )) {
+                        log::warn!("Reverting a dropped impersonation failed: {err}");
+                    }
+                });
+            }
+            Err(_) => log::warn!(
+                "An impersonation was dropped outside of a tokio runtime, so the name and the address stay changed."
+            ),
+        }
+    }
+}
+
+/// Reverts the name and the address, attempting both, even if reverting the name fails.
+async fn revert_changes<B: IdentityBackend>(
+    mut backend: B,
+    original_alias: Option<String>,
+    original_address: Option<Address>,
+) -> Result<(), Box<dyn Error>> {
+    let alias = match original_alias {
+        Some(alias) => backend.set_alias(&alias).await,
+        None => Ok(()),
+    };
+    let address = match original_address {
+        Some(address) => backend.restore_address(address).await,
+        None => Ok(()),
+    };
+    alias.and(address)
+}
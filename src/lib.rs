@@ -1,6 +1,15 @@
 #![allow(incomplete_features)]
 // opt-out of using the unstable feature "async_fn_in_trait". See https://github.com/rust-lang/rust/issues/91611.
 #![cfg_attr(not(feature = "disable_afit"), feature(async_fn_in_trait))]
-mod util;
 pub mod advertisement;
-pub mod session;
\ No newline at end of file
+#[cfg(feature = "btsnoop")]
+pub mod btsnoop;
+pub mod error;
+#[cfg(feature = "gatt")]
+pub mod gatt;
+pub mod identity;
+pub mod registry;
+pub mod replay;
+pub mod scan;
+pub mod session;
+mod util;
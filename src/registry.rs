@@ -0,0 +1,168 @@
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock, RwLock};
+
+use bluer::Address;
+
+#[cfg(feature = "airdrop")]
+use crate::advertisement::AirDropAdvertisementData;
+#[cfg(feature = "airprint")]
+use crate::advertisement::AirPrintAdvertisementData;
+#[cfg(feature = "findmy")]
+use crate::advertisement::FindMyAdvertisementData;
+#[cfg(feature = "handoff")]
+use crate::advertisement::HandoffAdvertisementData;
+#[cfg(feature = "nearby")]
+use crate::advertisement::NearbyInfoAdvertisementData;
+#[cfg(feature = "tethering")]
+use crate::advertisement::TetheringSourceAdvertisementData;
+#[cfg(feature = "watch")]
+use crate::advertisement::WatchConnectionAdvertisementData;
+use crate::advertisement::{decode_apple, AdvertisableData, AdvertisementType};
+#[cfg(feature = "airplay")]
+use crate::advertisement::{AirPlaySourceAdvertisementData, AirPlayTargetAdvertisementData};
+
+/// Message types decoded by this crate, depending on the enabled message type features.
+///
+/// FindMy's message type covers both the long and the short message.
+pub const BUILTIN_MESSAGE_TYPES: &[u8] = &[
+    #[cfg(feature = "airprint")]
+    AirPrintAdvertisementData::MESSAGE_TYPE,
+    #[cfg(feature = "airdrop")]
+    AirDropAdvertisementData::MESSAGE_TYPE,
+    #[cfg(feature = "airplay")]
+    AirPlayTargetAdvertisementData::MESSAGE_TYPE,
+    #[cfg(feature = "airplay")]
+    AirPlaySourceAdvertisementData::MESSAGE_TYPE,
+    #[cfg(feature = "handoff")]
+    HandoffAdvertisementData::MESSAGE_TYPE,
+    #[cfg(feature = "tethering")]
+    TetheringSourceAdvertisementData::MESSAGE_TYPE,
+    #[cfg(feature = "nearby")]
+    NearbyInfoAdvertisementData::MESSAGE_TYPE,
+    #[cfg(feature = "findmy")]
+    FindMyAdvertisementData::MESSAGE_TYPE,
+    #[cfg(feature = "watch")]
+    WatchConnectionAdvertisementData::MESSAGE_TYPE,
+];
+
+/// Decodes a Continuity message, starting with its type byte, advertised by the device with `address`.
+pub type DecodeFn = Arc<dyn Fn(Address, &[u8]) -> Option<AdvertisementType> + Send + Sync>;
+
+/// Maps Continuity message types to their decoders.
+///
+/// Registering a decoder replaces the previous one of the message type, including the built-in one.
+/// The registry can be shared between threads, [global](Self::global) is used by [decode_device](crate::advertisement::decode_device).
+pub struct DecoderRegistry {
+    decoders: RwLock<HashMap<u8, DecodeFn>>,
+}
+impl DecoderRegistry {
+    /// Creates a registry without any decoders.
+    pub fn empty() -> Self {
+        DecoderRegistry {
+            decoders: RwLock::new(HashMap::new()),
+        }
+    }
+    /// The registry consulted while scanning, populated with the built-in decoders.
+    pub fn global() -> &'static DecoderRegistry {
+        static GLOBAL: OnceLock<DecoderRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(DecoderRegistry::default)
+    }
+    /// Registers `decode_fn` for `message_type`.
+    pub fn register<F>(&self, message_type: u8, decode_fn: F)
+    where
+        F: Fn(Address, &[u8]) -> Option<AdvertisementType> + Send + Sync + 'static,
+    {
+        self.decoders
+            .write()
+            .expect("A decoder panicked while registering.")
+            .insert(message_type, Arc::new(decode_fn));
+    }
+    /// Decodes the manufacturer data with the decoder of its message type.
+    ///
+    /// Messages of a type without a decoder are returned as [AdvertisementType::Unknown].
+    /// [None] is returned if the decoder rejected the message, e.g. because it's truncated.
+    pub fn decode(&self, address: Address, manufacturer_data: &[u8]) -> Option<AdvertisementType> {
+        let message_type = *manufacturer_data.first()?;
+        // Clone the decoder, so it can register decoders itself.
+        let decoder = self
+            .decoders
+            .read()
+            .expect("A decoder panicked while registering.")
+            .get(&message_type)
+            .cloned();
+        match decoder {
+            Some(decoder) => decoder(address, manufacturer_data),
+            None => Some(AdvertisementType::Unknown {
+                message_type,
+                data: manufacturer_data.to_vec(),
+            }),
+        }
+    }
+    /// Decodes every message packed into the manufacturer data, like Nearby Info followed by Handoff.
+    ///
+    /// Each message is split off by its length byte and decoded like [decode](Self::decode).
+    /// Messages rejected by their decoder are skipped, a truncated last message ends the decoding.
+    pub fn decode_all(&self, address: Address, manufacturer_data: &[u8]) -> DecodedMessages {
+        let mut messages = Vec::new();
+        let mut remaining = manufacturer_data;
+        while let Some(&length) = remaining.get(1) {
+            let Some(message) = remaining.get(..2 + length as usize) else {
+                break;
+            };
+            messages.extend(self.decode(address, message));
+            remaining = &remaining[message.len()..];
+        }
+        DecodedMessages { messages }
+    }
+}
+
+impl Default for DecoderRegistry {
+    fn default() -> Self {
+        let registry = DecoderRegistry::empty();
+        for &message_type in BUILTIN_MESSAGE_TYPES {
+            registry.register(message_type, decode_apple);
+        }
+        registry
+    }
+}
+
+/// The messages decoded from one manufacturer data entry, see [DecoderRegistry::decode_all].
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct DecodedMessages {
+    messages: Vec<AdvertisementType>,
+}
+impl DecodedMessages {
+    /// Keeps only the messages of `message_type`.
+    pub fn filter_type(self, message_type: u8) -> Self {
+        DecodedMessages {
+            messages: self
+                .messages
+                .into_iter()
+                .filter(|message| message.message_type() == message_type)
+                .collect(),
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.messages.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.messages.is_empty()
+    }
+    pub fn iter(&self) -> std::slice::Iter<'_, AdvertisementType> {
+        self.messages.iter()
+    }
+}
+impl IntoIterator for DecodedMessages {
+    type Item = AdvertisementType;
+    type IntoIter = std::vec::IntoIter<AdvertisementType>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.messages.into_iter()
+    }
+}
+impl<'a> IntoIterator for &'a DecodedMessages {
+    type Item = &'a AdvertisementType;
+    type IntoIter = std::slice::Iter<'a, AdvertisementType>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.messages.iter()
+    }
+}
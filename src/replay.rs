@@ -0,0 +1,98 @@
+use std::error::Error;
+use std::future::Future;
+use std::time::Duration;
+
+use bluer::adv::AdvertisementHandle;
+use bluer::Address;
+
+#[cfg(feature = "findmy")]
+use crate::advertisement::AdvertisementParams;
+use crate::advertisement::AdvertisementType;
+use crate::session::Session;
+#[cfg(feature = "findmy")]
+use crate::util::spawn_set_static_random_addr;
+
+/// Re-advertises a captured sequence of advertisements, each one at its offset from the start of the replay.
+///
+/// Every advertisement replaces the previous one, the last one keeps being advertised until the returned handle is dropped.
+/// FindMy advertisements are sent from the random static address of their key, like [register_random](crate::advertisement::FindMyAdvertisement::register_random) does.
+/// Since changing it power cycles the adapter, the address is only changed when the key does, which delays that event by the time it takes.
+pub async fn replay(
+    session: &mut Session,
+    events: Vec<(Duration, AdvertisementType)>,
+) -> Result<Option<AdvertisementHandle>, Box<dyn Error>> {
+    let session = &*session;
+    replay_with(events, |advertisement, address| {
+        let mut session = session.clone();
+        async move {
+            #[cfg(feature = "findmy")]
+            if findmy_address(&advertisement).is_some() {
+                return register_findmy(&mut session, &advertisement, address).await;
+            }
+            // Only FindMy advertisements change the address.
+            #[cfg(not(feature = "findmy"))]
+            let _ = address;
+            advertisement.register(&mut session).await
+        }
+    })
+    .await
+}
+
+/// Advertises a FindMy advertisement from the random static address of its key, switching to `address` first, if given.
+#[cfg(feature = "findmy")]
+async fn register_findmy(
+    session: &mut Session,
+    advertisement: &AdvertisementType,
+    address: Option<Address>,
+) -> Result<AdvertisementHandle, Box<dyn Error>> {
+    if let Some(address) = address {
+        spawn_set_static_random_addr(session, address.0).await?;
+    }
+    // Unlike registering, assembling doesn't change the device address.
+    let advertisement = advertisement.to_advertisement(session, &AdvertisementParams::default())?;
+    let handle = session.adapter.advertise(advertisement).await?;
+    session.record_registration();
+    Ok(handle)
+}
+
+/// Drives a replay like [replay], but advertises through `advertise`, e.g. to replay into something other than an adapter.
+///
+/// `advertise` receives the address to switch to before a FindMy advertisement, if it differs from the one of the last FindMy advertisement.
+/// The handle it returns is dropped once the next advertisement is due, the last one is returned.
+/// The offsets are expected to increase like in a capture, an event whose offset already passed is advertised right away.
+pub async fn replay_with<F, Fut, H, E>(
+    events: Vec<(Duration, AdvertisementType)>,
+    mut advertise: F,
+) -> Result<Option<H>, E>
+where
+    F: FnMut(AdvertisementType, Option<Address>) -> Fut,
+    Fut: Future<Output = Result<H, E>>,
+{
+    let start = tokio::time::Instant::now();
+    let mut last_findmy_address = None;
+    let mut handle = None;
+    for (offset, advertisement) in events {
+        tokio::time::sleep_until(start + offset).await;
+        let address_change = match findmy_address(&advertisement) {
+            Some(address) if last_findmy_address != Some(address) => {
+                last_findmy_address = Some(address);
+                last_findmy_address
+            }
+            _ => None,
+        };
+        drop(handle.take());
+        handle = Some(advertise(advertisement, address_change).await?);
+    }
+    Ok(handle)
+}
+
+/// The address a FindMy advertisement of either length is sent from.
+fn findmy_address(advertisement: &AdvertisementType) -> Option<Address> {
+    match advertisement {
+        #[cfg(feature = "findmy")]
+        AdvertisementType::FindMy(data) => Some(data.address()),
+        #[cfg(feature = "findmy")]
+        AdvertisementType::FindMyNearby(data) => Some(data.address()),
+        _ => None,
+    }
+}
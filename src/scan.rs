@@ -0,0 +1,389 @@
+use std::collections::{HashMap, HashSet};
+use std::future::{ready, Future};
+use std::pin::pin;
+use std::time::{Duration, Instant, SystemTime};
+
+use bluer::{
+    Adapter, AdapterEvent, Address, DeviceEvent, DeviceProperty, DiscoveryFilter,
+    DiscoveryTransport,
+};
+use futures::{stream, Stream, StreamExt};
+
+use crate::advertisement::{decode_device, AdvertisementType, APPLE_MAGIC};
+use crate::registry::DecoderRegistry;
+use crate::util::to_hex_string;
+
+/// Discovery filter for Continuity messages, which are only advertised over LE.
+///
+/// Classic inquiry results are excluded and every advertisement is reported, even if its data didn't change, so the RSSI stays current.
+pub fn le_discovery_filter() -> DiscoveryFilter {
+    DiscoveryFilter {
+        transport: DiscoveryTransport::Le,
+        duplicate_data: true,
+        ..Default::default()
+    }
+}
+
+/// Extracts the RSSI updates of the device with `address` from its event stream.
+///
+/// The stream ends, once the adapter reports the device as removed.
+pub fn rssi_updates(
+    address: Address,
+    device_events: impl Stream<Item = DeviceEvent>,
+    adapter_events: impl Stream<Item = AdapterEvent>,
+) -> impl Stream<Item = i16> {
+    let rssi = device_events.filter_map(|event| {
+        ready(match event {
+            DeviceEvent::PropertyChanged(DeviceProperty::Rssi(rssi)) => Some(Some(rssi)),
+            _ => None,
+        })
+    });
+    let removed = adapter_events.filter_map(move |event| {
+        ready(match event {
+            AdapterEvent::DeviceRemoved(removed) if removed == address => Some(None),
+            _ => None,
+        })
+    });
+    stream::select(rssi, removed)
+        .take_while(|rssi| ready(rssi.is_some()))
+        .filter_map(ready)
+}
+
+/// Waits for the device with `address` to be added in `events` with a known RSSI, which is queried through `rssi`.
+///
+/// Returns [None], if the device didn't appear before `timeout` elapsed.
+pub async fn wait_for_device<F, Fut>(
+    address: Address,
+    events: impl Stream<Item = AdapterEvent>,
+    mut rssi: F,
+    timeout: Duration,
+) -> Option<(Address, i16)>
+where
+    F: FnMut(Address) -> Fut,
+    Fut: Future<Output = Option<i16>>,
+{
+    let search = async {
+        let mut events = pin!(events);
+        while let Some(event) = events.next().await {
+            match event {
+                AdapterEvent::DeviceAdded(added) if added == address => {
+                    if let Some(rssi) = rssi(added).await {
+                        return Some((added, rssi));
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    };
+    tokio::time::timeout(timeout, search).await.ok().flatten()
+}
+
+/// Suppresses advertisements, which the same device already advertised within `window`.
+///
+/// Changed advertisements always pass, e.g. when the action of a Nearby Info message changes.
+pub fn dedup_debounce(
+    advertisements: impl Stream<Item = (Address, AdvertisementType)>,
+    window: Duration,
+) -> impl Stream<Item = (Address, AdvertisementType)> {
+    let mut last_emitted: HashMap<Address, (AdvertisementType, Instant)> = HashMap::new();
+    advertisements.filter(move |(address, advertisement)| {
+        let now = Instant::now();
+        let emit = match last_emitted.get(address) {
+            Some((last, emitted)) => last != advertisement || now - *emitted >= window,
+            None => true,
+        };
+        if emit {
+            last_emitted.insert(*address, (advertisement.clone(), now));
+        }
+        ready(emit)
+    })
+}
+
+/// An advertisement yielded by [advertisement_events].
+#[derive(Clone, PartialEq, Debug)]
+pub enum AdvertisementEvent {
+    /// The device advertised for the first time or with the message type of its last advertisement.
+    Advertised {
+        address: Address,
+        advertisement: AdvertisementType,
+    },
+    /// The device switched to another message type, e.g. from AirDrop to Nearby Info.
+    TypeChanged {
+        address: Address,
+        from: AdvertisementType,
+        to: AdvertisementType,
+    },
+}
+
+/// Remembers the last advertisement of every device and reports when a device switches to another message type.
+pub fn advertisement_events(
+    advertisements: impl Stream<Item = (Address, AdvertisementType)>,
+) -> impl Stream<Item = AdvertisementEvent> {
+    let mut last_seen: HashMap<Address, AdvertisementType> = HashMap::new();
+    advertisements.map(move |(address, advertisement)| {
+        match last_seen.insert(address, advertisement.clone()) {
+            Some(from) if from.message_type() != advertisement.message_type() => {
+                AdvertisementEvent::TypeChanged {
+                    address,
+                    from,
+                    to: advertisement,
+                }
+            }
+            _ => AdvertisementEvent::Advertised {
+                address,
+                advertisement,
+            },
+        }
+    })
+}
+
+/// Counts the message types advertised by the device with `address`, until `duration` elapsed.
+pub async fn message_type_histogram(
+    address: Address,
+    advertisements: impl Stream<Item = (Address, AdvertisementType)>,
+    duration: Duration,
+) -> HashMap<u8, usize> {
+    let mut histogram = HashMap::new();
+    let mut advertisements = pin!(advertisements.take_until(tokio::time::sleep(duration)));
+    while let Some((seen, advertisement)) = advertisements.next().await {
+        if seen == address {
+            *histogram.entry(advertisement.message_type()).or_insert(0) += 1;
+        }
+    }
+    histogram
+}
+
+/// Decodes the devices with `addresses` through `decode`, skipping the ones without a decodable advertisement.
+///
+/// The devices are decoded concurrently, but returned in the order of `addresses`.
+pub async fn decode_addresses<F, Fut>(
+    addresses: impl IntoIterator<Item = Address>,
+    decode: F,
+) -> Vec<(Address, AdvertisementType)>
+where
+    F: Fn(Address) -> Fut,
+    Fut: Future<Output = Option<AdvertisementType>>,
+{
+    let decoded = futures::future::join_all(addresses.into_iter().map(|address| {
+        let advertisement = decode(address);
+        async move { Some((address, advertisement.await?)) }
+    }))
+    .await;
+    decoded.into_iter().flatten().collect()
+}
+
+/// Decodes the advertisement of every device, which is added to `events`, through `decode`, skipping the ones without one.
+///
+/// The devices are decoded one after another, in the order of the events.
+/// Changes are only decoded, if they're reported as added devices, like [discover_devices_with_changes](Adapter::discover_devices_with_changes) does, see [decode_events_with_changes] otherwise.
+pub fn decode_events<F, Fut>(
+    events: impl Stream<Item = AdapterEvent>,
+    decode: F,
+) -> impl Stream<Item = (Address, AdvertisementType)>
+where
+    F: Fn(Address) -> Fut,
+    Fut: Future<Output = Option<AdvertisementType>>,
+{
+    events.filter_map(move |event| {
+        let decoded = match event {
+            AdapterEvent::DeviceAdded(address) => Some((address, decode(address))),
+            _ => None,
+        };
+        async move {
+            let (address, advertisement) = decoded?;
+            Some((address, advertisement.await?))
+        }
+    })
+}
+
+/// Decodes the advertisement of every device added to `events` through `decode`, followed by every change of its manufacturer data of `company_id`, see [manufacturer_data_updates].
+///
+/// The changes are read from the stream `device_events` returns for the device, which is only requested once per device, even if it's added again.
+/// The devices are followed concurrently, so the changes of different devices are yielded as they arrive.
+pub fn decode_events_with_changes<F, Fut, G, GFut, S>(
+    events: impl Stream<Item = AdapterEvent>,
+    company_id: u16,
+    decode: F,
+    device_events: G,
+) -> impl Stream<Item = (Address, AdvertisementType)>
+where
+    F: Fn(Address) -> Fut,
+    Fut: Future<Output = Option<AdvertisementType>>,
+    G: Fn(Address) -> GFut,
+    GFut: Future<Output = Option<S>>,
+    S: Stream<Item = DeviceEvent>,
+{
+    let mut followed = HashSet::new();
+    events
+        .filter_map(move |event| {
+            ready(match event {
+                AdapterEvent::DeviceAdded(address) => Some((address, followed.insert(address))),
+                _ => None,
+            })
+        })
+        .map(move |(address, follow)| {
+            let changes = follow.then(|| device_events(address));
+            let current = decode(address);
+            Box::pin(
+                stream::once(async move {
+                    // Subscribe first, so no change is missed while decoding.
+                    let changes = match changes {
+                        Some(changes) => changes.await,
+                        None => None,
+                    };
+                    (current.await, changes)
+                })
+                .flat_map(move |(current, changes)| {
+                    stream::iter(current).chain(manufacturer_data_updates(
+                        address,
+                        company_id,
+                        stream::iter(changes).flatten(),
+                    ))
+                })
+                .map(move |advertisement| (address, advertisement)),
+            )
+        })
+        .flatten_unordered(None)
+}
+
+/// Decodes the Apple manufacturer data of every device, which the adapter reports as added, and of every change of it without blocking.
+///
+/// Devices without decodable Apple manufacturer data are skipped, see [decode_events_with_changes].
+/// This doesn't start a discovery, see [Session::scan](crate::session::Session::scan) for one.
+pub async fn decode_adapter_events(
+    adapter: &Adapter,
+) -> bluer::Result<impl Stream<Item = (Address, AdvertisementType)>> {
+    let events = adapter.events().await?;
+    let (decoding, following) = (adapter.clone(), adapter.clone());
+    Ok(decode_events_with_changes(
+        events,
+        APPLE_MAGIC,
+        move |address| {
+            let adapter = decoding.clone();
+            async move {
+                decode_device(&adapter.device(address).ok()?, APPLE_MAGIC)
+                    .await
+                    .ok()?
+            }
+        },
+        move |address| {
+            let adapter = following.clone();
+            async move { adapter.device(address).ok()?.events().await.ok() }
+        },
+    ))
+}
+
+/// Decodes the manufacturer data of `company_id` every time it changes in the event stream of the device with `address`.
+pub fn manufacturer_data_updates(
+    address: Address,
+    company_id: u16,
+    device_events: impl Stream<Item = DeviceEvent>,
+) -> impl Stream<Item = AdvertisementType> {
+    device_events.filter_map(move |event| {
+        ready(match event {
+            DeviceEvent::PropertyChanged(DeviceProperty::ManufacturerData(manufacturer_data)) => {
+                manufacturer_data
+                    .get(&company_id)
+                    .and_then(|data| DecoderRegistry::global().decode(address, data))
+            }
+            _ => None,
+        })
+    })
+}
+
+/// A decoded advertisement together with its reception, see [write_ndjson].
+#[derive(Clone, PartialEq, Debug)]
+pub struct ScanRecord {
+    pub address: Address,
+    pub advertisement: AdvertisementType,
+    pub rssi: Option<i16>,
+    pub timestamp: SystemTime,
+}
+#[cfg(feature = "serde")]
+impl ScanRecord {
+    /// The [JSON object](AdvertisementType::to_json) of the advertisement, extended by the `address`, the `rssi` and the `timestamp` in milliseconds since the Unix epoch.
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut value = self.advertisement.to_json();
+        value["address"] = self.address.to_string().into();
+        value["rssi"] = self.rssi.into();
+        value["timestamp"] = self
+            .timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |timestamp| timestamp.as_millis() as u64)
+            .into();
+        value
+    }
+}
+
+/// Writes every record as a JSON object on its own line (NDJSON), flushing after each one.
+#[cfg(feature = "serde")]
+pub async fn write_ndjson(
+    records: impl Stream<Item = ScanRecord>,
+    mut writer: impl std::io::Write,
+) -> std::io::Result<()> {
+    let mut records = pin!(records);
+    while let Some(record) = records.next().await {
+        writeln!(writer, "{}", record.to_json())?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+/// Writes every [Unknown](AdvertisementType::Unknown) message in `records` to `writer` once, flushing after each one.
+///
+/// Every line holds the timestamp in milliseconds since the Unix epoch, the address, the RSSI, left empty if unknown, and the complete message as hex, separated by tabs.
+/// Messages are de-duplicated by their bytes, regardless of the advertising device, and added to `seen`.
+/// Returns the number of written messages.
+pub async fn write_unknown_messages(
+    records: impl Stream<Item = ScanRecord>,
+    mut writer: impl std::io::Write,
+    seen: &mut HashSet<Vec<u8>>,
+) -> std::io::Result<usize> {
+    let mut records = pin!(records);
+    let mut written = 0;
+    while let Some(record) = records.next().await {
+        let AdvertisementType::Unknown { data, .. } = &record.advertisement else {
+            continue;
+        };
+        if seen.contains(data) {
+            continue;
+        }
+        let timestamp = record
+            .timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_or(0, |timestamp| timestamp.as_millis());
+        let rssi = record.rssi.map(|rssi| rssi.to_string()).unwrap_or_default();
+        writeln!(
+            writer,
+            "{timestamp}\t{}\t{rssi}\t{}",
+            record.address,
+            to_hex_string(data)
+        )?;
+        writer.flush()?;
+        seen.insert(data.clone());
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Reads the messages of a file written by [write_unknown_messages], so they aren't written again.
+///
+/// Malformed lines are skipped.
+pub fn read_unknown_messages(reader: impl std::io::BufRead) -> std::io::Result<HashSet<Vec<u8>>> {
+    let mut seen = HashSet::new();
+    for line in reader.lines() {
+        let line = line?;
+        let Some(hex) = line.rsplit('\t').next() else {
+            continue;
+        };
+        let data: Option<Vec<u8>> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+            .collect();
+        if let Some(data) = data.filter(|data| !data.is_empty()) {
+            seen.insert(data);
+        }
+    }
+    Ok(seen)
+}
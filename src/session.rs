@@ -1,15 +1,777 @@
+use std::collections::{BTreeSet, HashMap};
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime};
+
+use bluer::adv::{AdvertisementHandle, PlatformFeature, SecondaryChannel, Type};
+use bluer::{AdapterEvent, Address, Device, DiscoveryFilter};
+use futures::{Stream, StreamExt};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+#[cfg(feature = "findmy")]
+use crate::advertisement::FindMyAdvertisementData;
+use crate::advertisement::{
+    decode_apple, Advertisable, AdvertisableData, AdvertisementParams, AdvertisementType,
+    APPLE_MAGIC, LEGACY_MIN_NONCONNECTABLE_INTERVAL, MIN_ADVERTISING_INTERVAL,
+};
+use crate::error::AppleBleError;
+use crate::identity::{DeviceIdentity, Impersonation};
+use crate::registry::DecoderRegistry;
+#[cfg(feature = "serde")]
+use crate::scan::write_ndjson;
+use crate::scan::{
+    advertisement_events, decode_addresses, decode_events, le_discovery_filter,
+    manufacturer_data_updates, message_type_histogram, read_unknown_messages, rssi_updates,
+    wait_for_device, write_unknown_messages, AdvertisementEvent, ScanRecord,
+};
+#[cfg(feature = "findmy")]
+pub use crate::util::restore_bredr;
+use crate::util::{can_escalate, ensure_privileges, is_on_path};
+pub use crate::util::{
+    set_device_addr_with_progress, AddressProgress, CommandRunner, Privileges, Restart,
+    SystemCommandRunner,
+};
+
+/// Result of [Session::self_test].
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SelfTestReport {
+    /// The adapter is known to BlueZ.
+    pub adapter_present: bool,
+    pub adapter_powered: bool,
+    /// BlueZ exposes the LE advertising manager for the adapter.
+    pub advertising_supported: bool,
+    /// `bdaddr` is on PATH, which changing the device address for FindMy requires.
+    pub bdaddr_available: bool,
+    /// `btmgmt` is on PATH, which setting a random static address for FindMy requires.
+    pub btmgmt_available: bool,
+    /// The process is privileged or can escalate without blocking on a password prompt.
+    pub can_escalate: bool,
+}
+impl SelfTestReport {
+    /// Describes every failed check.
+    pub fn problems(&self) -> Vec<&'static str> {
+        [
+            (self.adapter_present, "The adapter isn't known to BlueZ."),
+            (self.adapter_powered, "The adapter isn't powered."),
+            (
+                self.advertising_supported,
+                "BlueZ doesn't support advertising on the adapter.",
+            ),
+            (self.bdaddr_available, "bdaddr isn't on PATH."),
+            (self.btmgmt_available, "btmgmt isn't on PATH."),
+            (
+                self.can_escalate,
+                "The process is unprivileged and can't escalate without a password prompt.",
+            ),
+        ]
+        .into_iter()
+        .filter_map(|(passed, problem)| (!passed).then_some(problem))
+        .collect()
+    }
+    /// Whether all checks passed.
+    pub fn is_ok(&self) -> bool {
+        self.problems().is_empty()
+    }
+}
+
+/// Limits the number of advertisements registered at the same time to the advertising instances of the controller.
+///
+/// BlueZ rejects advertisements exceeding it, possibly after others of a batch were registered successfully.
+#[derive(Clone, Debug)]
+pub struct AdvertiserLimit {
+    semaphore: Arc<Semaphore>,
+    max: usize,
+}
+/// An advertising instance acquired from an [AdvertiserLimit], which is released when dropped.
+#[derive(Debug)]
+pub struct AdvertiserPermit(OwnedSemaphorePermit);
+impl AdvertiserLimit {
+    pub fn new(max: usize) -> Self {
+        AdvertiserLimit {
+            semaphore: Arc::new(Semaphore::new(max)),
+            max,
+        }
+    }
+    pub fn max(&self) -> usize {
+        self.max
+    }
+    /// The number of instances, which are currently free.
+    pub fn available(&self) -> usize {
+        self.semaphore.available_permits()
+    }
+    /// Acquires an instance or returns [AppleBleError::TooManyAdvertisers], if all are in use.
+    pub fn try_acquire(&self) -> Result<AdvertiserPermit, AppleBleError> {
+        self.semaphore
+            .clone()
+            .try_acquire_owned()
+            .map(AdvertiserPermit)
+            .map_err(|_| AppleBleError::TooManyAdvertisers { max: self.max })
+    }
+    /// Waits until an instance is free. Waiters are served in order.
+    pub async fn acquire(&self) -> AdvertiserPermit {
+        AdvertiserPermit(
+            self.semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("The semaphore is never closed."),
+        )
+    }
+}
+
+/// Default of [AddressChangeLimit::min_interval], which leaves the bluetooth service time to come up again after a restart.
+pub const MIN_ADDRESS_CHANGE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Enforces a minimum interval between changes of the device address with `bdaddr`, e.g. by FindMy registrations.
+///
+/// Every change restarts the bluetooth service, which wedges some controllers if it happens too often, e.g. with a pathological FindMy rotation schedule.
+/// The last change is shared between clones of the session. Restoring the address is never limited.
+#[derive(Clone, Debug)]
+pub struct AddressChangeLimit {
+    min_interval: Duration,
+    last_change: Arc<Mutex<Option<Instant>>>,
+}
+impl AddressChangeLimit {
+    pub fn new(min_interval: Duration) -> Self {
+        AddressChangeLimit {
+            min_interval,
+            last_change: Arc::default(),
+        }
+    }
+    pub fn min_interval(&self) -> Duration {
+        self.min_interval
+    }
+    /// Returns [AppleBleError::RateLimited], if the last change at `now` is less than the minimum interval ago.
+    pub fn check(&self, now: Instant) -> Result<(), AppleBleError> {
+        let last_change = *self
+            .last_change
+            .lock()
+            .expect("The last change is never poisoned.");
+        match last_change {
+            Some(last_change) if now.saturating_duration_since(last_change) < self.min_interval => {
+                Err(AppleBleError::RateLimited {
+                    retry_after: self.min_interval - now.saturating_duration_since(last_change),
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Records a successful change at `now`.
+    pub fn record(&self, now: Instant) {
+        *self
+            .last_change
+            .lock()
+            .expect("The last change is never poisoned.") = Some(now);
+    }
+}
+
+/// Whether the device address was changed with `bdaddr`, e.g. by a FindMy registration, and not restored yet.
+///
+/// It's shared between clones of the session, so restoring the address through a clone, e.g. the one kept by a [FindMyRegistration](crate::advertisement::FindMyRegistration), allows changing it again.
+#[derive(Clone, Debug, Default)]
+pub struct AddressState {
+    changed: Arc<Mutex<bool>>,
+}
+impl AddressState {
+    pub fn is_changed(&self) -> bool {
+        *self
+            .changed
+            .lock()
+            .expect("The address state is never poisoned.")
+    }
+    /// Returns [AppleBleError::AddressAlreadyChanged], if the address is changed.
+    pub fn check(&self) -> Result<(), AppleBleError> {
+        match self.is_changed() {
+            true => Err(AppleBleError::AddressAlreadyChanged),
+            false => Ok(()),
+        }
+    }
+    /// Records a successful change.
+    pub fn record_change(&self) {
+        *self
+            .changed
+            .lock()
+            .expect("The address state is never poisoned.") = true;
+    }
+    /// Records that the original address was restored.
+    pub fn record_restore(&self) {
+        *self
+            .changed
+            .lock()
+            .expect("The address state is never poisoned.") = false;
+    }
+}
+
+/// A running discovery started by [Session::scan], which yields the decoded advertisements.
+///
+/// BlueZ stops discovering once the last discovery of the adapter is dropped, so other running scans keep it alive.
+pub struct Scan {
+    advertisements: Pin<Box<dyn Stream<Item = (Address, AdvertisementType)> + Send>>,
+}
+impl Scan {
+    /// Stops the discovery, which is equivalent to dropping the scan.
+    pub fn stop_discovery(self) {
+        drop(self)
+    }
+}
+impl Stream for Scan {
+    type Item = (Address, AdvertisementType);
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.advertisements.as_mut().poll_next(cx)
+    }
+}
+
+/// Counters of a [Session], which are shared between its clones, see [Session::stats].
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Stats {
+    /// Advertisements registered successfully.
+    pub registrations: usize,
+    /// Registrations, whose advertising interval was raised, see [clamp_intervals](Session::clamp_intervals).
+    pub clamped_intervals: usize,
+    /// Discoveries started by [Session::scan].
+    pub discoveries: usize,
+    /// Messages decoded successfully, by message type.
+    pub decoded: HashMap<u8, usize>,
+    /// Messages rejected by their decoder, e.g. because they're truncated, by message type.
+    pub decode_failures: HashMap<u8, usize>,
+}
+
+/// Whether the number of `active_instances` reported by BlueZ includes a live advertisement.
+///
+/// If the number couldn't be queried, the advertisement isn't considered live.
+pub fn advertising_live(active_instances: Option<u8>) -> bool {
+    active_instances.map_or(false, |active_instances| active_instances > 0)
+}
+
+/// Result of [register_on_all], keyed by the adapter names.
+#[derive(Default)]
+pub struct MultiRegistration {
+    pub handles: Vec<(String, AdvertisementHandle)>,
+    pub failures: Vec<(String, Box<dyn Error>)>,
+}
+impl MultiRegistration {
+    /// The names of the adapters, which advertise.
+    pub fn succeeded(&self) -> Vec<&str> {
+        self.handles.iter().map(|(name, _)| name.as_str()).collect()
+    }
+}
+
+/// Registers the same advertisement on every session, e.g. the ones returned by [Session::new_all].
+///
+/// A failure on one adapter doesn't prevent the registration on the others.
+pub async fn register_on_all<T: AdvertisableData, A: Advertisable<T>>(
+    sessions: &mut [Session],
+    user_data: &T,
+) -> MultiRegistration {
+    let mut registration = MultiRegistration::default();
+    for session in sessions {
+        let name = session.adapter.name().to_string();
+        match A::register(session, user_data).await {
+            Ok(handle) => registration.handles.push((name, handle)),
+            Err(err) => registration.failures.push((name, err)),
+        }
+    }
+    registration
+}
 
 /// Wrapper around the bluer [session](bluer::Session) and [adapter](bluer::Adapter)
+#[derive(Clone)]
 pub struct Session {
     pub session: bluer::Session,
     pub adapter: bluer::Adapter,
-    pub mac_changed: bool
+    /// Whether the device address was changed, it's shared between clones of the session.
+    pub address_state: AddressState,
+    /// Company id the manufacturer data is advertised under. Defaults to [APPLE_MAGIC].
+    pub company_id: u16,
+    /// Parameters applied to every advertisement, unless overridden for a single registration.
+    pub default_params: AdvertisementParams,
+    /// Raise advertising intervals, which the controller doesn't support, instead of failing the registration.
+    ///
+    /// Raised intervals are counted in the [stats](Self::stats).
+    pub clamp_intervals: bool,
+    /// How the bluetooth service is restarted after changing the device address.
+    pub restart: Restart,
+    /// Filter applied before every discovery of this session, see [configure_discovery](Self::configure_discovery).
+    ///
+    /// Defaults to [le_discovery_filter].
+    pub discovery_filter: DiscoveryFilter,
+    /// Tracks the advertising instances in use by [register_limited](crate::advertisement::Advertisable::register_limited).
+    ///
+    /// It's shared between clones of the session.
+    pub advertiser_limit: AdvertiserLimit,
+    /// Guards against changing the device address too often, it's shared between clones of the session.
+    pub address_change_limit: AddressChangeLimit,
+    stats: Arc<Mutex<Stats>>,
+}
+/// Awaits `connect`, failing with [AppleBleError::Timeout] once `timeout` elapsed.
+///
+/// Dropping `connect` cancels the pending D-Bus calls.
+pub async fn connect_timeout<T>(
+    connect: impl Future<Output = Result<T, AppleBleError>>,
+    timeout: Duration,
+) -> Result<T, AppleBleError> {
+    tokio::time::timeout(timeout, connect)
+        .await
+        .map_err(|_| AppleBleError::Timeout { timeout })?
 }
+
 impl Session {
     /// Creates a new [Session](crate::Session)
-    pub async fn new() -> bluer::Result<Self> {
+    ///
+    /// Fails with [AppleBleError::NoAdapter], if there is no Bluetooth adapter.
+    pub async fn new() -> Result<Self, AppleBleError> {
+        let session = bluer::Session::new().await?;
+        let adapter = session
+            .default_adapter()
+            .await
+            .map_err(AppleBleError::from_adapter_lookup)?;
+        Ok(Self::from_adapter(session, adapter).await?)
+    }
+    /// Like [new](Self::new), but fails with [AppleBleError::Timeout], if BlueZ doesn't respond within `timeout`, see [connect_timeout].
+    pub async fn new_with_timeout(timeout: Duration) -> Result<Self, AppleBleError> {
+        connect_timeout(Self::new(), timeout).await
+    }
+    /// Creates a new [Session](crate::Session) on the adapter named `name`, e.g. `hci1`.
+    pub async fn with_adapter(name: &str) -> bluer::Result<Self> {
+        let session = bluer::Session::new().await?;
+        let adapter = session.adapter(name)?;
+        Self::from_adapter(session, adapter).await
+    }
+    /// Creates a [Session](crate::Session) for every adapter, e.g. to advertise from all of them with [register_on_all].
+    pub async fn new_all() -> bluer::Result<Vec<Self>> {
         let session = bluer::Session::new().await?;
-        let adapter = session.default_adapter().await?;
-        Ok(Session { session, adapter, mac_changed: false })
+        let mut sessions = Vec::new();
+        for name in session.adapter_names().await? {
+            let adapter = session.adapter(&name)?;
+            sessions.push(Self::from_adapter(session.clone(), adapter).await?);
+        }
+        Ok(sessions)
+    }
+    async fn from_adapter(session: bluer::Session, adapter: bluer::Adapter) -> bluer::Result<Self> {
+        // BlueZ reports the instances, which are still free, so the ones in use have to be added.
+        let max_advertisers = adapter.active_advertising_instances().await.unwrap_or(0)
+            + adapter.supported_advertising_instances().await.unwrap_or(1);
+        Ok(Session {
+            session,
+            adapter,
+            address_state: AddressState::default(),
+            company_id: APPLE_MAGIC,
+            default_params: AdvertisementParams::default(),
+            clamp_intervals: false,
+            restart: Restart::default(),
+            discovery_filter: le_discovery_filter(),
+            advertiser_limit: AdvertiserLimit::new(max_advertisers as usize),
+            address_change_limit: AddressChangeLimit::new(MIN_ADDRESS_CHANGE_INTERVAL),
+            stats: Arc::default(),
+        })
+    }
+    /// A snapshot of the counters of this session and its clones.
+    pub fn stats(&self) -> Stats {
+        self.stats
+            .lock()
+            .expect("The stats are never poisoned.")
+            .clone()
+    }
+    fn update_stats(&self, update: impl FnOnce(&mut Stats)) {
+        update(&mut self.stats.lock().expect("The stats are never poisoned."))
+    }
+    pub(crate) fn record_registration(&self) {
+        self.update_stats(|stats| stats.registrations += 1)
+    }
+    pub(crate) fn record_clamped_interval(&self) {
+        self.update_stats(|stats| stats.clamped_intervals += 1)
+    }
+    /// Decodes manufacturer data of [company_id](Self::company_id) with the [global registry](DecoderRegistry::global), counting the result in the [stats](Self::stats).
+    pub fn decode(&self, address: Address, manufacturer_data: &[u8]) -> Option<AdvertisementType> {
+        let decoded = DecoderRegistry::global().decode(address, manufacturer_data);
+        if let Some(&message_type) = manufacturer_data.first() {
+            self.update_stats(|stats| {
+                let counters = match decoded {
+                    Some(_) => &mut stats.decoded,
+                    None => &mut stats.decode_failures,
+                };
+                *counters.entry(message_type).or_insert(0) += 1;
+            });
+        }
+        decoded
+    }
+    async fn decode_device(&self, device: &Device) -> Option<AdvertisementType> {
+        let manufacturer_data = device.manufacturer_data().await.ok()??;
+        self.decode(device.address(), manufacturer_data.get(&self.company_id)?)
+    }
+    /// Uses `name` as the alias of the adapter and as the [local name](AdvertisementParams::local_name) of every advertisement.
+    ///
+    /// Clients seeing the adapter over classic Bluetooth then see the same name as in the advertisements.
+    /// Note that BlueZ stores the alias persistently, so it outlives the session.
+    pub async fn set_advertised_identity(&mut self, name: &str) -> bluer::Result<()> {
+        self.adapter.set_alias(name.to_string()).await?;
+        self.default_params.local_name = Some(name.to_string());
+        Ok(())
+    }
+    /// Checks the environment for everything this crate relies on.
+    ///
+    /// This front-loads the diagnostics, which otherwise only surface as errors while registering.
+    pub async fn self_test(&self) -> SelfTestReport {
+        SelfTestReport {
+            adapter_present: self.adapter.is_powered().await.is_ok(),
+            adapter_powered: self.adapter.is_powered().await.unwrap_or(false),
+            advertising_supported: matches!(
+                self.adapter.supported_advertising_features().await,
+                Ok(Some(_))
+            ),
+            bdaddr_available: is_on_path("bdaddr"),
+            btmgmt_available: is_on_path("btmgmt"),
+            can_escalate: can_escalate(),
+        }
+    }
+    /// The maximum length of the advertising data supported by the controller.
+    ///
+    /// Falls back to the 31 bytes of legacy advertising, if BlueZ doesn't report it.
+    pub async fn max_advertisement_length(&self) -> usize {
+        self.adapter
+            .supported_advertising_capabilities()
+            .await
+            .ok()
+            .flatten()
+            .map_or(31, |capabilities| {
+                capabilities.max_advertisement_length as usize
+            })
     }
-}
\ No newline at end of file
+    /// The lowest and the highest transmit power in dBm the controller supports for advertising.
+    ///
+    /// Falls back to the range of the Bluetooth specification, -127dBm to 20dBm, if BlueZ doesn't report it.
+    pub async fn tx_power_range(&self) -> (i16, i16) {
+        self.adapter
+            .supported_advertising_capabilities()
+            .await
+            .ok()
+            .flatten()
+            .map_or((-127, 20), |capabilities| {
+                (capabilities.min_tx_power, capabilities.max_tx_power)
+            })
+    }
+    /// The shortest advertising interval the controller accepts for advertisements of `advertisement_type`.
+    ///
+    /// BlueZ doesn't expose the interval limits of the controller.
+    /// Controllers supporting extended advertising, which BlueZ reports as hardware offload, implement Bluetooth 5.0 or later and accept 20ms for every type.
+    /// Older controllers require at least 100ms for non-connectable advertisements.
+    pub async fn min_advertising_interval(&self, advertisement_type: Type) -> Duration {
+        let extended = self
+            .adapter
+            .supported_advertising_features()
+            .await
+            .ok()
+            .flatten()
+            .map_or(false, |features| {
+                features.contains(&PlatformFeature::HardwareOffload)
+            });
+        if extended || advertisement_type == Type::Peripheral {
+            MIN_ADVERTISING_INTERVAL
+        } else {
+            LEGACY_MIN_NONCONNECTABLE_INTERVAL
+        }
+    }
+    /// The PHYs the controller supports for extended advertising data, which is empty for legacy advertising only.
+    pub async fn supported_secondary_channels(&self) -> BTreeSet<SecondaryChannel> {
+        self.adapter
+            .supported_advertising_secondary_channels()
+            .await
+            .ok()
+            .flatten()
+            .unwrap_or_default()
+    }
+    /// Scans like [scan](Self::scan) and writes every decoded advertisement to `writer` as NDJSON, see [write_ndjson].
+    ///
+    /// This only returns once writing fails.
+    #[cfg(feature = "serde")]
+    pub async fn scan_to_writer(&self, writer: impl std::io::Write) -> Result<(), Box<dyn Error>> {
+        write_ndjson(self.scan_records().await?, writer).await?;
+        Ok(())
+    }
+    /// Scans like [scan](Self::scan) and appends every message of an unknown type to the file at `path`, see [write_unknown_messages].
+    ///
+    /// Messages already in the file aren't appended again, so repeated runs build up a corpus for reverse-engineering.
+    /// This only returns once writing fails.
+    pub async fn scan_learning(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut seen = match std::fs::File::open(&path) {
+            Ok(file) => read_unknown_messages(std::io::BufReader::new(file))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Default::default(),
+            Err(err) => return Err(err.into()),
+        };
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        write_unknown_messages(self.scan_records().await?, file, &mut seen).await?;
+        Ok(())
+    }
+    /// Scans like [scan](Self::scan) and queries the RSSI of every decoded advertisement.
+    async fn scan_records(&self) -> bluer::Result<impl Stream<Item = ScanRecord>> {
+        let adapter = self.adapter.clone();
+        Ok(self.scan().await?.then(move |(address, advertisement)| {
+            let adapter = adapter.clone();
+            async move {
+                let rssi = match adapter.device(address) {
+                    Ok(device) => device.rssi().await.ok().flatten(),
+                    Err(_) => None,
+                };
+                ScanRecord {
+                    address,
+                    advertisement,
+                    rssi,
+                    timestamp: SystemTime::now(),
+                }
+            }
+        }))
+    }
+    /// Checks whether BlueZ reports a live advertising instance for the registered advertisement, see [advertising_live].
+    ///
+    /// BlueZ only exposes the number of active instances on the adapter, not which advertisements they belong to, and the handle doesn't expose its object path either.
+    /// So this can't single out the advertisement of `handle`, it only confirms that some advertisement is transmitted, while the borrowed handle guarantees that this one wasn't unregistered.
+    /// With several registrations, an advertisement dropped by BlueZ goes unnoticed as long as another one is live.
+    pub async fn verify_advertising(&self, _handle: &AdvertisementHandle) -> bool {
+        advertising_live(self.adapter.active_advertising_instances().await.ok())
+    }
+    /// Recovers a controller, which silently stopped advertising, e.g. after changing its address with `bdaddr`.
+    ///
+    /// The adapter is powered off and on again, with `hci_reset` the controller is also reset with `hciconfig` in between, which requires privileges.
+    /// The adapter is powered on again, even if the reset failed.
+    pub async fn reset_adapter(&self, hci_reset: bool) -> Result<(), AppleBleError> {
+        self.adapter.set_powered(false).await?;
+        let reset = if hci_reset {
+            ensure_privileges().and_then(|_| {
+                SystemCommandRunner
+                    .run("hciconfig", &[self.adapter.name(), "reset"])
+                    .map_err(|err| AppleBleError::ResetFailed(err.to_string()))
+            })
+        } else {
+            Ok(())
+        };
+        self.adapter.set_powered(true).await?;
+        reset?;
+        if !self.adapter.is_powered().await? {
+            return Err(AppleBleError::ResetFailed(
+                "the adapter didn't power on again".to_string(),
+            ));
+        }
+        Ok(())
+    }
+    /// Takes on `identity` and registers `advertisement`, returning the impersonation, which reverts everything through [revert](Impersonation::revert).
+    ///
+    /// A FindMy advertisement sets the address of its key, unless `identity` has an address, like [register](Advertisable::register) does.
+    /// See [Impersonation] for how the changes are reverted.
+    pub async fn impersonate(
+        &mut self,
+        mut identity: DeviceIdentity,
+        advertisement: &AdvertisementType,
+    ) -> Result<Impersonation<Session>, Box<dyn Error>> {
+        if identity.address.is_none() {
+            identity.address = match advertisement {
+                #[cfg(feature = "findmy")]
+                AdvertisementType::FindMy(data) => Some(data.address()),
+                #[cfg(feature = "findmy")]
+                AdvertisementType::FindMyNearby(data) => Some(data.address()),
+                _ => None,
+            };
+        }
+        let mut impersonation = Impersonation::apply(self.clone(), &identity).await?;
+        // Failures revert it right away, instead of leaving it to the background revert on drop.
+        let advertisement =
+            match advertisement.to_advertisement(self, &AdvertisementParams::default()) {
+                Ok(advertisement) => identity.apply(advertisement),
+                Err(err) => {
+                    impersonation.revert().await?;
+                    return Err(err.into());
+                }
+            };
+        match self.adapter.advertise(advertisement).await {
+            Ok(handle) => impersonation.handle = Some(handle),
+            Err(err) => {
+                impersonation.revert().await?;
+                return Err(err.into());
+            }
+        }
+        self.record_registration();
+        Ok(impersonation)
+    }
+    /// Sets the filter of the following discoveries and applies it to the adapter right away.
+    pub async fn configure_discovery(&mut self, filter: DiscoveryFilter) -> bluer::Result<()> {
+        self.adapter.set_discovery_filter(filter.clone()).await?;
+        self.discovery_filter = filter;
+        Ok(())
+    }
+    async fn discover(&self) -> bluer::Result<impl Stream<Item = AdapterEvent>> {
+        self.adapter
+            .set_discovery_filter(self.discovery_filter.clone())
+            .await?;
+        self.adapter.discover_devices_with_changes().await
+    }
+    /// Discovers devices and yields the decoded advertisement of every device, which is added or changes.
+    ///
+    /// Devices without manufacturer data of [company_id](Self::company_id) are skipped.
+    /// Discovery stops once the returned [Scan] is dropped or [stopped](Scan::stop_discovery).
+    pub async fn scan(&self) -> bluer::Result<Scan> {
+        let session = self.clone();
+        let events = self.discover().await?;
+        self.update_stats(|stats| stats.discoveries += 1);
+        Ok(Scan {
+            advertisements: Box::pin(decode_events(events, move |address| {
+                let session = session.clone();
+                async move {
+                    let device = session.adapter.device(address).ok()?;
+                    session.decode_device(&device).await
+                }
+            })),
+        })
+    }
+    /// Decodes the last seen advertisement of every device known to BlueZ, without starting a discovery.
+    ///
+    /// This includes paired devices and the ones found by a previous scan, which BlueZ hasn't removed yet.
+    pub async fn decode_known_devices(&self) -> bluer::Result<Vec<(Address, AdvertisementType)>> {
+        let addresses = self.adapter.device_addresses().await?;
+        Ok(decode_addresses(addresses, |address| async move {
+            self.decode_device(&self.adapter.device(address).ok()?)
+                .await
+        })
+        .await)
+    }
+    /// Scans like [scan](Self::scan), but reports when a device switches to another message type, see [advertisement_events].
+    pub async fn scan_events(&self) -> bluer::Result<impl Stream<Item = AdvertisementEvent>> {
+        Ok(advertisement_events(self.scan().await?))
+    }
+    /// Scans the device with `address` for `duration` and counts the Continuity message types it emitted.
+    ///
+    /// A message is counted every time BlueZ reports a change of the device, e.g. of its RSSI, while it is advertised.
+    pub async fn observe(
+        &self,
+        address: Address,
+        duration: Duration,
+    ) -> bluer::Result<HashMap<u8, usize>> {
+        Ok(message_type_histogram(address, self.scan().await?, duration).await)
+    }
+    /// Follows the RSSI of a device, e.g. to locate a FindMy tag.
+    ///
+    /// The stream ends, once the device disappears.
+    pub async fn track(&self, address: Address) -> bluer::Result<impl Stream<Item = i16>> {
+        let device = self.adapter.device(address)?;
+        Ok(rssi_updates(
+            address,
+            device.events().await?,
+            self.adapter.events().await?,
+        ))
+    }
+    /// Decodes the advertisement of a device and every change of it afterwards.
+    ///
+    /// The current advertisement is yielded first, if the device has one.
+    pub async fn watch_device(
+        &self,
+        address: Address,
+    ) -> bluer::Result<impl Stream<Item = AdvertisementType>> {
+        let device = self.adapter.device(address)?;
+        let current = self.decode_device(&device).await;
+        Ok(
+            futures::stream::iter(current).chain(manufacturer_data_updates(
+                address,
+                self.company_id,
+                device.events().await?,
+            )),
+        )
+    }
+    /// Scans for the FindMy beacon with `public_key`, returning its address and RSSI once it appears.
+    ///
+    /// FindMy keys rotate, so the beacon is only found while it's still advertising this key.
+    #[cfg(feature = "findmy")]
+    pub async fn wait_for_findmy(
+        &self,
+        public_key: [u8; 28],
+        timeout: Duration,
+    ) -> bluer::Result<Option<(Address, i16)>> {
+        let address = FindMyAdvertisementData::new(public_key).address();
+        let events = self.discover().await?;
+        Ok(wait_for_device(
+            address,
+            events,
+            |address| async move { self.adapter.device(address).ok()?.rssi().await.ok()? },
+            timeout,
+        )
+        .await)
+    }
+    /// Reads back the manufacturer data advertised by this adapter, as `other` receives it over the air.
+    ///
+    /// BlueZ doesn't expose the data of registered advertisements, neither through `LEAdvertisingManager1` nor the management interface, so another adapter has to receive it.
+    /// Unlike [loopback_verify](Self::loopback_verify), nothing is matched, so this returns the data actually advertised.
+    /// The advertisement is recognized by the address of this adapter, which fails while it advertises from a random address, e.g. with privacy enabled.
+    pub async fn read_back_manufacturer_data(
+        &self,
+        other: &Session,
+        timeout: Duration,
+    ) -> Result<HashMap<u16, Vec<u8>>, AppleBleError> {
+        let address = self.adapter.address().await?;
+        // Forget what `other` received earlier, so stale data isn't returned.
+        let _ = other.adapter.remove_device(address).await;
+        let events = other.discover().await?;
+        let received = events.filter_map(|event| async move {
+            match event {
+                AdapterEvent::DeviceAdded(added) if added == address => other
+                    .adapter
+                    .device(added)
+                    .ok()?
+                    .manufacturer_data()
+                    .await
+                    .ok()?,
+                _ => None,
+            }
+        });
+        futures::pin_mut!(received);
+        tokio::time::timeout(timeout, received.next())
+            .await
+            .ok()
+            .flatten()
+            .ok_or(AppleBleError::NotReceived { timeout })
+    }
+    /// Registers the advertisement on this session and waits until `other` receives it.
+    ///
+    /// The advertisement is recognized by its manufacturer data, so the address it is advertised from doesn't matter.
+    /// It is unregistered again before returning.
+    pub async fn loopback_verify<T: AdvertisableData, A: Advertisable<T>>(
+        &mut self,
+        other: &Session,
+        user_data: &T,
+        timeout: Duration,
+    ) -> Result<AdvertisementType, AppleBleError> {
+        let company_id = self.company_id;
+        let octets = user_data.octets();
+        let _handle = A::register(self, user_data).await.map_err(|err| {
+            err.downcast_ref::<AppleBleError>()
+                .cloned()
+                .unwrap_or_else(|| AppleBleError::Bluetooth(err.to_string()))
+        })?;
+        let events = other.discover().await?;
+        let received = events.filter_map(|event| {
+            let octets = &octets;
+            async move {
+                match event {
+                    AdapterEvent::DeviceAdded(address) => {
+                        let device = other.adapter.device(address).ok()?;
+                        let manufacturer_data = device.manufacturer_data().await.ok()??;
+                        let data = manufacturer_data.get(&company_id)?;
+                        (data == octets).then(|| decode_apple(address, data))?
+                    }
+                    _ => None,
+                }
+            }
+        });
+        futures::pin_mut!(received);
+        tokio::time::timeout(timeout, received.next())
+            .await
+            .ok()
+            .flatten()
+            .ok_or(AppleBleError::NotReceived { timeout })
+    }
+}
@@ -1,23 +1,359 @@
 use std::error::Error;
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
 
+#[cfg(feature = "airdrop")]
 use sha2::{Digest, Sha256};
 
+use crate::error::AppleBleError;
 use crate::session::Session;
+
+/// Bit of CAP_NET_ADMIN in the capability sets, see capability.h.
+const CAP_NET_ADMIN: u32 = 12;
+
+#[cfg(feature = "airdrop")]
 pub fn get_first_two_bytes_of_sha256(input: impl AsRef<[u8]>) -> [u8; 2] {
     let mut hasher = Sha256::new();
     hasher.update(input);
     let result = hasher.finalize();
     [result[0], result[1]]
 }
+/// Hashes an AirDrop contact identifier exactly as supplied.
+#[cfg(feature = "airdrop")]
+pub fn hash_raw(input: &str) -> [u8; 2] {
+    get_first_two_bytes_of_sha256(input)
+}
+/// Hashes an AirDrop contact identifier after normalizing it.
+///
+/// Email addresses are trimmed and lowercased, phone numbers are reduced to their digits, so `+1 (555) 010-0199` becomes `15550100199`.
+/// Phone numbers have to include their country code, which isn't added.
+/// See [ContactHashing](crate::advertisement::ContactHashing) for how this relates to the hashes of Apple devices.
+#[cfg(feature = "airdrop")]
+pub fn hash_normalized(input: &str) -> [u8; 2] {
+    let normalized: String = if input.contains('@') {
+        input.trim().to_lowercase()
+    } else {
+        input.chars().filter(char::is_ascii_digit).collect()
+    };
+    get_first_two_bytes_of_sha256(normalized)
+}
+pub fn to_hex_string(input: impl AsRef<[u8]>) -> String {
+    input
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+/// Parses a two byte hash prefix, like the ones of AirDrop, from four hex digits.
+#[cfg(feature = "airdrop")]
+pub fn parse_hex_prefix(input: &str) -> Result<[u8; 2], AppleBleError> {
+    let invalid = || AppleBleError::InvalidUserData(format!("{input:?} isn't four hex digits."));
+    if input.len() != 4 || !input.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let byte = |range| u8::from_str_radix(&input[range], 16).map_err(|_| invalid());
+    Ok([byte(0..2)?, byte(2..4)?])
+}
+/// The host name of the machine, as reported by the kernel.
+#[cfg(feature = "airplay")]
+pub fn host_name() -> Option<String> {
+    let host_name = std::fs::read_to_string("/proc/sys/kernel/hostname").ok()?;
+    Some(host_name.trim().to_string()).filter(|host_name| !host_name.is_empty())
+}
+/// Checks whether CAP_NET_ADMIN is in the effective capability set of this process, which is always the case for root.
+pub fn has_cap_net_admin() -> bool {
+    std::fs::read_to_string("/proc/self/status")
+        .ok()
+        .and_then(|status| {
+            status
+                .lines()
+                .find_map(|line| line.strip_prefix("CapEff:"))
+                .and_then(|caps| u64::from_str_radix(caps.trim(), 16).ok())
+        })
+        .map_or(false, |caps| caps & (1 << CAP_NET_ADMIN) != 0)
+}
+/// Checks whether sudo can be used without a password prompt.
+fn has_passwordless_sudo() -> bool {
+    std::process::Command::new("sudo")
+        .args(["-n", "true"])
+        .status()
+        .map_or(false, |status| status.success())
+}
+/// What the process can do to reconfigure the controller, see [ensure](Self::ensure).
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Privileges {
+    /// CAP_NET_ADMIN is in the effective capability set, which is always the case for root.
+    pub cap_net_admin: bool,
+    /// Standard input is a terminal, which sudo can prompt for the password on.
+    pub terminal: bool,
+    /// sudo doesn't require a password.
+    pub passwordless_sudo: bool,
+}
+impl Privileges {
+    /// The privileges of this process.
+    ///
+    /// Whether sudo requires a password is only checked, by running `sudo -n true`, if the process is neither privileged nor attached to a terminal.
+    pub fn current() -> Self {
+        let cap_net_admin = has_cap_net_admin();
+        let terminal = std::io::stdin().is_terminal();
+        Privileges {
+            cap_net_admin,
+            terminal,
+            passwordless_sudo: !cap_net_admin && !terminal && has_passwordless_sudo(),
+        }
+    }
+    /// Whether the process is privileged or can escalate its privileges without blocking.
+    pub fn can_escalate(&self) -> bool {
+        self.cap_net_admin || self.terminal || self.passwordless_sudo
+    }
+    /// Makes sure the process is privileged enough to reconfigure the controller, escalating with sudo if required.
+    ///
+    /// Without a terminal to prompt for a password on, escalation is only attempted if sudo doesn't require one.
+    /// Otherwise this fails with [AppleBleError::PermissionDenied] instead of blocking.
+    pub fn ensure(&self) -> Result<(), AppleBleError> {
+        if self.cap_net_admin {
+            return Ok(());
+        }
+        if !self.terminal && !self.passwordless_sudo {
+            return Err(AppleBleError::PermissionDenied(
+                "no terminal to prompt for the sudo password".to_string(),
+            ));
+        }
+        sudo::escalate_if_needed()
+            .map_err(|err| AppleBleError::PermissionDenied(err.to_string()))?;
+        Ok(())
+    }
+}
+/// Checks whether the process is privileged or can escalate its privileges without blocking, see [Privileges::can_escalate].
+pub fn can_escalate() -> bool {
+    Privileges::current().can_escalate()
+}
+/// Checks whether `program` is an executable file in one of the directories in PATH.
+pub fn is_on_path(program: &str) -> bool {
+    std::env::var_os("PATH").map_or(false, |path| {
+        std::env::split_paths(&path).any(|directory| directory.join(program).is_file())
+    })
+}
+/// Makes sure the process is privileged enough to reconfigure the controller, see [Privileges::ensure].
+pub fn ensure_privileges() -> Result<(), AppleBleError> {
+    Privileges::current().ensure()
+}
+/// Parses a device address, which has to be exactly six bytes long.
+pub fn parse_device_addr(device_addr: &[u8]) -> Result<eui48::MacAddress, AppleBleError> {
+    if device_addr.len() != 6 {
+        return Err(AppleBleError::InvalidAddress {
+            len: device_addr.len(),
+        });
+    }
+    Ok(eui48::MacAddress::from_bytes(device_addr).expect("The length was checked."))
+}
+/// Changes the device address with `bdaddr` and restarts the bluetooth service.
+///
+/// Fails with [AppleBleError::RateLimited], if the address was changed less than [AddressChangeLimit::min_interval](crate::session::AddressChangeLimit::min_interval) ago.
+/// Fails with [AppleBleError::AddressAlreadyChanged], if it wasn't [restored](restore_device_addr) since the last change.
 pub fn set_device_addr(session: &mut Session, device_addr: &[u8]) -> Result<(), Box<dyn Error>> {
-    assert!(!session.mac_changed, "Can only change mac once.");
-    session.mac_changed = true;
-    sudo::escalate_if_needed()?;
-    std::process::Command::new("bdaddr").args([
-        "-i",
-        session.adapter.name(),
-        &eui48::MacAddress::from_bytes(device_addr)?.to_hex_string(),
-    ]);
-    systemctl::restart("bluetooth.service")?;
+    parse_device_addr(device_addr)?;
+    session.address_state.check()?;
+    session.address_change_limit.check(Instant::now())?;
+    write_device_addr(session, device_addr)?;
+    session.address_state.record_change();
+    session.address_change_limit.record(Instant::now());
+    Ok(())
+}
+/// Restores the device address after [set_device_addr], allowing it to be changed again.
+pub fn restore_device_addr(
+    session: &mut Session,
+    device_addr: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    write_device_addr(session, device_addr)?;
+    session.address_state.record_restore();
+    Ok(())
+}
+fn write_device_addr(session: &Session, device_addr: &[u8]) -> Result<(), Box<dyn Error>> {
+    ensure_privileges()?;
+    run_bdaddr(&SystemCommandRunner, session, device_addr)?;
+    session.restart.restart()
+}
+fn run_bdaddr(
+    runner: &dyn CommandRunner,
+    session: &Session,
+    device_addr: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    runner.run(
+        "bdaddr",
+        &[
+            "-i",
+            session.adapter.name(),
+            &parse_device_addr(device_addr)?.to_hex_string(),
+        ],
+    )
+}
+
+/// A step of [set_device_addr_with_progress], reported before it starts.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AddressProgress {
+    Escalating,
+    SettingAddress,
+    RestartingBluetooth,
+    Done,
+}
+
+/// Runs the privileged steps of changing the device address, so they can be replaced, e.g. in tests.
+pub trait CommandRunner {
+    /// Makes sure the following commands are privileged, see [Privileges::ensure].
+    fn escalate(&self) -> Result<(), Box<dyn Error>> {
+        Ok(ensure_privileges()?)
+    }
+    /// Runs `program` with `args`, failing unless it exits successfully.
+    fn run(&self, program: &str, args: &[&str]) -> Result<(), Box<dyn Error>>;
+}
+/// Runs the commands as child processes.
+pub struct SystemCommandRunner;
+impl CommandRunner for SystemCommandRunner {
+    fn run(&self, program: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
+        let status = std::process::Command::new(program).args(args).status()?;
+        if !status.success() {
+            return Err(format!("{program} failed with {status}").into());
+        }
+        Ok(())
+    }
+}
+
+/// Changes the device address with `bdaddr` like FindMy registrations do, but reports every step to `progress` and stops once `cancel` is set.
+///
+/// If it's cancelled after the address was written, the previous address is written again before returning [AppleBleError::Cancelled], so the adapter keeps a consistent address.
+/// The bluetooth service is restarted with [Session::restart] and the commands still block the executing thread while they run.
+pub async fn set_device_addr_with_progress(
+    session: &mut Session,
+    device_addr: &[u8],
+    runner: &dyn CommandRunner,
+    mut progress: impl FnMut(AddressProgress),
+    cancel: &AtomicBool,
+) -> Result<(), Box<dyn Error>> {
+    parse_device_addr(device_addr)?;
+    session.address_state.check()?;
+    session.address_change_limit.check(Instant::now())?;
+    let cancelled = || cancel.load(Ordering::SeqCst);
+
+    progress(AddressProgress::Escalating);
+    runner.escalate()?;
+    if cancelled() {
+        return Err(AppleBleError::Cancelled.into());
+    }
+    let original_address = session.adapter.address().await?;
+    progress(AddressProgress::SettingAddress);
+    run_bdaddr(runner, session, device_addr)?;
+    if cancelled() {
+        run_bdaddr(runner, session, &original_address.0)?;
+        return Err(AppleBleError::Cancelled.into());
+    }
+    progress(AddressProgress::RestartingBluetooth);
+    session.restart.restart()?;
+    session.address_state.record_change();
+    session.address_change_limit.record(Instant::now());
+    progress(AddressProgress::Done);
+    Ok(())
+}
+
+/// How the bluetooth service is restarted after changing the device address, see [Session::restart].
+#[derive(Clone, Copy, Debug, Default)]
+pub enum Restart {
+    /// Restart `bluetooth.service` through systemd, which disrupts every other user of the adapter.
+    #[default]
+    Systemd,
+    /// Don't restart anything, for controllers applying the address immediately.
+    None,
+    /// Run a custom restart, e.g. `rc-service bluetooth restart` on OpenRC.
+    Custom(fn() -> Result<(), Box<dyn Error>>),
+}
+impl Restart {
+    pub fn restart(&self) -> Result<(), Box<dyn Error>> {
+        match self {
+            Restart::Systemd => {
+                systemctl::restart("bluetooth.service")?;
+                Ok(())
+            }
+            Restart::None => Ok(()),
+            Restart::Custom(restart_fn) => restart_fn(),
+        }
+    }
+}
+
+/// Programs the controller's random static address through the BlueZ management interface.
+///
+/// BlueZ only uses the static address when BR/EDR is disabled, so BR/EDR is switched off while the adapter is powered down.
+/// It stays off afterwards, until it's switched back on with [restore_bredr].
+/// Unlike [set_device_addr], this needs neither `bdaddr` nor a restart of the bluetooth service, but `btmgmt` still blocks the executing thread while it runs.
+#[cfg(feature = "findmy")]
+pub fn set_static_random_addr(session: &Session, device_addr: &[u8]) -> Result<(), Box<dyn Error>> {
+    let address = parse_device_addr(device_addr)?.to_hex_string();
+    ensure_privileges()?;
+    run_btmgmt(
+        &SystemCommandRunner,
+        session,
+        &[
+            &["power", "off"],
+            &["bredr", "off"],
+            &["static-addr", &address],
+            &["power", "on"],
+        ],
+    )
+}
+/// Runs [set_static_random_addr] on a thread for blocking work, see [run_blocking].
+#[cfg(feature = "findmy")]
+pub(crate) async fn spawn_set_static_random_addr(
+    session: &Session,
+    device_addr: [u8; 6],
+) -> Result<(), Box<dyn Error>> {
+    let session = session.clone();
+    run_blocking(move || set_static_random_addr(&session, &device_addr)).await
+}
+/// Runs a blocking reconfiguration of the adapter, like changing its address, on a thread for blocking work, so it doesn't stall the async runtime.
+///
+/// Errors of this crate are passed on as they are, others only keep their message.
+#[cfg(feature = "findmy")]
+pub(crate) async fn run_blocking<R: Send + 'static>(
+    f: impl FnOnce() -> Result<R, Box<dyn Error>> + Send + 'static,
+) -> Result<R, Box<dyn Error>> {
+    tokio::task::spawn_blocking(move || {
+        f().map_err(|err| -> Box<dyn Error + Send + Sync> {
+            match err.downcast::<AppleBleError>() {
+                Ok(err) => err,
+                Err(err) => err.to_string().into(),
+            }
+        })
+    })
+    .await?
+    .map_err(|err| err as Box<dyn Error>)
+}
+/// Switches BR/EDR back on after [set_static_random_addr], so the adapter advertises on its public address again.
+#[cfg(feature = "findmy")]
+pub fn restore_bredr(session: &Session) -> Result<(), Box<dyn Error>> {
+    ensure_privileges()?;
+    run_btmgmt(
+        &SystemCommandRunner,
+        session,
+        &[&["power", "off"], &["bredr", "on"], &["power", "on"]],
+    )
+}
+#[cfg(feature = "findmy")]
+fn run_btmgmt(
+    runner: &dyn CommandRunner,
+    session: &Session,
+    commands: &[&[&str]],
+) -> Result<(), Box<dyn Error>> {
+    let index = session
+        .adapter
+        .name()
+        .strip_prefix("hci")
+        .ok_or("Adapter name doesn't follow the hciX scheme.")?;
+    for command in commands {
+        let args: Vec<&str> = ["--index", index]
+            .into_iter()
+            .chain(command.iter().copied())
+            .collect();
+        runner.run("btmgmt", &args)?;
+    }
     Ok(())
 }
@@ -1,107 +1,2234 @@
-use apple_ble::advertisement::{Advertisable, AirDropAdvertisementData, AdvertisableData, AirPlayTargetAdvertisementData, AirPrintAdvertisementData, FindMyAdvertisementData};
+#[cfg(feature = "airplay")]
+use apple_ble::advertisement::AirPlayTargetAdvertisementData;
+#[cfg(feature = "findmy")]
+use apple_ble::advertisement::FindMyAdvertisementData;
+use apple_ble::advertisement::{Advertisable, AdvertisableData};
+#[cfg(feature = "airdrop")]
+use apple_ble::advertisement::{AirDropAdvertisementData, AirDropMode};
+#[cfg(feature = "airprint")]
+use apple_ble::advertisement::{
+    AirPrintAdvertisementData, AirPrintSecurity, AIRPRINT_ADDRESS_TYPE, AIRPRINT_RESOURCE_PATH,
+};
+use apple_ble::session::Session;
 use bluer::Address;
-use std::{error::Error, net::{Ipv4Addr, Ipv6Addr}};
+#[cfg(feature = "findmy")]
+use bluer::AddressType;
+#[cfg(feature = "airplay")]
+use std::net::Ipv4Addr;
+#[cfg(feature = "airprint")]
+use std::net::Ipv6Addr;
+#[cfg(feature = "airdrop")]
+use std::sync::{
+    atomic::{AtomicU8, Ordering},
+    Arc,
+};
+use std::{error::Error, time::Duration};
 use tokio::test;
 
+#[cfg(feature = "airdrop")]
 #[test(flavor = "multi_thread", worker_threads = 1)]
 async fn test_airdrop_advertisement() -> Result<(), Box<dyn Error>> {
     let mut session = apple_ble::session::Session::new().await?;
     apple_ble::advertisement::AirDropAdvertisement::register(
         &mut session,
-        &apple_ble::advertisement::AirDropAdvertisementData{
+        &apple_ble::advertisement::AirDropAdvertisementData {
             apple_id: [0x00, 0x00],
             phone: [0x00, 0x00],
-            email : [0x00, 0x00]
+            email: [0x00, 0x00],
+            email2: None,
+            mode: AirDropMode::ContactsOnly,
         },
     )
     .await?;
     Ok(())
 }
 
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_dynamic_advertisement() -> Result<(), Box<dyn Error>> {
+    let mut session = apple_ble::session::Session::new().await?;
+    let counter = Arc::new(AtomicU8::new(0));
+    let update_counter = counter.clone();
+    let handle = apple_ble::advertisement::AirDropAdvertisement::register_dynamic(
+        &mut session,
+        &apple_ble::advertisement::AirDropAdvertisementData {
+            apple_id: [0x00, 0x00],
+            phone: [0x00, 0x00],
+            email: [0x00, 0x00],
+            email2: None,
+            mode: AirDropMode::ContactsOnly,
+        },
+        move || {
+            let count = update_counter.fetch_add(1, Ordering::SeqCst) + 1;
+            apple_ble::advertisement::AirDropAdvertisementData {
+                apple_id: [count, count],
+                phone: [0x00, 0x00],
+                email: [0x00, 0x00],
+                email2: None,
+                mode: AirDropMode::ContactsOnly,
+            }
+        },
+        Duration::from_millis(200),
+    )
+    .await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(counter.load(Ordering::SeqCst) >= 2);
+    assert!(!handle.is_finished());
+    Ok(())
+}
+
+#[cfg(feature = "nearby")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_dynamic_advertisement_reports_invalid_update() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{NearbyInfoAdvertisement, NearbyInfoAdvertisementData};
+    use apple_ble::error::{AppleBleError, Operation};
+
+    let mut session = Session::new().await?;
+    let data = NearbyInfoAdvertisementData {
+        status_flags: 0x01,
+        action_code: 0x07,
+        data_flags: 0x00,
+        auth_tag: None,
+        action_data: vec![],
+    };
+    let update = NearbyInfoAdvertisementData {
+        status_flags: 0x10,
+        ..data.clone()
+    };
+    let handle = NearbyInfoAdvertisement::register_dynamic(
+        &mut session,
+        &data,
+        move || update.clone(),
+        Duration::from_millis(100),
+    )
+    .await?;
+    // The update is validated like the initial data, so the updater stops on it.
+    match tokio::time::timeout(Duration::from_secs(5), handle.error()).await? {
+        AppleBleError::Registration {
+            advertisement,
+            operation,
+            ..
+        } => {
+            assert_eq!(advertisement, "NearbyInfo");
+            assert_eq!(operation, Operation::Assemble);
+        }
+        err => panic!("Unexpected error {err:?}."),
+    }
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_custom_company_id() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{decode_apple, AdvertisementType, TEST_COMPANY_ID};
+
+    let mut session = apple_ble::session::Session::new().await?;
+    session.company_id = TEST_COMPANY_ID;
+    let data = AirDropAdvertisementData {
+        apple_id: [0xfe, 0xdc],
+        phone: [0x76, 0x54],
+        email: [0xba, 0x98],
+        email2: None,
+        mode: AirDropMode::ContactsOnly,
+    };
+    let advertisement = apple_ble::advertisement::AirDropAdvertisement::assemble_advertisement(
+        &mut session,
+        &data,
+    )?;
+    assert_eq!(
+        decode_apple(
+            Address::any(),
+            &advertisement.manufacturer_data[&TEST_COMPANY_ID]
+        ),
+        Some(AdvertisementType::AirDrop(data.clone()))
+    );
+    apple_ble::advertisement::AirDropAdvertisement::register(&mut session, &data).await?;
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
 #[test(flavor = "multi_thread", worker_threads = 1)]
 async fn test_airplaysource_advertisement() -> Result<(), Box<dyn Error>> {
     let mut session = apple_ble::session::Session::new().await?;
     apple_ble::advertisement::AirPlaySourceAdvertisement::register(
         &mut session,
-        &apple_ble::advertisement::AirPlaySourceAdvertisementData {},
+        &apple_ble::advertisement::AirPlaySourceAdvertisementData::default(),
     )
     .await?;
     Ok(())
 }
 
+#[cfg(feature = "airplay")]
 #[test(flavor = "multi_thread", worker_threads = 1)]
 async fn test_airplaytarget_advertisement() -> Result<(), Box<dyn Error>> {
     let mut session = apple_ble::session::Session::new().await?;
     apple_ble::advertisement::AirPlayTargetAdvertisement::register(
         &mut session,
-        &apple_ble::advertisement::AirPlayTargetAdvertisementData {
-            ip_address: Ipv4Addr::LOCALHOST
-        },
+        &apple_ble::advertisement::AirPlayTargetAdvertisementData::new(Ipv4Addr::LOCALHOST),
     )
     .await?;
     Ok(())
 }
 
+#[cfg(all(feature = "airplay", feature = "gatt"))]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_airplaytarget_connectable_advertisement() -> Result<(), Box<dyn Error>> {
+    let mut session = apple_ble::session::Session::new().await?;
+    apple_ble::advertisement::AirPlayTargetAdvertisement::register_connectable(
+        &mut session,
+        &apple_ble::advertisement::AirPlayTargetAdvertisementData::new(Ipv4Addr::LOCALHOST),
+        "AppleTV6,2",
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(feature = "airprint")]
 #[test(flavor = "multi_thread", worker_threads = 1)]
 async fn test_airprint_advertisement() -> Result<(), Box<dyn Error>> {
     let mut session = apple_ble::session::Session::new().await?;
     apple_ble::advertisement::AirPrintAdvertisement::register(
         &mut session,
         &apple_ble::advertisement::AirPrintAdvertisementData {
+            address_type: AIRPRINT_ADDRESS_TYPE,
+            resource_path: AIRPRINT_RESOURCE_PATH,
+            security: AirPrintSecurity::Ipps,
             port: 0x1337,
             ip_addr: Ipv6Addr::LOCALHOST,
-            power: 100
+            power: 100,
         },
     )
     .await?;
     Ok(())
 }
 
+#[cfg(feature = "findmy")]
 #[test(flavor = "multi_thread", worker_threads = 1)]
 async fn test_findmy_advertisement() -> Result<(), Box<dyn Error>> {
     let mut session = apple_ble::session::Session::new().await?;
     apple_ble::advertisement::FindMyAdvertisement::register(
         &mut session,
-        &apple_ble::advertisement::FindMyAdvertisementData {
-            public_key: [0x88; 28]
-        },
+        &apple_ble::advertisement::FindMyAdvertisementData::new([0x88; 28]),
+    )
+    .await?;
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_findmy_random_advertisement() -> Result<(), Box<dyn Error>> {
+    // Programming the static address requires root.
+    if sudo::check() != sudo::RunningAs::Root {
+        return Ok(());
+    }
+    let mut session = apple_ble::session::Session::new().await?;
+    let data = apple_ble::advertisement::FindMyAdvertisementData::new([0x1e; 28]);
+    apple_ble::advertisement::FindMyAdvertisement::register_random(&mut session, &data).await?;
+    assert_eq!(session.adapter.address().await?, data.address());
+    assert_eq!(session.adapter.address_type().await?, AddressType::LeRandom);
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_findmy_restorable_advertisement() -> Result<(), Box<dyn Error>> {
+    // Changing the device address requires root.
+    if sudo::check() != sudo::RunningAs::Root {
+        return Ok(());
+    }
+    let mut session = apple_ble::session::Session::new().await?;
+    let registration = apple_ble::advertisement::FindMyAdvertisement::register_restorable(
+        &mut session,
+        &FindMyAdvertisementData::new([0x1e; 28]),
     )
     .await?;
+    let original_address = registration.original_address();
+    registration.stop().await?;
+    assert_eq!(session.adapter.address().await?, original_address);
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_findmy_restorable_advertisement_registers_again() -> Result<(), Box<dyn Error>> {
+    // Changing the device address requires root.
+    if sudo::check() != sudo::RunningAs::Root {
+        return Ok(());
+    }
+    let mut session = apple_ble::session::Session::new().await?;
+    session.address_change_limit =
+        apple_ble::session::AddressChangeLimit::new(std::time::Duration::ZERO);
+    let data = FindMyAdvertisementData::new([0x1e; 28]);
+    let registration =
+        apple_ble::advertisement::FindMyAdvertisement::register_restorable(&mut session, &data)
+            .await?;
+    assert!(session.address_state.is_changed());
+    // Stopping restores the address through the registration's clone of the session.
+    registration.stop().await?;
+    assert!(!session.address_state.is_changed());
+    let registration =
+        apple_ble::advertisement::FindMyAdvertisement::register_restorable(&mut session, &data)
+            .await?;
+    let original_address = registration.original_address();
+    registration.stop().await?;
+    assert_eq!(session.adapter.address().await?, original_address);
     Ok(())
 }
 
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_findmy_round_robin_advertisement() -> Result<(), Box<dyn Error>> {
+    // Programming the static address requires root.
+    if sudo::check() != sudo::RunningAs::Root {
+        return Ok(());
+    }
+    let mut session = apple_ble::session::Session::new().await?;
+    let keys = vec![[0x1e; 28], [0x2f; 28]];
+    let _handle = apple_ble::advertisement::FindMyAdvertisement::register_round_robin(
+        &mut session,
+        keys.clone(),
+        Duration::from_secs(2),
+    )
+    .await?;
+    // Sample in the middle of each dwell period.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    for public_key in keys {
+        assert_eq!(
+            session.adapter.address().await?,
+            FindMyAdvertisementData::new(public_key).address()
+        );
+        tokio::time::sleep(Duration::from_secs(2)).await;
+    }
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy"
+))]
 #[test(flavor = "multi_thread", worker_threads = 1)]
 async fn test_serialization_and_deserialization() -> Result<(), Box<dyn Error>> {
     let data = AirDropAdvertisementData {
         apple_id: [0xfe, 0xdc],
         email: [0xba, 0x98],
-        phone: [0x76, 0x54]
+        email2: None,
+        mode: AirDropMode::ContactsOnly,
+        phone: [0x76, 0x54],
     };
     let serialized = data.clone().octets();
     let deserialized = AirDropAdvertisementData::try_from(serialized)?;
     assert_eq!(data, deserialized);
 
-    let data = AirPlayTargetAdvertisementData {
-        ip_address: Ipv4Addr::LOCALHOST
-    };
+    let data = AirPlayTargetAdvertisementData::new(Ipv4Addr::LOCALHOST);
     let serialized = data.clone().octets();
     let deserialized = AirPlayTargetAdvertisementData::try_from(serialized)?;
     assert_eq!(data, deserialized);
 
     let data = AirPrintAdvertisementData {
+        address_type: AIRPRINT_ADDRESS_TYPE,
+        resource_path: AIRPRINT_RESOURCE_PATH,
+        security: AirPrintSecurity::Ipps,
         port: 0xf00d,
         ip_addr: Ipv6Addr::LOCALHOST,
-        power: 0xff
+        power: 0xff,
     };
     let serialized = data.clone().octets();
     let deserialized = AirPrintAdvertisementData::try_from(serialized)?;
     assert_eq!(data, deserialized);
 
-    let data = FindMyAdvertisementData {
-        public_key: [0x00_u8; 28]
-    };
+    let data = FindMyAdvertisementData::new([0x00_u8; 28]);
     let serialized = data.clone().octets();
-    let deserialized = FindMyAdvertisementData::try_from((Address::new(data.public_key[0..6].try_into()?), serialized))?;
+    let deserialized = FindMyAdvertisementData::try_from((
+        Address::new(data.public_key[0..6].try_into()?),
+        serialized,
+    ))?;
     assert_eq!(data, deserialized);
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(all(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy"
+))]
+fn assert_send<T: Send>(_: &T) {}
+
+// Never called, this only checks at compile time that the register futures are Send.
+#[cfg(all(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy"
+))]
+#[allow(dead_code)]
+fn register_futures_are_send(session: &mut Session) {
+    assert_send(&apple_ble::advertisement::AirDropAdvertisement::register(
+        session,
+        &AirDropAdvertisementData {
+            apple_id: [0x00, 0x00],
+            phone: [0x00, 0x00],
+            email: [0x00, 0x00],
+            email2: None,
+            mode: AirDropMode::ContactsOnly,
+        },
+    ));
+    assert_send(
+        &apple_ble::advertisement::AirPlaySourceAdvertisement::register(
+            session,
+            &apple_ble::advertisement::AirPlaySourceAdvertisementData::default(),
+        ),
+    );
+    assert_send(
+        &apple_ble::advertisement::AirPlayTargetAdvertisement::register(
+            session,
+            &AirPlayTargetAdvertisementData::new(Ipv4Addr::LOCALHOST),
+        ),
+    );
+    assert_send(&apple_ble::advertisement::AirPrintAdvertisement::register(
+        session,
+        &AirPrintAdvertisementData {
+            address_type: AIRPRINT_ADDRESS_TYPE,
+            resource_path: AIRPRINT_RESOURCE_PATH,
+            security: AirPrintSecurity::Ipps,
+            port: 0x1337,
+            ip_addr: Ipv6Addr::LOCALHOST,
+            power: 100,
+        },
+    ));
+    assert_send(&apple_ble::advertisement::FindMyAdvertisement::register(
+        session,
+        &FindMyAdvertisementData::new([0x88; 28]),
+    ));
+}
+
+#[cfg(all(feature = "airdrop", feature = "findmy", feature = "serde"))]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_json_schema() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AdvertisementType, JSON_SCHEMA_VERSION};
+
+    let json = AdvertisementType::AirDrop(AirDropAdvertisementData {
+        apple_id: [0xfe, 0xdc],
+        phone: [0x76, 0x54],
+        email: [0xba, 0x98],
+        email2: None,
+        mode: AirDropMode::ContactsOnly,
+    })
+    .to_json();
+    assert_eq!(
+        json,
+        serde_json::json!({
+            "type": "AirDrop",
+            "version": JSON_SCHEMA_VERSION,
+            "apple_id": "fedc",
+            "phone": "7654",
+            "email": "ba98",
+            "email2": null,
+            "mode": "ContactsOnly"
+        })
+    );
+
+    let json = AdvertisementType::FindMy(FindMyAdvertisementData::new([0x01; 28])).to_json();
+    assert_eq!(json["type"], "FindMy");
+    assert_eq!(json["public_key"], "01".repeat(28));
+    assert_eq!(json["address"], "C1:01:01:01:01:01");
+    Ok(())
+}
+
+#[cfg(feature = "nearby")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_nearby_info_versions() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{ContinuityVersion, NearbyInfoAdvertisementData};
+
+    let data = NearbyInfoAdvertisementData {
+        status_flags: 0x01,
+        action_code: 0x07,
+        data_flags: 0x1c,
+        auth_tag: Some([0xaa, 0xbb, 0xcc]),
+        action_data: vec![],
+    };
+    let serialized = data.octets();
+    assert_eq!(serialized, vec![0x10, 0x05, 0x17, 0x1c, 0xaa, 0xbb, 0xcc]);
+    assert_eq!(
+        NearbyInfoAdvertisementData::try_from(serialized.clone())?,
+        data
+    );
+    assert_eq!(data.version(), ContinuityVersion::Ios13);
+    assert!(NearbyInfoAdvertisementData::try_from((serialized, ContinuityVersion::Ios12)).is_err());
+
+    let data = NearbyInfoAdvertisementData {
+        auth_tag: None,
+        ..data
+    };
+    let serialized = data.octets();
+    assert_eq!(serialized, vec![0x10, 0x02, 0x17, 0x1c]);
+    assert_eq!(
+        NearbyInfoAdvertisementData::try_from(serialized.clone())?,
+        data
+    );
+    assert_eq!(
+        NearbyInfoAdvertisementData::try_from((serialized.clone(), ContinuityVersion::Ios12))?,
+        data
+    );
+    // The latest layout expects an authentication tag.
+    assert!(NearbyInfoAdvertisementData::try_from((serialized, ContinuityVersion::Ios13)).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "nearby")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_nearby_info_auth_tag_detection() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{NearbyInfoAdvertisementData, NEARBY_INFO_AUTH_TAG_FLAG};
+
+    // The length byte claims an authentication tag, which is cut off.
+    assert!(NearbyInfoAdvertisementData::try_from(vec![0x10, 0x05, 0x17, 0x1c, 0xaa]).is_err());
+    // The data flags claim an authentication tag, which the length byte doesn't account for.
+    assert!(NearbyInfoAdvertisementData::try_from(vec![
+        0x10,
+        0x02,
+        0x17,
+        NEARBY_INFO_AUTH_TAG_FLAG
+    ])
+    .is_err());
+    // Trailing bytes after the message are ignored.
+    assert_eq!(
+        NearbyInfoAdvertisementData::try_from(vec![
+            0x10,
+            0x05,
+            0x17,
+            NEARBY_INFO_AUTH_TAG_FLAG,
+            0xaa,
+            0xbb,
+            0xcc,
+            0xdd
+        ])?,
+        NearbyInfoAdvertisementData {
+            status_flags: 0x01,
+            action_code: 0x07,
+            data_flags: NEARBY_INFO_AUTH_TAG_FLAG,
+            auth_tag: Some([0xaa, 0xbb, 0xcc]),
+            action_data: vec![]
+        }
+    );
+    // The long form with the flag set round-trips, including its action data.
+    let data = NearbyInfoAdvertisementData {
+        status_flags: 0x01,
+        action_code: 0x07,
+        data_flags: NEARBY_INFO_AUTH_TAG_FLAG | 0x1c,
+        auth_tag: Some([0xaa, 0xbb, 0xcc]),
+        action_data: vec![0xdd],
+    };
+    let serialized = data.octets();
+    assert_eq!(
+        serialized,
+        vec![0x10, 0x06, 0x17, 0x3c, 0xaa, 0xbb, 0xcc, 0xdd]
+    );
+    assert_eq!(NearbyInfoAdvertisementData::try_from(serialized)?, data);
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_airdrop_modes() -> Result<(), Box<dyn Error>> {
+    for (mode, octet) in [
+        (AirDropMode::Off, 0x00),
+        (AirDropMode::ContactsOnly, 0x01),
+        (AirDropMode::Everyone, 0x02),
+        (AirDropMode::Other(0x07), 0x07),
+    ] {
+        let data = AirDropAdvertisementData {
+            apple_id: [0xfe, 0xdc],
+            phone: [0x76, 0x54],
+            email: [0xba, 0x98],
+            email2: None,
+            mode,
+        };
+        let serialized = data.octets();
+        assert_eq!(serialized.len(), 20);
+        assert_eq!(serialized[19], octet);
+        assert_eq!(AirDropAdvertisementData::try_from(serialized)?, data);
+    }
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_validate_advertisement() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::validate_advertisement;
+    use apple_ble::error::AppleBleError;
+    use std::collections::BTreeMap;
+
+    // Broadcasts don't carry the flags, which BlueZ adds to peripheral advertisements.
+    let advertisement = bluer::adv::Advertisement {
+        advertisement_type: bluer::adv::Type::Broadcast,
+        manufacturer_data: BTreeMap::from([(0x4c, vec![0x00; 28])]),
+        min_interval: Some(Duration::from_millis(10)),
+        max_interval: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    assert_eq!(
+        validate_advertisement(&advertisement, 31),
+        vec![
+            AppleBleError::PayloadTooLong {
+                length: 32,
+                max: 31
+            },
+            AppleBleError::InvalidInterval {
+                min: Duration::from_millis(10),
+                max: Duration::from_millis(200)
+            }
+        ]
+    );
+
+    let advertisement = bluer::adv::Advertisement {
+        advertisement_type: bluer::adv::Type::Broadcast,
+        manufacturer_data: BTreeMap::from([(0x4c, vec![0x00; 27])]),
+        min_interval: Some(Duration::from_millis(100)),
+        max_interval: Some(Duration::from_millis(200)),
+        ..Default::default()
+    };
+    assert!(validate_advertisement(&advertisement, 31).is_empty());
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_preflight() -> Result<(), Box<dyn Error>> {
+    let mut session = apple_ble::session::Session::new().await?;
+    let result = apple_ble::advertisement::FindMyAdvertisement::preflight(
+        &mut session,
+        &FindMyAdvertisementData::new([0x1e; 28]),
+    )
+    .await;
+    assert_eq!(result, Ok(()));
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_unsupported_interval() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{fit_interval, LEGACY_MIN_NONCONNECTABLE_INTERVAL};
+    use apple_ble::error::AppleBleError;
+
+    let fast = || bluer::adv::Advertisement {
+        min_interval: Some(Duration::from_millis(20)),
+        max_interval: Some(Duration::from_millis(30)),
+        ..Default::default()
+    };
+    assert_eq!(
+        fit_interval(fast(), LEGACY_MIN_NONCONNECTABLE_INTERVAL, false).err(),
+        Some(AppleBleError::IntervalUnsupported {
+            requested: Duration::from_millis(20),
+            min_allowed: LEGACY_MIN_NONCONNECTABLE_INTERVAL
+        })
+    );
+    let (clamped, warning) = fit_interval(fast(), LEGACY_MIN_NONCONNECTABLE_INTERVAL, true)?;
+    assert_eq!(
+        warning,
+        Some(AppleBleError::IntervalUnsupported {
+            requested: Duration::from_millis(20),
+            min_allowed: LEGACY_MIN_NONCONNECTABLE_INTERVAL
+        })
+    );
+    assert_eq!(
+        clamped.min_interval,
+        Some(LEGACY_MIN_NONCONNECTABLE_INTERVAL)
+    );
+    assert_eq!(
+        clamped.max_interval,
+        Some(LEGACY_MIN_NONCONNECTABLE_INTERVAL)
+    );
+    let (accepted, warning) = fit_interval(fast(), Duration::from_millis(20), false)?;
+    assert_eq!(accepted.min_interval, Some(Duration::from_millis(20)));
+    assert_eq!(warning, None);
+    Ok(())
+}
+
+#[cfg(feature = "handoff")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_handoff_apple_id_flag() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::HandoffAdvertisementData;
+
+    for apple_id in [false, true] {
+        let data = HandoffAdvertisementData {
+            clipboard: true,
+            apple_id,
+            sequence_number: 0x1234,
+            auth_tag: 0x56,
+            encrypted_data: [0x78; 10],
+        };
+        let serialized = data.octets();
+        assert_eq!(serialized.len(), 16);
+        assert_eq!(serialized[2], if apple_id { 0x09 } else { 0x08 });
+        assert_eq!(&serialized[3..6], &[0x12, 0x34, 0x56]);
+        assert_eq!(HandoffAdvertisementData::try_from(serialized)?, data);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+struct SlowAirDropAdvertisement;
+#[cfg(feature = "airdrop")]
+impl Advertisable<AirDropAdvertisementData> for SlowAirDropAdvertisement {
+    fn default_advertisement(session: &Session) -> bluer::adv::Advertisement {
+        bluer::adv::Advertisement {
+            min_interval: Some(Duration::from_millis(1000)),
+            max_interval: Some(Duration::from_millis(2000)),
+            ..apple_ble::advertisement::AirDropAdvertisement::default_advertisement(session)
+        }
+    }
+}
+
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_overridden_default_advertisement() -> Result<(), Box<dyn Error>> {
+    let mut session = apple_ble::session::Session::new().await?;
+    let data = AirDropAdvertisementData {
+        apple_id: [0xfe, 0xdc],
+        phone: [0x76, 0x54],
+        email: [0xba, 0x98],
+        email2: None,
+        mode: AirDropMode::ContactsOnly,
+    };
+    let advertisement = SlowAirDropAdvertisement::assemble_advertisement(&mut session, &data)?;
+    assert_eq!(
+        advertisement.min_interval,
+        Some(Duration::from_millis(1000))
+    );
+    assert_eq!(
+        advertisement.max_interval,
+        Some(Duration::from_millis(2000))
+    );
+    assert_eq!(
+        advertisement.manufacturer_data[&session.company_id],
+        data.octets()
+    );
+    let advertisement = apple_ble::advertisement::AirDropAdvertisement::assemble_advertisement(
+        &mut session,
+        &data,
+    )?;
+    assert_eq!(advertisement.min_interval, Some(Duration::from_millis(100)));
+    Ok(())
+}
+
+#[cfg(feature = "tethering")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_tethering_source_credentials_flag() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::TetheringSourceAdvertisementData;
+
+    for credentials_available in [false, true] {
+        let data = TetheringSourceAdvertisementData {
+            version: 0x01,
+            credentials_available,
+            flags: 0x10,
+            battery_life: 0x64,
+            cell_service_type: 0x0007,
+            cell_service_strength: 0x03,
+        };
+        let serialized = data.octets();
+        assert_eq!(
+            serialized[3],
+            if credentials_available { 0x11 } else { 0x10 }
+        );
+        assert_eq!(
+            TetheringSourceAdvertisementData::try_from(serialized)?,
+            data
+        );
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "airplay", feature = "airprint", feature = "serde"))]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_advertisement_spec_from_toml() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::AdvertisementSpec;
+
+    #[derive(serde::Deserialize)]
+    struct Config {
+        advertisements: Vec<AdvertisementSpec>,
+    }
+    let config: Config = config::Config::builder()
+        .add_source(config::File::from_str(
+            r#"
+            [[advertisements]]
+            type = "AirPlayTarget"
+            ip_address = "192.168.1.2"
+
+            [[advertisements]]
+            type = "AirPrint"
+            port = 631
+            ip_addr = "::1"
+            power = 100
+            "#,
+            config::FileFormat::Toml,
+        ))
+        .build()?
+        .try_deserialize()?;
+    assert_eq!(
+        config.advertisements,
+        vec![
+            AdvertisementSpec::AirPlayTarget(AirPlayTargetAdvertisementData::new(Ipv4Addr::new(
+                192, 168, 1, 2
+            ))),
+            AdvertisementSpec::AirPrint(AirPrintAdvertisementData {
+                address_type: AIRPRINT_ADDRESS_TYPE,
+                resource_path: AIRPRINT_RESOURCE_PATH,
+                security: AirPrintSecurity::Ipps,
+                port: 631,
+                ip_addr: Ipv6Addr::LOCALHOST,
+                power: 100
+            })
+        ]
+    );
+    assert_eq!(
+        apple_ble::advertisement::AdvertisementType::from(config.advertisements[0].clone()),
+        apple_ble::advertisement::AdvertisementType::AirPlayTarget(
+            AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 1, 2))
+        )
+    );
+    let mut session = apple_ble::session::Session::new().await?;
+    let mut handles = Vec::new();
+    for advertisement in &config.advertisements {
+        handles.push(advertisement.register(&mut session).await?);
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "airprint", feature = "handoff", feature = "tethering"))]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_multi_byte_fields_are_big_endian() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{HandoffAdvertisementData, TetheringSourceAdvertisementData};
+
+    let data = AirPrintAdvertisementData {
+        address_type: AIRPRINT_ADDRESS_TYPE,
+        resource_path: AIRPRINT_RESOURCE_PATH,
+        security: AirPrintSecurity::Ipps,
+        port: 0x1234,
+        ip_addr: Ipv6Addr::LOCALHOST,
+        power: 100,
+    };
+    let serialized = data.octets();
+    assert_eq!(&serialized[5..7], &[0x12, 0x34]);
+    assert_eq!(
+        AirPrintAdvertisementData::try_from(serialized)?.port,
+        0x1234
+    );
+
+    let data = HandoffAdvertisementData {
+        clipboard: false,
+        apple_id: false,
+        sequence_number: 0x1234,
+        auth_tag: 0x00,
+        encrypted_data: [0x00; 10],
+    };
+    let serialized = data.octets();
+    assert_eq!(&serialized[3..5], &[0x12, 0x34]);
+    assert_eq!(
+        HandoffAdvertisementData::try_from(serialized)?.sequence_number,
+        0x1234
+    );
+
+    let data = TetheringSourceAdvertisementData {
+        version: 0x01,
+        credentials_available: false,
+        flags: 0x00,
+        battery_life: 0x64,
+        cell_service_type: 0x1234,
+        cell_service_strength: 0x03,
+    };
+    let serialized = data.octets();
+    assert_eq!(&serialized[5..7], &[0x12, 0x34]);
+    assert_eq!(
+        TetheringSourceAdvertisementData::try_from(serialized)?.cell_service_type,
+        0x1234
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_advertisement_params_precedence() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::AdvertisementParams;
+
+    let session_params = AdvertisementParams {
+        local_name: Some("session".to_string()),
+        min_interval: Some(Duration::from_millis(150)),
+        ..Default::default()
+    };
+    let call_params = AdvertisementParams {
+        local_name: Some("call".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        call_params.merge(&session_params),
+        AdvertisementParams {
+            local_name: Some("call".to_string()),
+            min_interval: Some(Duration::from_millis(150)),
+            ..Default::default()
+        }
+    );
+
+    let mut session = apple_ble::session::Session::new().await?;
+    session.default_params = session_params;
+    let advertisement = apple_ble::advertisement::AirPlaySourceAdvertisement::assemble_with_params(
+        &mut session,
+        &apple_ble::advertisement::AirPlaySourceAdvertisementData::default(),
+        &call_params,
+    )?;
+    // Per call, session and crate defaults each contribute a field.
+    assert_eq!(advertisement.local_name, Some("call".to_string()));
+    assert_eq!(advertisement.min_interval, Some(Duration::from_millis(150)));
+    assert_eq!(advertisement.max_interval, Some(Duration::from_millis(200)));
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_contact_hashing() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::ContactHashing;
+
+    assert_eq!(ContactHashing::Raw.hash("Test@Example.com "), [0x1e, 0x5c]);
+    assert_eq!(
+        ContactHashing::Normalized.hash("Test@Example.com "),
+        [0x97, 0x3d]
+    );
+    assert_eq!(ContactHashing::Raw.hash("+1 (555) 010-0199"), [0x17, 0xe3]);
+    assert_eq!(
+        ContactHashing::Normalized.hash("+1 (555) 010-0199"),
+        [0x90, 0xbe]
+    );
+
+    let data = AirDropAdvertisementData::new(
+        "Test@Example.com ",
+        "+1 (555) 010-0199",
+        "Test@Example.com ",
+        AirDropMode::Everyone,
+        ContactHashing::Normalized,
+    );
+    assert_eq!(
+        data,
+        AirDropAdvertisementData {
+            apple_id: [0x97, 0x3d],
+            phone: [0x90, 0xbe],
+            email: [0x97, 0x3d],
+            email2: None,
+            mode: AirDropMode::Everyone
+        }
+    );
+    Ok(())
+}
+
+#[cfg(all(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy",
+    feature = "nearby",
+    feature = "handoff",
+    feature = "tethering"
+))]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_default_local_names() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::*;
+
+    assert_eq!(AirDropAdvertisement::default_local_name(), None);
+    assert_eq!(AirPrintAdvertisement::default_local_name(), None);
+    assert_eq!(FindMyAdvertisement::default_local_name(), None);
+    assert_eq!(NearbyInfoAdvertisement::default_local_name(), None);
+    assert_eq!(HandoffAdvertisement::default_local_name(), None);
+    assert_eq!(TetheringSourceAdvertisement::default_local_name(), None);
+    let host_name = std::fs::read_to_string("/proc/sys/kernel/hostname")?
+        .trim()
+        .to_string();
+    assert_eq!(
+        AirPlaySourceAdvertisement::default_local_name(),
+        Some(host_name.clone())
+    );
+    assert_eq!(
+        AirPlayTargetAdvertisement::default_local_name(),
+        Some(host_name)
+    );
+
+    let session = Session::new().await?;
+    assert_eq!(
+        FindMyAdvertisement::default_advertisement(&session).local_name,
+        None
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+struct FailingAirDropAdvertisement;
+#[cfg(feature = "airdrop")]
+impl Advertisable<AirDropAdvertisementData> for FailingAirDropAdvertisement {
+    fn assemble_advertisement(
+        _session: &mut Session,
+        _user_data: &AirDropAdvertisementData,
+    ) -> Result<bluer::adv::Advertisement, Box<dyn Error>> {
+        Err("simulated failure".into())
+    }
+}
+
+#[cfg(all(feature = "airdrop", feature = "findmy"))]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_registration_error_context() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::advertisement_name;
+    use apple_ble::error::{AppleBleError, Operation};
+
+    assert_eq!(
+        advertisement_name::<apple_ble::advertisement::FindMyAdvertisement>(),
+        "FindMy"
+    );
+    let mut session = apple_ble::session::Session::new().await?;
+    let err = FailingAirDropAdvertisement::register(
+        &mut session,
+        &AirDropAdvertisementData {
+            apple_id: [0x00, 0x00],
+            phone: [0x00, 0x00],
+            email: [0x00, 0x00],
+            email2: None,
+            mode: AirDropMode::ContactsOnly,
+        },
+    )
+    .await
+    .unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Failed to assemble FailingAirDrop advertisement: simulated failure"
+    );
+    assert_eq!(
+        err.downcast_ref::<AppleBleError>(),
+        Some(&AppleBleError::Registration {
+            advertisement: "FailingAirDrop".to_string(),
+            operation: Operation::Assemble,
+            reason: "simulated failure".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[cfg(feature = "nearby")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_invalid_user_data_error_context() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{NearbyInfoAdvertisement, NearbyInfoAdvertisementData};
+    use apple_ble::error::{AppleBleError, Operation};
+
+    let mut session = Session::new().await?;
+    let err = NearbyInfoAdvertisement::register(
+        &mut session,
+        &NearbyInfoAdvertisementData {
+            status_flags: 0x10,
+            action_code: 0x07,
+            data_flags: 0x00,
+            auth_tag: None,
+            action_data: vec![],
+        },
+    )
+    .await
+    .unwrap_err();
+    // Rejected user data names the advertisement like other registration failures.
+    assert_eq!(
+        err.downcast_ref::<AppleBleError>(),
+        Some(&AppleBleError::Registration {
+            advertisement: "NearbyInfo".to_string(),
+            operation: Operation::Assemble,
+            reason: "Status flags and action code are limited to four bits.".to_string()
+        })
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_coded_phy() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        AdvertisementParams, AirPlaySourceAdvertisement, AirPlaySourceAdvertisementData,
+    };
+    use apple_ble::error::AppleBleError;
+    use bluer::adv::SecondaryChannel;
+
+    let mut session = apple_ble::session::Session::new().await?;
+    let params = AdvertisementParams {
+        secondary_channel: Some(SecondaryChannel::Coded),
+        ..Default::default()
+    };
+    let result = AirPlaySourceAdvertisement::register_with_params(
+        &mut session,
+        &AirPlaySourceAdvertisementData::default(),
+        &params,
+    )
+    .await;
+    if session
+        .supported_secondary_channels()
+        .await
+        .contains(&SecondaryChannel::Coded)
+    {
+        result?;
+    } else {
+        assert_eq!(
+            result
+                .err()
+                .and_then(|err| err.downcast_ref::<AppleBleError>().cloned()),
+            Some(AppleBleError::PhyUnsupported {
+                phy: SecondaryChannel::Coded
+            })
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_findmy_octets() -> Result<(), Box<dyn Error>> {
+    let mut public_key = [0x00; 28];
+    public_key[0] = 0xa5;
+    for (i, byte) in public_key.iter_mut().enumerate().skip(1) {
+        *byte = i as u8;
+    }
+    let data = FindMyAdvertisementData::new(public_key);
+    // Laid out like the advertisement template of OpenHaystack's firmware, without the AD header and company id.
+    assert_eq!(
+        data.octets(),
+        vec![
+            0x12, 0x19, 0x00, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10,
+            0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x02, 0x00
+        ]
+    );
+    assert_eq!(
+        data.address(),
+        Address::new([0xe5, 0x01, 0x02, 0x03, 0x04, 0x05])
+    );
+    assert_eq!(
+        FindMyAdvertisementData::try_from((data.address(), data.octets()))?,
+        data
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_pause_and_resume() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        AdvertisementParams, AirPlayTargetAdvertisement, PausableAdvertisement,
+    };
+
+    let session = apple_ble::session::Session::new().await?;
+    let idle = session.adapter.active_advertising_instances().await?;
+    let mut advertisement = PausableAdvertisement::<_, AirPlayTargetAdvertisement>::register(
+        &session,
+        AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 1, 2)),
+        AdvertisementParams::default(),
+    )
+    .await?;
+    assert!(advertisement.is_active());
+    assert_eq!(
+        session.adapter.active_advertising_instances().await?,
+        idle + 1
+    );
+
+    advertisement.pause();
+    assert!(!advertisement.is_active());
+    // The advertisement is unregistered in the background.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(session.adapter.active_advertising_instances().await?, idle);
+
+    advertisement.resume().await?;
+    assert!(advertisement.is_active());
+    assert_eq!(
+        session.adapter.active_advertising_instances().await?,
+        idle + 1
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_airplay_source_flags() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::AirPlaySourceAdvertisementData;
+
+    assert_eq!(
+        AirPlaySourceAdvertisementData::default().octets(),
+        vec![0x0a, 0x01, 0x00]
+    );
+    let data = AirPlaySourceAdvertisementData { flags: 0x03 };
+    let serialized = data.octets();
+    assert_eq!(serialized, vec![0x0a, 0x01, 0x03]);
+    assert_eq!(AirPlaySourceAdvertisementData::try_from(serialized)?, data);
+    assert!(AirPlaySourceAdvertisementData::try_from(vec![0x0a, 0x01]).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_airdrop() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::AdvertisementType;
+
+    let airdrop = AirDropAdvertisementData {
+        apple_id: [0x01, 0x02],
+        phone: [0x03, 0x04],
+        email: [0x05, 0x06],
+        email2: None,
+        mode: AirDropMode::ContactsOnly,
+    };
+    let same = AdvertisementType::AirDrop(AirDropAdvertisementData {
+        mode: AirDropMode::Everyone,
+        ..airdrop.clone()
+    });
+    let other = AdvertisementType::AirDrop(AirDropAdvertisementData {
+        phone: [0x00, 0x00],
+        ..airdrop.clone()
+    });
+    assert!(AdvertisementType::AirDrop(airdrop.clone()).same_identity(&same));
+    assert!(!AdvertisementType::AirDrop(airdrop).same_identity(&other));
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_findmy() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::AdvertisementType;
+
+    let findmy = AdvertisementType::FindMy(FindMyAdvertisementData::new([0x01; 28]));
+    assert!(findmy.same_identity(&findmy.clone()));
+    assert!(
+        !findmy.same_identity(&AdvertisementType::FindMy(FindMyAdvertisementData::new(
+            [0x02; 28]
+        )))
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_airplay_target() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AdvertisementType, ConfigSeed};
+
+    let airplay = AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 1, 2));
+    let same = AdvertisementType::AirPlayTarget(AirPlayTargetAdvertisementData {
+        config_seed: ConfigSeed(0x08),
+        ..airplay.clone()
+    });
+    assert!(AdvertisementType::AirPlayTarget(airplay.clone()).same_identity(&same));
+    assert!(!AdvertisementType::AirPlayTarget(airplay).same_identity(
+        &AdvertisementType::AirPlayTarget(AirPlayTargetAdvertisementData::new(Ipv4Addr::new(
+            192, 168, 1, 3
+        )))
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "airprint")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_airprint() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::AdvertisementType;
+
+    let airprint = AirPrintAdvertisementData {
+        address_type: AIRPRINT_ADDRESS_TYPE,
+        resource_path: AIRPRINT_RESOURCE_PATH,
+        security: AirPrintSecurity::Ipps,
+        port: 631,
+        ip_addr: Ipv6Addr::LOCALHOST,
+        power: 0xc5,
+    };
+    assert!(AdvertisementType::AirPrint(airprint.clone()).same_identity(
+        &AdvertisementType::AirPrint(AirPrintAdvertisementData {
+            power: 0xb0,
+            ..airprint.clone()
+        })
+    ));
+    assert!(
+        !AdvertisementType::AirPrint(airprint.clone()).same_identity(&AdvertisementType::AirPrint(
+            AirPrintAdvertisementData {
+                port: 632,
+                ..airprint
+            }
+        ))
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_airplay_source() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AdvertisementType, AirPlaySourceAdvertisementData};
+
+    let airplay = AdvertisementType::AirPlaySource(AirPlaySourceAdvertisementData { flags: 0x00 });
+    assert!(airplay.same_identity(&airplay.clone()));
+    assert!(!airplay.same_identity(&AdvertisementType::AirPlaySource(
+        AirPlaySourceAdvertisementData { flags: 0x01 }
+    )));
+    Ok(())
+}
+
+#[cfg(feature = "nearby")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_nearby_info() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AdvertisementType, NearbyInfoAdvertisementData};
+
+    let data = NearbyInfoAdvertisementData {
+        status_flags: 0x01,
+        action_code: 0x07,
+        data_flags: 0x1c,
+        auth_tag: Some([0xaa, 0xbb, 0xcc]),
+        action_data: vec![],
+    };
+    let nearby = AdvertisementType::NearbyInfo(data.clone());
+    assert!(nearby.same_identity(&nearby.clone()));
+    // The action changes, while the device stays the same.
+    assert!(nearby.same_identity(&AdvertisementType::NearbyInfo(
+        NearbyInfoAdvertisementData {
+            action_code: 0x0b,
+            action_data: vec![0x01],
+            ..data.clone()
+        }
+    )));
+    assert!(!nearby.same_identity(&AdvertisementType::NearbyInfo(
+        NearbyInfoAdvertisementData {
+            auth_tag: Some([0xdd, 0xee, 0xff]),
+            ..data
+        }
+    )));
+    Ok(())
+}
+
+#[cfg(feature = "handoff")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_handoff() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AdvertisementType, HandoffAdvertisementData};
+
+    let data = HandoffAdvertisementData {
+        clipboard: false,
+        apple_id: true,
+        sequence_number: 0x0001,
+        auth_tag: 0x12,
+        encrypted_data: [0x00; 10],
+    };
+    let handoff = AdvertisementType::Handoff(data.clone());
+    assert!(handoff.same_identity(&handoff.clone()));
+    // The sequence number is a counter.
+    assert!(
+        handoff.same_identity(&AdvertisementType::Handoff(HandoffAdvertisementData {
+            sequence_number: 0x0002,
+            ..data.clone()
+        }))
+    );
+    assert!(
+        !handoff.same_identity(&AdvertisementType::Handoff(HandoffAdvertisementData {
+            encrypted_data: [0x01; 10],
+            ..data
+        }))
+    );
+    Ok(())
+}
+
+#[cfg(feature = "tethering")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_tethering_source() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AdvertisementType, TetheringSourceAdvertisementData};
+
+    let data = TetheringSourceAdvertisementData {
+        version: 0x01,
+        credentials_available: true,
+        flags: 0x00,
+        battery_life: 0x64,
+        cell_service_type: 0x0007,
+        cell_service_strength: 0x03,
+    };
+    let tethering = AdvertisementType::TetheringSource(data.clone());
+    assert!(tethering.same_identity(&tethering.clone()));
+    // The battery and the signal strength change over time.
+    assert!(tethering.same_identity(&AdvertisementType::TetheringSource(
+        TetheringSourceAdvertisementData {
+            battery_life: 0x32,
+            cell_service_strength: 0x01,
+            ..data.clone()
+        }
+    )));
+    assert!(
+        !tethering.same_identity(&AdvertisementType::TetheringSource(
+            TetheringSourceAdvertisementData {
+                cell_service_type: 0x0005,
+                ..data
+            }
+        ))
+    );
+    Ok(())
+}
+
+#[cfg(feature = "watch")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_watch_connection() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AdvertisementType, WatchConnectionAdvertisementData};
+
+    let watch = AdvertisementType::WatchConnection(WatchConnectionAdvertisementData {
+        status: 0x13,
+        data: vec![0x00, 0x39, 0x2e],
+    });
+    assert!(watch.same_identity(&watch.clone()));
+    assert!(!watch.same_identity(&AdvertisementType::WatchConnection(
+        WatchConnectionAdvertisementData {
+            status: 0x13,
+            data: vec![0x00, 0x39, 0x2f],
+        }
+    )));
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_same_identity_unknown() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::AdvertisementType;
+
+    let unknown = AdvertisementType::Unknown {
+        message_type: 0x42,
+        data: vec![0x42, 0x00],
+    };
+    assert!(!unknown.same_identity(&unknown.clone()));
+    assert!(
+        !unknown.same_identity(&AdvertisementType::FindMy(FindMyAdvertisementData::new(
+            [0x01; 28]
+        )))
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airprint")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_airprint_security_and_path() -> Result<(), Box<dyn Error>> {
+    let data = AirPrintAdvertisementData {
+        address_type: AIRPRINT_ADDRESS_TYPE,
+        resource_path: 0x03,
+        security: AirPrintSecurity::Ipp,
+        port: 631,
+        ip_addr: Ipv6Addr::LOCALHOST,
+        power: 100,
+    };
+    let serialized = data.octets();
+    assert_eq!(&serialized[2..5], &[0x74, 0x03, 0x00]);
+    assert_eq!(AirPrintAdvertisementData::try_from(serialized)?, data);
+
+    let data = AirPrintAdvertisementData {
+        resource_path: AIRPRINT_RESOURCE_PATH,
+        security: AirPrintSecurity::Ipps,
+        ..data
+    };
+    let serialized = data.octets();
+    assert_eq!(&serialized[2..5], &[0x74, 0x07, 0x6f]);
+    assert_eq!(AirPrintAdvertisementData::try_from(serialized)?, data);
+
+    // Unknown security bytes are kept instead of failing the decode.
+    let data = AirPrintAdvertisementData {
+        security: AirPrintSecurity::Other(0x42),
+        ..data
+    };
+    let serialized = data.octets();
+    assert_eq!(serialized[4], 0x42);
+    assert_eq!(AirPrintAdvertisementData::try_from(serialized)?, data);
+    Ok(())
+}
+
+#[cfg(feature = "airprint")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_airprint_port_validation() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AirPrintAdvertisement, StrictAirPrintAdvertisement};
+    use apple_ble::error::AppleBleError;
+
+    let data = AirPrintAdvertisementData {
+        address_type: AIRPRINT_ADDRESS_TYPE,
+        resource_path: AIRPRINT_RESOURCE_PATH,
+        security: AirPrintSecurity::Ipps,
+        port: 0x1337,
+        ip_addr: Ipv6Addr::LOCALHOST,
+        power: 100,
+    };
+    assert!(AirPrintAdvertisement::validate_user_data(&data).is_ok());
+    let warning = AppleBleError::InvalidUserData(
+        "Port 4919 isn't a printing port, clients won't reach the printer.".to_string(),
+    );
+    assert_eq!(data.check_port(false), Ok(Some(warning.clone())));
+    assert_eq!(data.check_port(true), Err(warning));
+    assert_eq!(
+        AirPrintAdvertisementData {
+            port: 631,
+            ..data.clone()
+        }
+        .check_port(false),
+        Ok(None)
+    );
+    assert!(StrictAirPrintAdvertisement::validate_user_data(&data).is_err());
+    for port in [631, 443] {
+        assert!(
+            StrictAirPrintAdvertisement::validate_user_data(&AirPrintAdvertisementData {
+                port,
+                ..data.clone()
+            })
+            .is_ok()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_findmy_address_byte_order() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{address_to_key_bytes, key_bytes_to_address};
+
+    let address: Address = "C1:02:03:04:05:06".parse()?;
+    assert_eq!(
+        address_to_key_bytes(address),
+        [0xc1, 0x02, 0x03, 0x04, 0x05, 0x06]
+    );
+    assert_eq!(
+        key_bytes_to_address([0x01, 0x02, 0x03, 0x04, 0x05, 0x06]),
+        address
+    );
+
+    let mut public_key = [0x00; 28];
+    public_key[..6].copy_from_slice(&[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+    let data = FindMyAdvertisementData::new(public_key);
+    assert_eq!(data.address().to_string(), "C1:02:03:04:05:06");
+    let decoded = FindMyAdvertisementData::try_from((address, data.octets()))?;
+    assert_eq!(
+        &decoded.public_key[..6],
+        &[0x01, 0x02, 0x03, 0x04, 0x05, 0x06]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_airdrop_email_slots() -> Result<(), Box<dyn Error>> {
+    let data = AirDropAdvertisementData {
+        apple_id: [0xfe, 0xdc],
+        phone: [0x76, 0x54],
+        email: [0xba, 0x98],
+        email2: None,
+        mode: AirDropMode::ContactsOnly,
+    };
+    let serialized = data.octets();
+    assert_eq!(&serialized[15..19], &[0xba, 0x98, 0xba, 0x98]);
+    assert_eq!(AirDropAdvertisementData::try_from(serialized)?, data);
+
+    let data = AirDropAdvertisementData {
+        email2: Some([0x32, 0x10]),
+        ..data
+    };
+    let serialized = data.octets();
+    assert_eq!(&serialized[15..19], &[0xba, 0x98, 0x32, 0x10]);
+    assert_eq!(AirDropAdvertisementData::try_from(serialized)?, data);
+    Ok(())
+}
+
+#[cfg(feature = "openhaystack")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_openhaystack_key_file() -> Result<(), Box<dyn Error>> {
+    let mut public_key = [0x00; 28];
+    for (i, byte) in public_key.iter_mut().enumerate() {
+        *byte = 0x30 + i as u8;
+    }
+    let keys = "Private key: YGFiY2RlZmdoaWprbG1ub3BxcnN0dXZ3eHl6ew==
+Advertisement key: MDEyMzQ1Njc4OTo7PD0+P0BBQkNERUZHSElKSw==
+Hashed adv key: gmV2VbH1dV+G0vcWeJ76RETijdCKG5ZOLWqknXGWI6U=
+";
+    assert_eq!(
+        FindMyAdvertisementData::from_openhaystack(keys.as_bytes())?,
+        FindMyAdvertisementData::new(public_key)
+    );
+    assert_eq!(
+        FindMyAdvertisementData::from_openhaystack(
+            "MDEyMzQ1Njc4OTo7PD0+P0BBQkNERUZHSElKSw==\n".as_bytes()
+        )?,
+        FindMyAdvertisementData::new(public_key)
+    );
+    // The private key alone isn't enough.
+    assert!(FindMyAdvertisementData::from_openhaystack("Private key: YGFiY2RlZmdoaWprbG1ub3BxcnN0dXZ3eHl6ew==\nHashed adv key: gmV2VbH1dV+G0vcWeJ76RETijdCKG5ZOLWqknXGWI6U=\n".as_bytes()).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_manufacturer_data_padding() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        AdvertisementParams, AirPlaySourceAdvertisement, AirPlaySourceAdvertisementData,
+    };
+
+    let mut session = apple_ble::session::Session::new().await?;
+    let data = AirPlaySourceAdvertisementData::default();
+    let unpadded = AirPlaySourceAdvertisement::assemble_with_params(
+        &mut session,
+        &data,
+        &AdvertisementParams::default(),
+    )?;
+    assert_eq!(
+        unpadded.manufacturer_data[&session.company_id],
+        data.octets()
+    );
+
+    let padded = AirPlaySourceAdvertisement::assemble_with_params(
+        &mut session,
+        &data,
+        &AdvertisementParams {
+            padded_length: Some(8),
+            ..Default::default()
+        },
+    )?;
+    let padded = &padded.manufacturer_data[&session.company_id];
+    assert_eq!(padded.len(), 8);
+    assert_eq!(&padded[..data.octets().len()], data.octets().as_slice());
+    assert!(padded[data.octets().len()..]
+        .iter()
+        .all(|&byte| byte == 0x00));
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_strict_findmy_key() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{FindMyAdvertisement, StrictFindMyAdvertisement};
+
+    let placeholder = FindMyAdvertisementData::new([0x88; 28]);
+    assert!(FindMyAdvertisement::validate_user_data(&placeholder).is_ok());
+    assert!(StrictFindMyAdvertisement::validate_user_data(&placeholder).is_err());
+    assert!(
+        StrictFindMyAdvertisement::validate_user_data(&FindMyAdvertisementData::new([0x00; 28]))
+            .is_err()
+    );
+
+    let mut public_key = [0x88; 28];
+    public_key[27] = 0x42;
+    assert!(
+        StrictFindMyAdvertisement::validate_user_data(&FindMyAdvertisementData::new(public_key))
+            .is_ok()
+    );
+    Ok(())
+}
+
+#[cfg(feature = "findmy")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_findmy_status() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::FindMyStatus;
+
+    let mut public_key = [0x00; 28];
+    public_key[0] = 0xc1;
+    public_key[27] = 0x42;
+    let separated = FindMyAdvertisementData::new(public_key);
+    assert_eq!(separated.status, FindMyStatus::Separated);
+    let owned = FindMyAdvertisementData {
+        public_key,
+        status: FindMyStatus::Owned,
+    };
+    let other = FindMyAdvertisementData {
+        public_key,
+        status: FindMyStatus::Other(0x84),
+    };
+    for (data, status) in [(separated, 0x00), (owned, 0x04), (other, 0x84)] {
+        let serialized = data.octets();
+        assert_eq!(serialized[2], status);
+        // The bits derived from the key are independent of the status.
+        assert_eq!(serialized[25], 0b11);
+        assert_eq!(
+            FindMyAdvertisementData::try_from((data.address(), serialized))?,
+            data
+        );
+    }
+    Ok(())
+}
+
+#[cfg(all(feature = "findmy", feature = "nearby"))]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_decoded_to_advertisement() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{decode_apple, AdvertisementParams, AdvertisementType};
+
+    let mut session = Session::new().await?;
+    let nearby_info = [0x10, 0x05, 0x17, 0x1c, 0xaa, 0xbb, 0xcc];
+    let decoded = decode_apple(Address::any(), &nearby_info).unwrap();
+    let advertisement = decoded.to_advertisement(
+        &mut session,
+        &AdvertisementParams {
+            local_name: Some("rebroadcast".to_string()),
+            ..Default::default()
+        },
+    )?;
+    assert_eq!(
+        advertisement.manufacturer_data[&session.company_id],
+        nearby_info
+    );
+    assert_eq!(advertisement.local_name, Some("rebroadcast".to_string()));
+
+    // Assembling a FindMy advertisement leaves the device address alone.
+    let address = session.adapter.address().await?;
+    let findmy = AdvertisementType::FindMy(FindMyAdvertisementData::new([0x1e; 28]));
+    let advertisement = findmy.to_advertisement(&mut session, &AdvertisementParams::default())?;
+    assert_eq!(
+        advertisement.manufacturer_data[&session.company_id],
+        findmy.octets()
+    );
+    assert_eq!(session.adapter.address().await?, address);
+
+    let unknown = AdvertisementType::Unknown {
+        message_type: 0x42,
+        data: vec![0x42, 0x00],
+    };
+    assert!(unknown
+        .to_advertisement(&mut session, &AdvertisementParams::default())
+        .is_err());
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_additional_manufacturer_data() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        check_length, AdvertisementParams, AirPlaySourceAdvertisement,
+        AirPlaySourceAdvertisementData, TEST_COMPANY_ID,
+    };
+    use apple_ble::error::AppleBleError;
+    use std::collections::BTreeMap;
+
+    let mut session = Session::new().await?;
+    let data = AirPlaySourceAdvertisementData::default();
+    let params = AdvertisementParams {
+        additional_manufacturer_data: Some(BTreeMap::from([
+            (TEST_COMPANY_ID, vec![0x01, 0x02]),
+            // The entry of the session's company id can't be replaced.
+            (session.company_id, vec![0xff]),
+        ])),
+        ..Default::default()
+    };
+    let advertisement =
+        AirPlaySourceAdvertisement::assemble_with_params(&mut session, &data, &params)?;
+    assert_eq!(advertisement.manufacturer_data.len(), 2);
+    assert_eq!(
+        advertisement.manufacturer_data[&session.company_id],
+        data.octets()
+    );
+    assert_eq!(
+        advertisement.manufacturer_data[&TEST_COMPANY_ID],
+        vec![0x01, 0x02]
+    );
+    // AirPlay sources advertise the host name as local name.
+    let local_name = advertisement
+        .local_name
+        .as_ref()
+        .map_or(0, |name| 2 + name.len());
+    assert!(check_length(&advertisement, 4 + data.octets().len() + 4 + 2 + local_name).is_ok());
+
+    let params = AdvertisementParams {
+        additional_manufacturer_data: Some(BTreeMap::from([(TEST_COMPANY_ID, vec![0x00; 24])])),
+        ..Default::default()
+    };
+    let advertisement =
+        AirPlaySourceAdvertisement::assemble_with_params(&mut session, &data, &params)?;
+    assert_eq!(
+        check_length(&advertisement, 31),
+        Err(AppleBleError::PayloadTooLong {
+            length: 4 + data.octets().len() + 4 + 24 + local_name,
+            max: 31
+        })
+    );
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_check_length_counts_bluez_structures() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::check_length;
+    use apple_ble::error::AppleBleError;
+    use std::collections::BTreeMap;
+
+    let advertisement = bluer::adv::Advertisement {
+        advertisement_type: bluer::adv::Type::Broadcast,
+        manufacturer_data: BTreeMap::from([(0x004c, vec![0x00; 10])]),
+        ..Default::default()
+    };
+    assert!(check_length(&advertisement, 14).is_ok());
+    // The local name and the appearance are framed like other structures.
+    let named = bluer::adv::Advertisement {
+        local_name: Some("name".to_string()),
+        appearance: Some(0x0040),
+        ..advertisement.clone()
+    };
+    assert_eq!(
+        check_length(&named, 14),
+        Err(AppleBleError::PayloadTooLong {
+            length: 14 + 2 + 4 + 4,
+            max: 14
+        })
+    );
+    // BlueZ adds the flags to peripheral and discoverable advertisements.
+    for advertisement in [
+        bluer::adv::Advertisement {
+            advertisement_type: bluer::adv::Type::Peripheral,
+            ..advertisement.clone()
+        },
+        bluer::adv::Advertisement {
+            discoverable: Some(true),
+            ..advertisement.clone()
+        },
+    ] {
+        assert_eq!(
+            check_length(&advertisement, 14),
+            Err(AppleBleError::PayloadTooLong {
+                length: 14 + 3,
+                max: 14
+            })
+        );
+    }
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_airplay_target_config_seed() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::ConfigSeed;
+
+    // The default keeps the bytes advertised before the seed was configurable.
+    assert_eq!(ConfigSeed::default(), ConfigSeed(0x07));
+    assert_eq!(
+        AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 1, 2)).octets(),
+        vec![0x09, 0x06, 0x03, 0x07, 0xc0, 0xa8, 0x01, 0x02]
+    );
+    #[cfg(feature = "serde")]
+    assert_eq!(
+        serde_json::from_str::<AirPlayTargetAdvertisementData>(r#"{"ip_address":"192.168.1.2"}"#)?
+            .config_seed,
+        ConfigSeed(0x07)
+    );
+
+    let mut data = AirPlayTargetAdvertisementData {
+        ip_address: Ipv4Addr::new(192, 168, 1, 2),
+        config_seed: ConfigSeed(0xfe),
+    };
+    assert_eq!(data.octets()[3], 0xfe);
+    assert_eq!(
+        AirPlayTargetAdvertisementData::try_from(data.octets())?,
+        data
+    );
+
+    data.config_seed.increment();
+    assert_eq!(data.config_seed, ConfigSeed(0xff));
+    data.config_seed.increment();
+    assert_eq!(data.config_seed, ConfigSeed(0x00));
+    assert_eq!(
+        AirPlayTargetAdvertisementData::try_from(data.octets())?,
+        data
+    );
+    assert!(AirPlayTargetAdvertisementData::try_from(vec![0x09, 0x06, 0x03]).is_err());
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_airdrop_hex_prefixes() -> Result<(), Box<dyn Error>> {
+    use apple_ble::error::AppleBleError;
+
+    let data = AirDropAdvertisementData::from_hex_prefixes("fedc", "7654", "BA98")?;
+    assert_eq!(data.apple_id, [0xfe, 0xdc]);
+    assert_eq!(data.phone, [0x76, 0x54]);
+    assert_eq!(data.email, [0xba, 0x98]);
+    assert_eq!(data.email2, None);
+    assert_eq!(data.mode, AirDropMode::default());
+
+    for invalid in ["fed", "fedcb", "fedg", "+fed", "fé"] {
+        assert!(matches!(
+            AirDropAdvertisementData::from_hex_prefixes(invalid, "7654", "ba98"),
+            Err(AppleBleError::InvalidUserData(_))
+        ));
+    }
+    assert!(AirDropAdvertisementData::from_hex_prefixes("fedc", "7654", "").is_err());
+    Ok(())
+}
+
+#[cfg(feature = "nearby")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_nearby_info_action_data() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{NearbyInfoAdvertisement, NearbyInfoAdvertisementData};
+
+    let data = NearbyInfoAdvertisementData {
+        status_flags: 0x01,
+        action_code: 0x0e,
+        data_flags: 0x1c,
+        auth_tag: Some([0xaa, 0xbb, 0xcc]),
+        action_data: vec![0x01, 0x02],
+    };
+    let serialized = data.octets();
+    assert_eq!(
+        serialized,
+        vec![0x10, 0x07, 0x1e, 0x1c, 0xaa, 0xbb, 0xcc, 0x01, 0x02]
+    );
+    assert_eq!(
+        NearbyInfoAdvertisementData::try_from(serialized.clone())?,
+        data
+    );
+    // The length byte covers the action data.
+    assert!(NearbyInfoAdvertisementData::try_from(serialized[..8].to_vec()).is_err());
+
+    let without = NearbyInfoAdvertisementData {
+        action_data: vec![],
+        ..data.clone()
+    };
+    assert_eq!(
+        without.octets(),
+        vec![0x10, 0x05, 0x1e, 0x1c, 0xaa, 0xbb, 0xcc]
+    );
+    assert_eq!(
+        NearbyInfoAdvertisementData::try_from(without.octets())?,
+        without
+    );
+
+    assert!(
+        NearbyInfoAdvertisement::validate_user_data(&NearbyInfoAdvertisementData {
+            auth_tag: None,
+            ..data.clone()
+        })
+        .is_err()
+    );
+
+    // The length byte has to cover the header, the tag and the action data.
+    let longest = NearbyInfoAdvertisementData {
+        action_data: vec![0x00; 250],
+        ..data.clone()
+    };
+    NearbyInfoAdvertisement::validate_user_data(&longest)?;
+    assert_eq!(longest.octets()[1], 0xff);
+    assert_eq!(
+        NearbyInfoAdvertisementData::try_from(longest.octets())?,
+        longest
+    );
+    assert!(
+        NearbyInfoAdvertisement::validate_user_data(&NearbyInfoAdvertisementData {
+            action_data: vec![0x00; 251],
+            ..data
+        })
+        .is_err()
+    );
+    Ok(())
+}
+
+#[cfg(feature = "watch")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_watch_connection_data_length() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        WatchConnectionAdvertisement, WatchConnectionAdvertisementData,
+    };
+
+    let longest = WatchConnectionAdvertisementData {
+        status: 0x01,
+        data: vec![0x00; 254],
+    };
+    WatchConnectionAdvertisement::validate_user_data(&longest)?;
+    assert_eq!(longest.octets()[1], 0xff);
+    assert_eq!(
+        WatchConnectionAdvertisementData::try_from(longest.octets())?,
+        longest
+    );
+    assert!(
+        WatchConnectionAdvertisement::validate_user_data(&WatchConnectionAdvertisementData {
+            data: vec![0x00; 255],
+            ..longest
+        })
+        .is_err()
+    );
+    Ok(())
+}
+
+#[cfg(feature = "openhaystack")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_findmy_from_base64() -> Result<(), Box<dyn Error>> {
+    use apple_ble::error::AppleBleError;
+
+    let mut public_key = [0x00; 28];
+    for (i, byte) in public_key.iter_mut().enumerate() {
+        *byte = 0x30 + i as u8;
+    }
+    assert_eq!(
+        FindMyAdvertisementData::from_base64(" MDEyMzQ1Njc4OTo7PD0+P0BBQkNERUZHSElKSw==\n")?,
+        FindMyAdvertisementData::new(public_key)
+    );
+    assert_eq!(
+        FindMyAdvertisementData::from_base64("MDEy"),
+        Err(AppleBleError::InvalidUserData(
+            "the advertisement key is 3 bytes long instead of 28".to_string()
+        ))
+    );
+    assert!(matches!(
+        FindMyAdvertisementData::from_base64("not base64!"),
+        Err(AppleBleError::InvalidUserData(_))
+    ));
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_contact_hashes_are_independent() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::ContactHashing;
+
+    // SHA-256 of "john.appleseed@icloud.com" starts with 76a6, the one of "14155550123" with 413b.
+    let data = AirDropAdvertisementData::new(
+        "John.Appleseed@iCloud.com",
+        "+1 415 555 0123",
+        "john.appleseed@icloud.com",
+        AirDropMode::ContactsOnly,
+        ContactHashing::Normalized,
+    );
+    assert_eq!(data.apple_id, [0x76, 0xa6]);
+    assert_eq!(data.phone, [0x41, 0x3b]);
+    assert_eq!(data.email, [0x76, 0xa6]);
+    // Raw hashing keeps the case and the plus sign.
+    let data = AirDropAdvertisementData::new(
+        "John.Appleseed@iCloud.com",
+        "+14155550123",
+        "john.appleseed@icloud.com",
+        AirDropMode::ContactsOnly,
+        ContactHashing::Raw,
+    );
+    assert_eq!(data.apple_id, [0xc5, 0x27]);
+    assert_eq!(data.phone, [0x36, 0xa2]);
+    assert_eq!(data.email, [0x76, 0xa6]);
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_tx_power_range() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        check_tx_power, AdvertisementParams, AirPlaySourceAdvertisement,
+        AirPlaySourceAdvertisementData,
+    };
+    use apple_ble::error::AppleBleError;
+    use bluer::adv::Advertisement;
+
+    let advertisement = Advertisement {
+        tx_power: Some(-30),
+        ..Default::default()
+    };
+    assert!(check_tx_power(&advertisement, (-40, 10)).is_ok());
+    let error = check_tx_power(&advertisement, (-20, 10)).unwrap_err();
+    assert_eq!(
+        error,
+        AppleBleError::TxPowerUnsupported {
+            requested: -30,
+            min: -20,
+            max: 10
+        }
+    );
+    assert!(error.to_string().contains("-20dBm to 10dBm"));
+    assert!(check_tx_power(&Advertisement::default(), (-20, 10)).is_ok());
+
+    let mut session = Session::new().await?;
+    // Only controllers reporting their range can be checked against it.
+    if session
+        .adapter
+        .supported_advertising_capabilities()
+        .await?
+        .is_none()
+    {
+        return Ok(());
+    }
+    let (min, max) = session.tx_power_range().await;
+    assert!(min <= max);
+    let data = AirPlaySourceAdvertisementData::default();
+    let params = AdvertisementParams {
+        tx_power: Some(max),
+        ..Default::default()
+    };
+    let handle =
+        AirPlaySourceAdvertisement::register_with_params(&mut session, &data, &params).await?;
+    drop(handle);
+    let params = AdvertisementParams {
+        tx_power: Some(max + 1),
+        ..Default::default()
+    };
+    let error = AirPlaySourceAdvertisement::register_with_params(&mut session, &data, &params)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<AppleBleError>(),
+        Some(&AppleBleError::TxPowerUnsupported {
+            requested: max + 1,
+            min,
+            max
+        })
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airprint")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_typed_constructors() -> Result<(), Box<dyn Error>> {
+    let advertisement =
+        apple_ble::advertisement::AdvertisementType::air_print(631, Ipv6Addr::LOCALHOST, 100)?;
+    let apple_ble::advertisement::AdvertisementType::AirPrint(data) = &advertisement else {
+        panic!("Expected an AirPrint advertisement, got {advertisement:?}.");
+    };
+    assert_eq!(
+        (data.address_type, data.resource_path, data.security),
+        (
+            AIRPRINT_ADDRESS_TYPE,
+            AIRPRINT_RESOURCE_PATH,
+            AirPrintSecurity::Ipps
+        )
+    );
+
+    let mut session = apple_ble::session::Session::new().await?;
+    advertisement.register(&mut session).await?;
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_raw_advertisement_data() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        AdvertisementType, RawAppleAdvertisement, RawAppleAdvertisementData,
+    };
+
+    let octets = AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 0, 12)).octets();
+    let raw = RawAppleAdvertisementData(octets.clone());
+    assert_eq!(raw.octets(), octets);
+    assert_eq!(raw.message_type(), Some(0x09));
+    RawAppleAdvertisement::validate_user_data(&raw)?;
+    assert!(RawAppleAdvertisement::validate_user_data(&RawAppleAdvertisementData(vec![])).is_err());
+    assert!(
+        RawAppleAdvertisement::validate_user_data(&RawAppleAdvertisementData(vec![0x42; 253]))
+            .is_err()
+    );
+
+    let address = Address::new([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06]);
+    assert_eq!(
+        AdvertisementType::from_manufacturer_data(address, &raw.octets()),
+        Some(AdvertisementType::AirPlayTarget(
+            AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 0, 12))
+        ))
+    );
+    Ok(())
+}
+
+#[cfg(feature = "openhaystack")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_findmy_summary() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::AdvertisementType;
+
+    let mut public_key = [0x00; 28];
+    for (i, byte) in public_key.iter_mut().enumerate() {
+        *byte = 0x30 + i as u8;
+    }
+    let data = FindMyAdvertisementData::new(public_key);
+    let summary = data.summary();
+    assert_eq!(
+        summary.public_key,
+        "MDEyMzQ1Njc4OTo7PD0+P0BBQkNERUZHSElKSw=="
+    );
+    assert_eq!(
+        summary.address,
+        Address::new([0xf0, 0x31, 0x32, 0x33, 0x34, 0x35])
+    );
+    assert_eq!(summary.advertising_data[..4], [0x1e, 0xff, 0x4c, 0x00]);
+    assert_eq!(summary.advertising_data[4..], data.octets());
+    assert_eq!(
+        AdvertisementType::from_ad_structure(summary.address, &summary.advertising_data),
+        Some(AdvertisementType::FindMy(data.clone()))
+    );
+
+    let text = summary.to_string();
+    assert!(text.contains("Address: F0:31:32:33:34:35"));
+    assert_eq!(
+        FindMyAdvertisementData::from_openhaystack(text.as_bytes())?,
+        data
+    );
+    Ok(())
+}
+
+#[cfg(all(feature = "airplay", feature = "airprint"))]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_diff() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AdvertisementType, FieldDiff};
+
+    let data = AirPrintAdvertisementData {
+        address_type: AIRPRINT_ADDRESS_TYPE,
+        resource_path: AIRPRINT_RESOURCE_PATH,
+        security: AirPrintSecurity::Ipps,
+        port: 631,
+        ip_addr: Ipv6Addr::LOCALHOST,
+        power: 100,
+    };
+    let original = AdvertisementType::AirPrint(data.clone());
+    let changed = AdvertisementType::AirPrint(AirPrintAdvertisementData { port: 443, ..data });
+    assert_eq!(
+        original.diff(&changed),
+        vec![FieldDiff {
+            field: "port",
+            old: "631".to_string(),
+            new: "443".to_string()
+        }]
+    );
+    assert!(original.diff(&original).is_empty());
+
+    let other_type =
+        AdvertisementType::AirPlayTarget(AirPlayTargetAdvertisementData::new(Ipv4Addr::LOCALHOST));
+    assert_eq!(
+        original.diff(&other_type),
+        vec![FieldDiff {
+            field: "type",
+            old: "AirPrint".to_string(),
+            new: "AirPlayTarget".to_string()
+        }]
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn test_additional_advertising_data() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        check_advertising_data, check_length, AdvertisementParams, AirPlaySourceAdvertisementData,
+        APPLE_MAGIC,
+    };
+    use apple_ble::error::AppleBleError;
+    use bluer::adv::Advertisement;
+    use bluer::Uuid;
+    use std::collections::BTreeMap;
+
+    let data = AirPlaySourceAdvertisementData::default();
+    // A URI, using the scheme code of "https:".
+    let uri = [&[0x17], b"//example.com".as_slice()].concat();
+    let params = AdvertisementParams {
+        additional_advertising_data: Some(BTreeMap::from([(0x24, uri.clone())])),
+        ..Default::default()
+    };
+    let advertisement = params.apply(Advertisement {
+        advertisement_type: bluer::adv::Type::Broadcast,
+        manufacturer_data: BTreeMap::from([(APPLE_MAGIC, data.octets())]),
+        ..Default::default()
+    });
+    assert_eq!(advertisement.advertisting_data.get(&0x24), Some(&uri));
+    assert_eq!(advertisement.manufacturer_data[&APPLE_MAGIC], data.octets());
+    assert!(check_advertising_data(&advertisement).is_ok());
+    assert_eq!(
+        check_length(&advertisement, 20),
+        Err(AppleBleError::PayloadTooLong {
+            length: 4 + data.octets().len() + 2 + uri.len(),
+            max: 20
+        })
+    );
+
+    // Service data and the flags are set through their own properties.
+    for ad_type in [0x16, 0x01] {
+        let params = AdvertisementParams {
+            additional_advertising_data: Some(BTreeMap::from([(ad_type, vec![0x0a, 0x18])])),
+            ..Default::default()
+        };
+        assert!(matches!(
+            check_advertising_data(&params.apply(Advertisement::default())),
+            Err(AppleBleError::InvalidUserData(_))
+        ));
+    }
+    let advertisement = Advertisement {
+        service_data: BTreeMap::from([(
+            Uuid::from_u128(0x0000180a_0000_1000_8000_00805f9b34fb),
+            vec![0x0a, 0x18],
+        )]),
+        discoverable: Some(true),
+        ..Default::default()
+    };
+    assert!(check_advertising_data(&advertisement).is_ok());
+    Ok(())
+}
@@ -0,0 +1,58 @@
+#![cfg(all(feature = "btsnoop", feature = "airplay"))]
+use apple_ble::advertisement::{
+    AdvertisementType, AirPlaySourceAdvertisementData, AirPlayTargetAdvertisementData, ConfigSeed,
+};
+use apple_ble::btsnoop::{decode_capture, read_advertising_reports};
+use apple_ble::error::AppleBleError;
+use bluer::Address;
+use std::{error::Error, net::Ipv4Addr};
+
+const H4_CAPTURE: &[u8] = include_bytes!("data/advertisements_h4.btsnoop");
+const HCI_CAPTURE: &[u8] = include_bytes!("data/advertisements_hci.btsnoop");
+
+#[test]
+fn decodes_apple_advertisements_of_capture() -> Result<(), Box<dyn Error>> {
+    let expected = vec![
+        (
+            Address::new([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06]),
+            AdvertisementType::AirPlayTarget(AirPlayTargetAdvertisementData {
+                ip_address: Ipv4Addr::new(192, 168, 1, 2),
+                config_seed: ConfigSeed(0x07),
+            }),
+        ),
+        (
+            Address::new([0xe5, 0x66, 0x77, 0x88, 0x99, 0xaa]),
+            AdvertisementType::AirPlaySource(AirPlaySourceAdvertisementData { flags: 0x00 }),
+        ),
+    ];
+    // The same capture, once with H4 and once with un-encapsulated HCI framing.
+    assert_eq!(decode_capture(H4_CAPTURE)?, expected);
+    assert_eq!(decode_capture(HCI_CAPTURE)?, expected);
+    Ok(())
+}
+
+#[test]
+fn reads_every_advertising_report() -> Result<(), Box<dyn Error>> {
+    let reports = read_advertising_reports(H4_CAPTURE)?;
+    assert_eq!(
+        reports
+            .iter()
+            .map(|report| (report.address, report.rssi))
+            .collect::<Vec<_>>(),
+        vec![
+            (Address::new([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06]), -60),
+            (Address::new([0xd4, 0x11, 0x22, 0x33, 0x44, 0x55]), -70),
+            (Address::new([0xe5, 0x66, 0x77, 0x88, 0x99, 0xaa]), -50),
+        ]
+    );
+    assert_eq!(reports[1].decode(), None);
+    Ok(())
+}
+
+#[test]
+fn rejects_other_files() {
+    assert!(matches!(
+        read_advertising_reports(&b"not a capture"[..]),
+        Err(AppleBleError::InvalidCapture(_))
+    ));
+}
@@ -0,0 +1,643 @@
+#[cfg(any(feature = "findmy", feature = "handoff", feature = "watch"))]
+use apple_ble::advertisement::AdvertisableData;
+#[cfg(feature = "handoff")]
+use apple_ble::advertisement::HandoffAdvertisementData;
+#[cfg(feature = "nearby")]
+use apple_ble::advertisement::NearbyInfoAdvertisementData;
+#[cfg(feature = "tethering")]
+use apple_ble::advertisement::TetheringSourceAdvertisementData;
+#[cfg(feature = "watch")]
+use apple_ble::advertisement::WatchConnectionAdvertisementData;
+use apple_ble::advertisement::{decode_apple, AdvertisementType};
+#[cfg(feature = "airplay")]
+use apple_ble::advertisement::{
+    decode_manufacturer_data, parse_manufacturer_data, AirPlaySourceAdvertisementData,
+    AirPlayTargetAdvertisementData, ConfigSeed, APPLE_MAGIC,
+};
+#[cfg(feature = "airdrop")]
+use apple_ble::advertisement::{AirDropAdvertisementData, AirDropMode};
+#[cfg(feature = "airprint")]
+use apple_ble::advertisement::{
+    AirPrintAdvertisementData, AirPrintSecurity, AIRPRINT_ADDRESS_TYPE, AIRPRINT_RESOURCE_PATH,
+};
+#[cfg(feature = "findmy")]
+use apple_ble::advertisement::{
+    FindMyAdvertisementData, FindMyNearbyAdvertisementData, FindMyStatus,
+};
+#[cfg(feature = "airplay")]
+use apple_ble::error::DecodeError;
+#[cfg(all(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy",
+    feature = "nearby",
+    feature = "handoff",
+    feature = "tethering",
+    feature = "watch"
+))]
+use apple_ble::registry::BUILTIN_MESSAGE_TYPES;
+use bluer::Address;
+#[cfg(feature = "airplay")]
+use std::collections::HashMap;
+#[cfg(feature = "airplay")]
+use std::net::Ipv4Addr;
+#[cfg(feature = "airprint")]
+use std::net::Ipv6Addr;
+
+const ADDRESS: Address = Address([0x40, 0x11, 0x22, 0x33, 0x44, 0x55]);
+
+#[cfg(feature = "airdrop")]
+const AIRDROP: [u8; 20] = [
+    0x05, 0x12, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xfe, 0xdc, 0x76, 0x54, 0xba,
+    0x98, 0xba, 0x98, 0x00,
+];
+#[cfg(feature = "airplay")]
+const AIRPLAY_SOURCE: [u8; 3] = [0x0a, 0x01, 0x00];
+#[cfg(feature = "airplay")]
+const AIRPLAY_TARGET: [u8; 8] = [0x09, 0x06, 0x03, 0x07, 0xc0, 0xa8, 0x01, 0x02];
+#[cfg(feature = "airprint")]
+const AIRPRINT: [u8; 24] = [
+    0x03, 0x16, 0x74, 0x07, 0x6f, 0x02, 0x77, 0xfe, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xc5,
+];
+#[cfg(feature = "findmy")]
+const FINDMY_ADDRESS: Address = Address([0xd1, 0x22, 0x33, 0x44, 0x55, 0x66]);
+#[cfg(feature = "findmy")]
+const FINDMY: [u8; 26] = [
+    0x12, 0x19, 0x00, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f, 0x10, 0x11, 0x12, 0x13,
+    0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x02,
+];
+#[cfg(feature = "findmy")]
+const FINDMY_NEARBY: [u8; 4] = [0x12, 0x02, 0x04, 0x02];
+#[cfg(feature = "nearby")]
+const NEARBY_INFO: [u8; 7] = [0x10, 0x05, 0x17, 0x1c, 0xaa, 0xbb, 0xcc];
+#[cfg(feature = "nearby")]
+const NEARBY_INFO_SHORT: [u8; 4] = [0x10, 0x02, 0x17, 0x1c];
+#[cfg(feature = "watch")]
+const WATCH_CONNECTION: [u8; 6] = [0x0b, 0x04, 0x13, 0x00, 0x39, 0x2e];
+#[cfg(feature = "handoff")]
+const HANDOFF: [u8; 16] = [
+    0x0c, 0x0e, 0x08, 0x12, 0x34, 0xab, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09,
+];
+#[cfg(feature = "tethering")]
+const TETHERING_SOURCE: [u8; 8] = [0x0e, 0x06, 0x01, 0x11, 0x64, 0x00, 0x07, 0x03];
+
+#[cfg(feature = "airdrop")]
+#[test]
+fn decodes_airdrop() {
+    assert_eq!(
+        decode_apple(ADDRESS, &AIRDROP),
+        Some(AdvertisementType::AirDrop(AirDropAdvertisementData {
+            apple_id: [0xfe, 0xdc],
+            phone: [0x76, 0x54],
+            email: [0xba, 0x98],
+            email2: None,
+            mode: AirDropMode::Off
+        }))
+    );
+}
+
+#[cfg(feature = "airplay")]
+#[test]
+fn decodes_airplay_source() {
+    assert_eq!(
+        decode_apple(ADDRESS, &AIRPLAY_SOURCE),
+        Some(AdvertisementType::AirPlaySource(
+            AirPlaySourceAdvertisementData { flags: 0x00 }
+        ))
+    );
+}
+
+#[cfg(feature = "airplay")]
+#[test]
+fn decodes_airplay_target() {
+    assert_eq!(
+        decode_apple(ADDRESS, &AIRPLAY_TARGET),
+        Some(AdvertisementType::AirPlayTarget(
+            AirPlayTargetAdvertisementData {
+                ip_address: Ipv4Addr::new(192, 168, 1, 2),
+                config_seed: ConfigSeed(0x07)
+            }
+        ))
+    );
+}
+
+#[cfg(feature = "airprint")]
+#[test]
+fn decodes_airprint() {
+    assert_eq!(
+        decode_apple(ADDRESS, &AIRPRINT),
+        Some(AdvertisementType::AirPrint(AirPrintAdvertisementData {
+            address_type: AIRPRINT_ADDRESS_TYPE,
+            resource_path: AIRPRINT_RESOURCE_PATH,
+            security: AirPrintSecurity::Ipps,
+            port: 631,
+            ip_addr: Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1),
+            power: 0xc5
+        }))
+    );
+}
+
+#[cfg(feature = "findmy")]
+#[test]
+fn decodes_findmy() {
+    let mut public_key = [0x00; 28];
+    public_key[0..6].copy_from_slice(&[0x91, 0x22, 0x33, 0x44, 0x55, 0x66]);
+    public_key[6..28].copy_from_slice(&FINDMY[3..25]);
+    assert_eq!(
+        decode_apple(FINDMY_ADDRESS, &FINDMY),
+        Some(AdvertisementType::FindMy(FindMyAdvertisementData::new(
+            public_key
+        )))
+    );
+}
+
+#[cfg(feature = "findmy")]
+#[test]
+fn decodes_findmy_by_length() {
+    let nearby = FindMyNearbyAdvertisementData {
+        key_prefix: [0x91, 0x22, 0x33, 0x44, 0x55, 0x66],
+        status: FindMyStatus::Owned,
+    };
+    assert_eq!(
+        decode_apple(FINDMY_ADDRESS, &FINDMY_NEARBY),
+        Some(AdvertisementType::FindMyNearby(nearby.clone()))
+    );
+    assert_eq!(nearby.octets(), FINDMY_NEARBY);
+    assert_eq!(nearby.address(), FINDMY_ADDRESS);
+
+    let Some(AdvertisementType::FindMy(separated)) = decode_apple(FINDMY_ADDRESS, &FINDMY) else {
+        panic!("The long message wasn't decoded as FindMy.");
+    };
+    assert_eq!(separated.public_key[0..6], nearby.key_prefix);
+    assert_eq!(separated.status, FindMyStatus::Separated);
+    // Truncated short messages aren't mistaken for long ones.
+    assert_eq!(decode_apple(FINDMY_ADDRESS, &FINDMY_NEARBY[..3]), None);
+}
+
+#[cfg(feature = "nearby")]
+#[test]
+fn decodes_nearby_info() {
+    assert_eq!(
+        decode_apple(ADDRESS, &NEARBY_INFO),
+        Some(AdvertisementType::NearbyInfo(NearbyInfoAdvertisementData {
+            status_flags: 0x01,
+            action_code: 0x07,
+            data_flags: 0x1c,
+            auth_tag: Some([0xaa, 0xbb, 0xcc]),
+            action_data: vec![]
+        }))
+    );
+}
+
+#[cfg(feature = "nearby")]
+#[test]
+fn decodes_short_nearby_info() {
+    assert_eq!(
+        decode_apple(ADDRESS, &NEARBY_INFO_SHORT),
+        Some(AdvertisementType::NearbyInfo(NearbyInfoAdvertisementData {
+            status_flags: 0x01,
+            action_code: 0x07,
+            data_flags: 0x1c,
+            auth_tag: None,
+            action_data: vec![]
+        }))
+    );
+}
+
+#[cfg(feature = "handoff")]
+#[test]
+fn decodes_handoff_apple_id_at_the_same_offsets() {
+    for (status, apple_id) in [(0x08, false), (0x09, true)] {
+        let mut message = HANDOFF;
+        message[2] = status;
+        let data = HandoffAdvertisementData {
+            clipboard: true,
+            apple_id,
+            sequence_number: 0x1234,
+            auth_tag: 0xab,
+            encrypted_data: [0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09],
+        };
+        assert_eq!(
+            decode_apple(ADDRESS, &message),
+            Some(AdvertisementType::Handoff(data.clone()))
+        );
+        assert_eq!(data.octets(), message);
+    }
+}
+
+#[test]
+fn rejects_unknown_and_truncated_messages() {
+    assert_eq!(decode_apple(ADDRESS, &[]), None);
+    assert_eq!(decode_apple(ADDRESS, &[0xff, 0x00]), None);
+    #[cfg(feature = "findmy")]
+    assert_eq!(decode_apple(FINDMY_ADDRESS, &FINDMY[..10]), None);
+}
+
+#[cfg(feature = "nearby")]
+#[test]
+fn reencodes_modified_advertisement() {
+    let decoded = decode_apple(ADDRESS, &NEARBY_INFO).unwrap();
+    assert_eq!(decoded.octets(), NEARBY_INFO);
+    let modified = decoded.with_modified(|advertisement| {
+        if let AdvertisementType::NearbyInfo(data) = advertisement {
+            data.action_code = 0x0b;
+        }
+    });
+    let octets = modified.octets();
+    assert_eq!(octets.len(), NEARBY_INFO.len());
+    // Only the action code in the lower nibble of the first payload byte changed.
+    let changed: Vec<usize> = (0..octets.len())
+        .filter(|&i| octets[i] != NEARBY_INFO[i])
+        .collect();
+    assert_eq!(changed, vec![2]);
+    assert_eq!(octets[2], 0x1b);
+}
+
+#[cfg(feature = "nearby")]
+#[test]
+fn maps_nearby_info_device_class() {
+    use apple_ble::advertisement::DeviceClass;
+
+    for (data_flags, device_class) in [
+        (0x04, DeviceClass::IPhone),
+        (0x08, DeviceClass::IPad),
+        (0x0c, DeviceClass::Mac),
+        (0x10, DeviceClass::Watch),
+        (0x00, DeviceClass::Unknown),
+        (0x1c, DeviceClass::Unknown),
+        // Bits outside of the device class are ignored.
+        (0x05 | 0x20, DeviceClass::IPhone),
+    ] {
+        let data = NearbyInfoAdvertisementData {
+            status_flags: 0x01,
+            action_code: 0x07,
+            data_flags,
+            auth_tag: None,
+            action_data: vec![],
+        };
+        assert_eq!(data.device_class(), device_class, "{data_flags:#04x}");
+    }
+}
+
+#[cfg(all(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy",
+    feature = "nearby",
+    feature = "handoff",
+    feature = "tethering",
+    feature = "watch"
+))]
+fn assert_starts_with_message_type<T: AdvertisableData>(data: &T) {
+    assert_eq!(data.octets()[0], T::MESSAGE_TYPE, "{data:?}");
+}
+
+#[cfg(all(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy",
+    feature = "nearby",
+    feature = "handoff",
+    feature = "tethering",
+    feature = "watch"
+))]
+#[test]
+fn octets_start_with_message_type() {
+    let decoded = [
+        decode_apple(ADDRESS, &AIRDROP),
+        decode_apple(ADDRESS, &AIRPLAY_SOURCE),
+        decode_apple(ADDRESS, &AIRPLAY_TARGET),
+        decode_apple(ADDRESS, &AIRPRINT),
+        decode_apple(FINDMY_ADDRESS, &FINDMY),
+        decode_apple(ADDRESS, &NEARBY_INFO),
+        decode_apple(ADDRESS, &HANDOFF),
+        Some(AdvertisementType::TetheringSource(
+            TetheringSourceAdvertisementData {
+                version: 0x01,
+                credentials_available: true,
+                flags: 0x10,
+                battery_life: 0x64,
+                cell_service_type: 0x0007,
+                cell_service_strength: 0x03,
+            },
+        )),
+        decode_apple(ADDRESS, &WATCH_CONNECTION),
+    ];
+    for advertisement in decoded.iter().map(|decoded| decoded.as_ref().unwrap()) {
+        match advertisement {
+            AdvertisementType::AirDrop(data) => assert_starts_with_message_type(data),
+            AdvertisementType::AirPlaySource(data) => assert_starts_with_message_type(data),
+            AdvertisementType::AirPlayTarget(data) => assert_starts_with_message_type(data),
+            AdvertisementType::AirPrint(data) => assert_starts_with_message_type(data),
+            AdvertisementType::FindMy(data) => assert_starts_with_message_type(data),
+            AdvertisementType::FindMyNearby(data) => assert_starts_with_message_type(data),
+            AdvertisementType::NearbyInfo(data) => assert_starts_with_message_type(data),
+            AdvertisementType::Handoff(data) => assert_starts_with_message_type(data),
+            AdvertisementType::TetheringSource(data) => assert_starts_with_message_type(data),
+            AdvertisementType::WatchConnection(data) => assert_starts_with_message_type(data),
+            AdvertisementType::Unknown { .. } => panic!("{advertisement:?} wasn't decoded"),
+        }
+        assert_eq!(advertisement.octets()[0], advertisement.message_type());
+    }
+    let mut message_types: Vec<u8> = decoded
+        .iter()
+        .map(|decoded| decoded.as_ref().unwrap().message_type())
+        .collect();
+    message_types.sort();
+    let mut builtin = BUILTIN_MESSAGE_TYPES.to_vec();
+    builtin.sort();
+    assert_eq!(message_types, builtin);
+}
+
+#[cfg(feature = "airplay")]
+#[test]
+fn raw_company_id_is_little_endian() {
+    // Flags, followed by the AirPlay Target message as manufacturer data of company 0x004c.
+    let mut advertising_data = vec![0x02, 0x01, 0x06, 0x0b, 0xff, 0x4c, 0x00];
+    advertising_data.extend(AIRPLAY_TARGET);
+    let manufacturer_data = parse_manufacturer_data(&advertising_data);
+    assert_eq!(
+        manufacturer_data.keys().copied().collect::<Vec<_>>(),
+        vec![APPLE_MAGIC]
+    );
+    assert_eq!(APPLE_MAGIC, 0x004c);
+    assert_eq!(
+        AdvertisementType::from_advertising_data(ADDRESS, &advertising_data),
+        decode_apple(ADDRESS, &AIRPLAY_TARGET)
+    );
+    // Read big-endian, the same bytes would be company 0x4c00, which isn't Apple.
+    advertising_data[5..7].copy_from_slice(&[0x00, 0x4c]);
+    assert_eq!(
+        parse_manufacturer_data(&advertising_data)
+            .keys()
+            .copied()
+            .collect::<Vec<_>>(),
+        vec![0x4c00]
+    );
+    assert_eq!(
+        AdvertisementType::from_advertising_data(ADDRESS, &advertising_data),
+        None
+    );
+}
+
+#[cfg(feature = "airplay")]
+#[test]
+fn decodes_framed_and_unframed_manufacturer_data() {
+    let mut framed = vec![0x0b, 0xff, 0x4c, 0x00];
+    framed.extend(AIRPLAY_TARGET);
+    let unframed = AdvertisementType::from_manufacturer_data(ADDRESS, &AIRPLAY_TARGET);
+    assert!(unframed.is_some());
+    assert_eq!(
+        AdvertisementType::from_ad_structure(ADDRESS, &framed),
+        unframed
+    );
+    // The bare message isn't mistaken for a structure.
+    assert_eq!(
+        AdvertisementType::from_ad_structure(ADDRESS, &AIRPLAY_TARGET),
+        None
+    );
+    // Another company id, AD type or a length not covering the structure is rejected.
+    let mut other_company = framed.clone();
+    other_company[2..4].copy_from_slice(&[0x06, 0x00]);
+    assert_eq!(
+        AdvertisementType::from_ad_structure(ADDRESS, &other_company),
+        None
+    );
+    let mut other_type = framed.clone();
+    other_type[1] = 0x16;
+    assert_eq!(
+        AdvertisementType::from_ad_structure(ADDRESS, &other_type),
+        None
+    );
+    assert_eq!(
+        AdvertisementType::from_ad_structure(ADDRESS, &framed[..framed.len() - 1]),
+        None
+    );
+}
+
+#[cfg(all(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy",
+    feature = "nearby",
+    feature = "handoff",
+    feature = "tethering",
+    feature = "watch"
+))]
+fn assert_payload_len<T: AdvertisableData>(data: T, payload_len: u8) {
+    let octets = data.octets();
+    assert_eq!(octets.len(), 2 + payload_len as usize, "{data:?}");
+    assert_eq!(octets[1], payload_len, "{data:?}");
+}
+
+#[cfg(all(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy",
+    feature = "nearby",
+    feature = "handoff",
+    feature = "tethering",
+    feature = "watch"
+))]
+#[test]
+fn octets_match_payload_len() {
+    for decoded in [
+        decode_apple(ADDRESS, &AIRDROP),
+        decode_apple(ADDRESS, &AIRPLAY_SOURCE),
+        decode_apple(ADDRESS, &AIRPLAY_TARGET),
+        decode_apple(ADDRESS, &AIRPRINT),
+        decode_apple(FINDMY_ADDRESS, &FINDMY),
+        decode_apple(FINDMY_ADDRESS, &FINDMY_NEARBY),
+        decode_apple(ADDRESS, &HANDOFF),
+        decode_apple(ADDRESS, &TETHERING_SOURCE),
+    ] {
+        match decoded.unwrap() {
+            AdvertisementType::AirDrop(data) => {
+                assert_payload_len(data, AirDropAdvertisementData::PAYLOAD_LEN)
+            }
+            AdvertisementType::AirPlaySource(data) => {
+                assert_payload_len(data, AirPlaySourceAdvertisementData::PAYLOAD_LEN)
+            }
+            AdvertisementType::AirPlayTarget(data) => {
+                assert_payload_len(data, AirPlayTargetAdvertisementData::PAYLOAD_LEN)
+            }
+            AdvertisementType::AirPrint(data) => {
+                assert_payload_len(data, AirPrintAdvertisementData::PAYLOAD_LEN)
+            }
+            AdvertisementType::FindMy(data) => {
+                assert_payload_len(data, FindMyAdvertisementData::PAYLOAD_LEN)
+            }
+            AdvertisementType::FindMyNearby(data) => {
+                assert_payload_len(data, FindMyNearbyAdvertisementData::PAYLOAD_LEN)
+            }
+            AdvertisementType::Handoff(data) => {
+                assert_payload_len(data, HandoffAdvertisementData::PAYLOAD_LEN)
+            }
+            AdvertisementType::TetheringSource(data) => {
+                assert_payload_len(data, TetheringSourceAdvertisementData::PAYLOAD_LEN)
+            }
+            advertisement => panic!("{advertisement:?} has no fixed length"),
+        }
+    }
+    assert_eq!(AirDropAdvertisementData::PAYLOAD_LEN, 18);
+    assert_eq!(AirPrintAdvertisementData::PAYLOAD_LEN, 22);
+    assert_eq!(FindMyAdvertisementData::PAYLOAD_LEN, 25);
+    // One byte short of the declared length.
+    assert_eq!(decode_apple(ADDRESS, &AIRDROP[..19]), None);
+    assert_eq!(decode_apple(ADDRESS, &AIRPRINT[..23]), None);
+}
+
+#[cfg(feature = "airplay")]
+#[test]
+fn distinguishes_decode_failures() {
+    assert_eq!(
+        decode_manufacturer_data(ADDRESS, None, APPLE_MAGIC),
+        Ok(None)
+    );
+    assert_eq!(
+        decode_manufacturer_data(ADDRESS, Some(&HashMap::new()), APPLE_MAGIC),
+        Ok(None)
+    );
+    let other_companies = HashMap::from([(0x0075, vec![0x42, 0x04]), (0x0006, vec![0x01])]);
+    let error = decode_manufacturer_data(ADDRESS, Some(&other_companies), APPLE_MAGIC).unwrap_err();
+    assert_eq!(
+        error,
+        DecodeError::OtherCompanies {
+            company_ids: vec![0x0006, 0x0075]
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "The device only advertises manufacturer data of 0x0006 0x0075."
+    );
+    let truncated = HashMap::from([(APPLE_MAGIC, AIRPLAY_TARGET[..4].to_vec())]);
+    let error = decode_manufacturer_data(ADDRESS, Some(&truncated), APPLE_MAGIC).unwrap_err();
+    assert_eq!(
+        error,
+        DecodeError::Undecodable {
+            data: AIRPLAY_TARGET[..4].to_vec()
+        }
+    );
+    assert_eq!(
+        error.to_string(),
+        "The manufacturer data 09060307 couldn't be decoded."
+    );
+    let airplay_target = HashMap::from([(APPLE_MAGIC, AIRPLAY_TARGET.to_vec())]);
+    assert_eq!(
+        decode_manufacturer_data(ADDRESS, Some(&airplay_target), APPLE_MAGIC),
+        Ok(decode_apple(ADDRESS, &AIRPLAY_TARGET))
+    );
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn watch_connection_round_trip() {
+    let decoded = decode_apple(ADDRESS, &WATCH_CONNECTION);
+    assert_eq!(
+        decoded,
+        Some(AdvertisementType::WatchConnection(
+            WatchConnectionAdvertisementData {
+                status: 0x13,
+                data: vec![0x00, 0x39, 0x2e],
+            }
+        ))
+    );
+    assert_eq!(decoded.unwrap().octets(), WATCH_CONNECTION);
+    let status_only = WatchConnectionAdvertisementData {
+        status: 0x01,
+        data: vec![],
+    };
+    assert_eq!(
+        WatchConnectionAdvertisementData::try_from(status_only.octets()).unwrap(),
+        status_only
+    );
+    // The length byte promises more than the message carries.
+    assert_eq!(decode_apple(ADDRESS, &WATCH_CONNECTION[..5]), None);
+    assert_eq!(decode_apple(ADDRESS, &[0x0b, 0x00]), None);
+}
+
+/// Every prefix of a decodable message is rejected instead of being read out of bounds.
+#[cfg(any(
+    feature = "airdrop",
+    feature = "airplay",
+    feature = "airprint",
+    feature = "findmy",
+    feature = "nearby",
+    feature = "handoff",
+    feature = "tethering",
+    feature = "watch"
+))]
+fn assert_rejects_truncated(address: Address, message: &[u8]) {
+    assert!(decode_apple(address, message).is_some(), "{message:02x?}");
+    for len in 0..message.len() {
+        assert_eq!(
+            decode_apple(address, &message[..len]),
+            None,
+            "{:02x?}",
+            &message[..len]
+        );
+    }
+}
+
+#[cfg(feature = "airdrop")]
+#[test]
+fn rejects_truncated_airdrop() {
+    assert_rejects_truncated(ADDRESS, &AIRDROP);
+}
+
+#[cfg(feature = "airplay")]
+#[test]
+fn rejects_truncated_airplay_source() {
+    assert_rejects_truncated(ADDRESS, &AIRPLAY_SOURCE);
+}
+
+#[cfg(feature = "airplay")]
+#[test]
+fn rejects_truncated_airplay_target() {
+    assert_rejects_truncated(ADDRESS, &AIRPLAY_TARGET);
+}
+
+#[cfg(feature = "airprint")]
+#[test]
+fn rejects_truncated_airprint() {
+    assert_rejects_truncated(ADDRESS, &AIRPRINT);
+}
+
+#[cfg(feature = "findmy")]
+#[test]
+fn rejects_truncated_findmy() {
+    // The trailing hint isn't decoded, so the message already ends after the key bits.
+    assert_rejects_truncated(FINDMY_ADDRESS, &FINDMY);
+    assert_rejects_truncated(FINDMY_ADDRESS, &FINDMY_NEARBY);
+}
+
+#[cfg(feature = "nearby")]
+#[test]
+fn rejects_truncated_nearby_info() {
+    assert_rejects_truncated(ADDRESS, &NEARBY_INFO);
+    assert_rejects_truncated(ADDRESS, &NEARBY_INFO_SHORT);
+}
+
+#[cfg(feature = "handoff")]
+#[test]
+fn rejects_truncated_handoff() {
+    assert_rejects_truncated(ADDRESS, &HANDOFF);
+}
+
+#[cfg(feature = "tethering")]
+#[test]
+fn rejects_truncated_tethering_source() {
+    assert_rejects_truncated(ADDRESS, &TETHERING_SOURCE);
+}
+
+#[cfg(feature = "watch")]
+#[test]
+fn rejects_truncated_watch_connection() {
+    assert_rejects_truncated(ADDRESS, &WATCH_CONNECTION);
+}
@@ -0,0 +1,82 @@
+use apple_ble::error::AppleBleError;
+use apple_ble::session::Privileges;
+use std::error::Error;
+
+#[test]
+fn permission_denied_gives_guidance() {
+    let privileges = Privileges {
+        cap_net_admin: false,
+        terminal: false,
+        passwordless_sudo: false,
+    };
+    assert!(!privileges.can_escalate());
+    let error: Box<dyn Error> = privileges.ensure().unwrap_err().into();
+    let message = error.to_string();
+    assert!(message.starts_with("Permission denied: no terminal to prompt for the sudo password."));
+    assert!(message.contains("CAP_NET_ADMIN"));
+    assert_eq!(
+        error.downcast_ref::<AppleBleError>(),
+        Some(&AppleBleError::PermissionDenied(
+            "no terminal to prompt for the sudo password".to_string()
+        ))
+    );
+    assert_eq!(
+        Privileges {
+            cap_net_admin: true,
+            ..privileges
+        }
+        .ensure(),
+        Ok(())
+    );
+}
+
+#[test]
+fn not_received_names_timeout() {
+    let error = AppleBleError::NotReceived {
+        timeout: std::time::Duration::from_secs(10),
+    };
+    assert_eq!(
+        error.to_string(),
+        "The advertisement wasn't received within 10s."
+    );
+}
+
+#[test]
+fn registration_names_advertisement_and_operation() {
+    let error = AppleBleError::Registration {
+        advertisement: "FindMy".to_string(),
+        operation: apple_ble::error::Operation::Advertise,
+        reason: "org.bluez.Error.Failed".to_string(),
+    };
+    assert_eq!(
+        error.to_string(),
+        "Failed to register FindMy advertisement: org.bluez.Error.Failed"
+    );
+    let error = AppleBleError::Registration {
+        advertisement: "FindMy".to_string(),
+        operation: apple_ble::error::Operation::PrepareAdapter,
+        reason: "bdaddr failed".to_string(),
+    };
+    assert_eq!(
+        error.to_string(),
+        "Failed to prepare the adapter for FindMy advertisement: bdaddr failed"
+    );
+}
+
+#[test]
+fn missing_adapter_is_no_adapter() {
+    let error = AppleBleError::from_adapter_lookup(bluer::Error {
+        kind: bluer::ErrorKind::NotFound,
+        message: String::new(),
+    });
+    assert_eq!(error, AppleBleError::NoAdapter);
+    assert!(error.to_string().contains("rfkill"));
+    assert!(error.to_string().contains("bluetoothctl"));
+    assert!(matches!(
+        AppleBleError::from_adapter_lookup(bluer::Error {
+            kind: bluer::ErrorKind::NotReady,
+            message: "Resource Not Ready".to_string(),
+        }),
+        AppleBleError::Bluetooth(_)
+    ));
+}
@@ -0,0 +1,180 @@
+use apple_ble::identity::{DeviceIdentity, IdentityBackend, Impersonation};
+#[cfg(feature = "disable_afit")]
+use async_trait::async_trait;
+use bluer::adv::Advertisement;
+use bluer::Address;
+use std::{
+    error::Error,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::test;
+
+#[derive(Debug, PartialEq)]
+struct AdapterState {
+    alias: String,
+    address: Address,
+    changes: Vec<&'static str>,
+}
+
+struct MockBackend {
+    state: Arc<Mutex<AdapterState>>,
+    fail_alias: bool,
+}
+impl MockBackend {
+    fn new(state: &Arc<Mutex<AdapterState>>) -> Self {
+        MockBackend {
+            state: state.clone(),
+            fail_alias: false,
+        }
+    }
+}
+#[cfg_attr(feature = "disable_afit", async_trait)]
+impl IdentityBackend for MockBackend {
+    async fn alias(&self) -> Result<String, Box<dyn Error>> {
+        Ok(self.state.lock().unwrap().alias.clone())
+    }
+    async fn set_alias(&mut self, alias: &str) -> Result<(), Box<dyn Error>> {
+        if self.fail_alias {
+            return Err("the alias can't be set".into());
+        }
+        let mut state = self.state.lock().unwrap();
+        state.alias = alias.to_string();
+        state.changes.push("alias");
+        Ok(())
+    }
+    async fn address(&self) -> Result<Address, Box<dyn Error>> {
+        Ok(self.state.lock().unwrap().address)
+    }
+    async fn set_address(&mut self, address: Address) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        state.address = address;
+        state.changes.push("address");
+        Ok(())
+    }
+    async fn restore_address(&mut self, address: Address) -> Result<(), Box<dyn Error>> {
+        let mut state = self.state.lock().unwrap();
+        state.address = address;
+        state.changes.push("restored address");
+        Ok(())
+    }
+}
+
+const ORIGINAL_ADDRESS: Address = Address([0x00, 0x1a, 0x7d, 0xda, 0x71, 0x13]);
+const SPOOFED_ADDRESS: Address = Address([0x40, 0x11, 0x22, 0x33, 0x44, 0x55]);
+
+fn adapter_state() -> Arc<Mutex<AdapterState>> {
+    Arc::new(Mutex::new(AdapterState {
+        alias: "raspberrypi".to_string(),
+        address: ORIGINAL_ADDRESS,
+        changes: vec![],
+    }))
+}
+
+// These run on the current thread runtime, which the session backend has to support as well.
+#[test]
+async fn impersonation_applies_and_reverts_identity() -> Result<(), Box<dyn Error>> {
+    let state = adapter_state();
+    let identity = DeviceIdentity {
+        name: "Living Room".to_string(),
+        address: Some(SPOOFED_ADDRESS),
+        appearance: Some(0x0080),
+    };
+    let impersonation = Impersonation::apply(MockBackend::new(&state), &identity).await?;
+    assert_eq!(impersonation.original_address(), Some(ORIGINAL_ADDRESS));
+    assert_eq!(
+        *state.lock().unwrap(),
+        AdapterState {
+            alias: "Living Room".to_string(),
+            address: SPOOFED_ADDRESS,
+            changes: vec!["address", "alias"],
+        }
+    );
+    assert_eq!(
+        identity.apply(Advertisement::default()).appearance,
+        Some(0x0080)
+    );
+
+    impersonation.revert().await?;
+    assert_eq!(
+        *state.lock().unwrap(),
+        AdapterState {
+            alias: "raspberrypi".to_string(),
+            address: ORIGINAL_ADDRESS,
+            changes: vec!["address", "alias", "alias", "restored address"],
+        }
+    );
+    Ok(())
+}
+
+#[test]
+async fn dropping_impersonation_reverts_identity() -> Result<(), Box<dyn Error>> {
+    let state = adapter_state();
+    let identity = DeviceIdentity {
+        name: "Living Room".to_string(),
+        address: Some(SPOOFED_ADDRESS),
+        appearance: None,
+    };
+    drop(Impersonation::apply(MockBackend::new(&state), &identity).await?);
+    // The revert runs in the background.
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while state.lock().unwrap().changes.len() < 4 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    assert_eq!(
+        *state.lock().unwrap(),
+        AdapterState {
+            alias: "raspberrypi".to_string(),
+            address: ORIGINAL_ADDRESS,
+            changes: vec!["address", "alias", "alias", "restored address"],
+        }
+    );
+    Ok(())
+}
+
+#[test]
+async fn failed_impersonation_restores_address() -> Result<(), Box<dyn Error>> {
+    let state = adapter_state();
+    let identity = DeviceIdentity {
+        name: "Living Room".to_string(),
+        address: Some(SPOOFED_ADDRESS),
+        appearance: None,
+    };
+    let backend = MockBackend {
+        fail_alias: true,
+        ..MockBackend::new(&state)
+    };
+    assert!(Impersonation::apply(backend, &identity).await.is_err());
+    assert_eq!(
+        *state.lock().unwrap(),
+        AdapterState {
+            alias: "raspberrypi".to_string(),
+            address: ORIGINAL_ADDRESS,
+            changes: vec!["address", "restored address"],
+        }
+    );
+    Ok(())
+}
+
+#[test]
+async fn impersonation_without_address_keeps_it() -> Result<(), Box<dyn Error>> {
+    let state = adapter_state();
+    let identity = DeviceIdentity::new("Living Room");
+    let impersonation = Impersonation::apply(MockBackend::new(&state), &identity).await?;
+    assert_eq!(impersonation.original_address(), None);
+    assert_eq!(
+        identity
+            .apply(Advertisement {
+                appearance: Some(0x0040),
+                ..Default::default()
+            })
+            .appearance,
+        Some(0x0040)
+    );
+    impersonation.revert().await?;
+    assert_eq!(state.lock().unwrap().changes, vec!["alias", "alias"]);
+    assert_eq!(state.lock().unwrap().address, ORIGINAL_ADDRESS);
+    Ok(())
+}
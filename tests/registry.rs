@@ -0,0 +1,81 @@
+use apple_ble::advertisement::AdvertisementType;
+#[cfg(feature = "airplay")]
+use apple_ble::advertisement::AirPlayTargetAdvertisementData;
+use apple_ble::registry::DecoderRegistry;
+use bluer::Address;
+#[cfg(feature = "airplay")]
+use std::net::Ipv4Addr;
+
+const ADDRESS: Address = Address([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]);
+
+#[cfg(feature = "airplay")]
+#[test]
+fn decodes_builtin_types() {
+    assert_eq!(
+        DecoderRegistry::default().decode(ADDRESS, &[0x09, 0x06, 0x03, 0x07, 192, 168, 0, 10]),
+        Some(AdvertisementType::AirPlayTarget(
+            AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 0, 10))
+        ))
+    );
+    assert_eq!(DecoderRegistry::empty().decode(ADDRESS, &[]), None);
+}
+
+#[test]
+fn decodes_custom_type() {
+    let registry = DecoderRegistry::default();
+    assert_eq!(
+        registry.decode(ADDRESS, &[0x42, 0x01, 0xff]),
+        Some(AdvertisementType::Unknown {
+            message_type: 0x42,
+            data: vec![0x42, 0x01, 0xff]
+        })
+    );
+    registry.register(0x42, |_, data| {
+        Some(AdvertisementType::Unknown {
+            message_type: 0x42,
+            data: data.get(2..)?.to_vec(),
+        })
+    });
+    assert_eq!(
+        registry.decode(ADDRESS, &[0x42, 0x01, 0xff]),
+        Some(AdvertisementType::Unknown {
+            message_type: 0x42,
+            data: vec![0xff]
+        })
+    );
+    // Custom decoders take precedence over the built-in ones.
+    registry.register(0x09, |_, _| None);
+    assert_eq!(
+        registry.decode(ADDRESS, &[0x09, 0x06, 0x03, 0x07, 192, 168, 0, 10]),
+        None
+    );
+}
+
+#[cfg(all(feature = "nearby", feature = "handoff"))]
+#[test]
+fn decodes_composite_messages() {
+    let nearby_info = [0x10, 0x05, 0x17, 0x1c, 0xaa, 0xbb, 0xcc];
+    let handoff = [
+        0x0c, 0x0e, 0x08, 0x12, 0x34, 0xab, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08,
+        0x09,
+    ];
+    let truncated = [0x10, 0x05, 0x17];
+    let manufacturer_data = [&nearby_info[..], &handoff, &truncated].concat();
+    let decoded = DecoderRegistry::default().decode_all(ADDRESS, &manufacturer_data);
+    assert_eq!(decoded.len(), 2);
+    let mut message_types = Vec::new();
+    for message in decoded.clone() {
+        message_types.push(message.message_type());
+    }
+    assert_eq!(message_types, vec![0x10, 0x0c]);
+
+    let handoff = decoded.filter_type(0x0c);
+    assert_eq!(handoff.len(), 1);
+    match handoff.into_iter().next() {
+        Some(AdvertisementType::Handoff(data)) => {
+            assert!(data.clipboard);
+            assert_eq!(data.sequence_number, 0x1234);
+        }
+        other => panic!("Expected Handoff, got {other:?}"),
+    }
+}
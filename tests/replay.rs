@@ -0,0 +1,82 @@
+#![cfg(all(feature = "airplay", feature = "findmy"))]
+use apple_ble::advertisement::{
+    AdvertisementType, AirPlaySourceAdvertisementData, FindMyAdvertisementData,
+};
+use apple_ble::replay::replay_with;
+use bluer::Address;
+use std::{convert::Infallible, error::Error, time::Duration};
+use tokio::{test, time::Instant};
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn replays_in_order_with_timing() -> Result<(), Box<dyn Error>> {
+    let source = AdvertisementType::AirPlaySource(AirPlaySourceAdvertisementData { flags: 0x00 });
+    let first_key = FindMyAdvertisementData::new([0x11; 28]);
+    let second_key = FindMyAdvertisementData::new([0x22; 28]);
+    let events = vec![
+        (Duration::ZERO, source.clone()),
+        (
+            Duration::from_millis(100),
+            AdvertisementType::FindMy(first_key.clone()),
+        ),
+        (
+            Duration::from_millis(200),
+            AdvertisementType::FindMy(first_key.clone()),
+        ),
+        (
+            Duration::from_millis(300),
+            AdvertisementType::FindMy(second_key.clone()),
+        ),
+    ];
+    let start = Instant::now();
+    let mut emitted: Vec<(Duration, AdvertisementType, Option<Address>)> = Vec::new();
+    let last = replay_with(events.clone(), |advertisement, address| {
+        emitted.push((start.elapsed(), advertisement, address));
+        let handle = emitted.len();
+        async move { Ok::<_, Infallible>(handle) }
+    })
+    .await?;
+    assert_eq!(last, Some(4));
+    assert_eq!(
+        emitted
+            .iter()
+            .map(|(_, advertisement, address)| (advertisement.clone(), *address))
+            .collect::<Vec<_>>(),
+        vec![
+            (source, None),
+            (
+                AdvertisementType::FindMy(first_key.clone()),
+                Some(first_key.address())
+            ),
+            // The key didn't change, so neither does the address.
+            (AdvertisementType::FindMy(first_key), None),
+            (
+                AdvertisementType::FindMy(second_key.clone()),
+                Some(second_key.address())
+            ),
+        ]
+    );
+    for ((elapsed, ..), (offset, _)) in emitted.iter().zip(&events) {
+        assert!(elapsed >= offset, "{elapsed:?} is before {offset:?}");
+        assert!(
+            *elapsed < *offset + Duration::from_millis(50),
+            "{elapsed:?} is too late for {offset:?}"
+        );
+    }
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn replay_stops_at_first_error() {
+    let source = AdvertisementType::AirPlaySource(AirPlaySourceAdvertisementData { flags: 0x00 });
+    let mut advertised = 0;
+    let result = replay_with(
+        vec![(Duration::ZERO, source.clone()), (Duration::ZERO, source)],
+        |_, _| {
+            advertised += 1;
+            async { Err::<(), _>("Advertising failed.") }
+        },
+    )
+    .await;
+    assert_eq!(result, Err("Advertising failed."));
+    assert_eq!(advertised, 1);
+}
@@ -0,0 +1,391 @@
+#![cfg(feature = "airplay")]
+use apple_ble::advertisement::{AdvertisementType, AirPlayTargetAdvertisementData};
+use apple_ble::scan::{
+    decode_addresses, dedup_debounce, manufacturer_data_updates, message_type_histogram,
+    read_unknown_messages, rssi_updates, wait_for_device, write_unknown_messages, ScanRecord,
+};
+use bluer::{AdapterEvent, Address, DeviceEvent, DeviceProperty};
+use futures::{stream, StreamExt};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    net::Ipv4Addr,
+    time::{Duration, SystemTime},
+};
+use tokio::test;
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn rssi_updates_end_on_removal() -> Result<(), Box<dyn Error>> {
+    let address = Address::new([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06]);
+    let device_events = stream::iter([
+        DeviceEvent::PropertyChanged(DeviceProperty::Rssi(-70)),
+        DeviceEvent::PropertyChanged(DeviceProperty::Connected(false)),
+        DeviceEvent::PropertyChanged(DeviceProperty::Rssi(-60)),
+    ])
+    .chain(stream::pending());
+    let adapter_events = stream::iter([AdapterEvent::DeviceRemoved(Address::any())]).chain(
+        stream::once(async move {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            AdapterEvent::DeviceRemoved(address)
+        }),
+    );
+    let rssi: Vec<i16> = rssi_updates(address, device_events, adapter_events)
+        .collect()
+        .await;
+    assert_eq!(rssi, vec![-70, -60]);
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn waits_for_device() -> Result<(), Box<dyn Error>> {
+    let address = Address::new([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06]);
+    let events = stream::iter([
+        AdapterEvent::DeviceAdded(Address::any()),
+        AdapterEvent::DeviceAdded(address),
+    ]);
+    let found = wait_for_device(
+        address,
+        events,
+        |added| async move { (added == address).then_some(-42) },
+        Duration::from_secs(1),
+    )
+    .await;
+    assert_eq!(found, Some((address, -42)));
+
+    let found = wait_for_device(
+        address,
+        stream::pending(),
+        |_| async { Some(-42) },
+        Duration::from_millis(50),
+    )
+    .await;
+    assert_eq!(found, None);
+    Ok(())
+}
+
+fn airplay_target(last_octet: u8) -> AdvertisementType {
+    AdvertisementType::AirPlayTarget(AirPlayTargetAdvertisementData::new(Ipv4Addr::new(
+        192, 168, 1, last_octet,
+    )))
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn dedup_debounce_suppresses_duplicates() -> Result<(), Box<dyn Error>> {
+    let first = Address::new([0x01; 6]);
+    let second = Address::new([0x02; 6]);
+    let advertisements = stream::iter([
+        (first, airplay_target(1)),
+        (first, airplay_target(1)),
+        (second, airplay_target(1)),
+        (first, airplay_target(2)),
+        (first, airplay_target(2)),
+        (first, airplay_target(1)),
+    ]);
+    let deduplicated: Vec<_> = dedup_debounce(advertisements, Duration::from_secs(3600))
+        .collect()
+        .await;
+    assert_eq!(
+        deduplicated,
+        vec![
+            (first, airplay_target(1)),
+            (second, airplay_target(1)),
+            (first, airplay_target(2)),
+            (first, airplay_target(1)),
+        ]
+    );
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn dedup_debounce_passes_duplicates_after_window() -> Result<(), Box<dyn Error>> {
+    let address = Address::new([0x01; 6]);
+    let advertisements =
+        stream::iter([airplay_target(1), airplay_target(1)]).then(|advertisement| async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            (address, advertisement)
+        });
+    let deduplicated: Vec<_> = dedup_debounce(advertisements, Duration::from_millis(10))
+        .collect()
+        .await;
+    assert_eq!(deduplicated.len(), 2);
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn counts_message_types() -> Result<(), Box<dyn Error>> {
+    let address = Address::new([0x01; 6]);
+    let advertisements = stream::iter([
+        (address, airplay_target(1)),
+        (
+            Address::new([0x02; 6]),
+            AdvertisementType::AirPlaySource(Default::default()),
+        ),
+        (
+            address,
+            AdvertisementType::AirPlaySource(Default::default()),
+        ),
+        (address, airplay_target(2)),
+    ])
+    .chain(stream::pending());
+    let histogram =
+        message_type_histogram(address, advertisements, Duration::from_millis(50)).await;
+    assert_eq!(histogram, HashMap::from([(0x09, 2), (0x0a, 1)]));
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn decode_addresses_skips_undecodable() -> Result<(), Box<dyn Error>> {
+    let known = Address::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]);
+    let other = Address::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbd]);
+    let advertisement = AdvertisementType::AirPlayTarget(AirPlayTargetAdvertisementData::new(
+        Ipv4Addr::new(192, 168, 1, 2),
+    ));
+    let decoded = decode_addresses([other, known], |address| {
+        let advertisement = advertisement.clone();
+        async move { (address == known).then_some(advertisement) }
+    })
+    .await;
+    assert_eq!(decoded, vec![(known, advertisement)]);
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn decodes_manufacturer_data_changes() -> Result<(), Box<dyn Error>> {
+    let address = Address::new([0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc]);
+    let manufacturer_data = |last_octet: u8| {
+        DeviceEvent::PropertyChanged(DeviceProperty::ManufacturerData(HashMap::from([(
+            0x4c,
+            vec![0x09, 0x06, 0x03, 0x07, 192, 168, 1, last_octet],
+        )])))
+    };
+    let device_events = stream::iter([
+        manufacturer_data(1),
+        DeviceEvent::PropertyChanged(DeviceProperty::Rssi(-60)),
+        DeviceEvent::PropertyChanged(DeviceProperty::ManufacturerData(HashMap::from([(
+            0xffff,
+            vec![0x09, 0x06, 0x03, 0x07, 192, 168, 1, 3],
+        )]))),
+        manufacturer_data(2),
+    ]);
+    let decoded: Vec<AdvertisementType> = manufacturer_data_updates(address, 0x4c, device_events)
+        .collect()
+        .await;
+    assert_eq!(decoded, vec![airplay_target(1), airplay_target(2)]);
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn writes_ndjson_records() -> Result<(), Box<dyn Error>> {
+    use apple_ble::scan::{write_ndjson, ScanRecord};
+    use std::time::SystemTime;
+
+    let timestamp = SystemTime::UNIX_EPOCH + Duration::from_millis(1_700_000_000_123);
+    let records = [
+        ScanRecord {
+            address: Address::new([0x01; 6]),
+            advertisement: airplay_target(2),
+            rssi: Some(-60),
+            timestamp,
+        },
+        ScanRecord {
+            address: Address::new([0x02; 6]),
+            advertisement: airplay_target(3),
+            rssi: None,
+            timestamp,
+        },
+    ];
+    let mut buffer = Vec::new();
+    write_ndjson(stream::iter(records), &mut buffer).await?;
+
+    let output = String::from_utf8(buffer)?;
+    let lines: Vec<serde_json::Value> = output
+        .lines()
+        .map(serde_json::from_str)
+        .collect::<Result<_, _>>()?;
+    assert_eq!(lines.len(), 2);
+    assert_eq!(lines[0]["type"], "AirPlayTarget");
+    assert_eq!(lines[0]["ip_address"], "192.168.1.2");
+    assert_eq!(lines[0]["address"], "01:01:01:01:01:01");
+    assert_eq!(lines[0]["rssi"], -60);
+    assert_eq!(lines[0]["timestamp"], 1_700_000_000_123_u64);
+    assert_eq!(lines[1]["ip_address"], "192.168.1.3");
+    assert!(lines[1]["rssi"].is_null());
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn advertisement_events_report_type_changes() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::AirPlaySourceAdvertisementData;
+    use apple_ble::scan::{advertisement_events, AdvertisementEvent};
+
+    let first = Address::new([0x01; 6]);
+    let second = Address::new([0x02; 6]);
+    let source = AdvertisementType::AirPlaySource(AirPlaySourceAdvertisementData::default());
+    let events: Vec<_> = advertisement_events(stream::iter([
+        (first, airplay_target(2)),
+        (second, source.clone()),
+        (first, airplay_target(3)),
+        (first, source.clone()),
+    ]))
+    .collect()
+    .await;
+    assert_eq!(
+        events,
+        vec![
+            AdvertisementEvent::Advertised {
+                address: first,
+                advertisement: airplay_target(2),
+            },
+            AdvertisementEvent::Advertised {
+                address: second,
+                advertisement: source.clone(),
+            },
+            // Changed data of the same message type isn't a transition.
+            AdvertisementEvent::Advertised {
+                address: first,
+                advertisement: airplay_target(3),
+            },
+            AdvertisementEvent::TypeChanged {
+                address: first,
+                from: airplay_target(3),
+                to: source,
+            },
+        ]
+    );
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn decode_events_skips_non_apple_devices() -> Result<(), Box<dyn Error>> {
+    use apple_ble::scan::decode_events;
+
+    let apple = Address::new([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06]);
+    let other = Address::new([0xd4, 0x11, 0x22, 0x33, 0x44, 0x55]);
+    let airplay_target = AdvertisementType::AirPlayTarget(AirPlayTargetAdvertisementData::new(
+        Ipv4Addr::new(192, 168, 1, 2),
+    ));
+    let events = stream::iter([
+        AdapterEvent::DeviceAdded(other),
+        AdapterEvent::DeviceAdded(apple),
+        AdapterEvent::DeviceRemoved(apple),
+        AdapterEvent::DeviceAdded(apple),
+    ]);
+    let decoded: Vec<_> = decode_events(events, |address| {
+        let advertisement = (address == apple).then(|| airplay_target.clone());
+        async move { advertisement }
+    })
+    .collect()
+    .await;
+    assert_eq!(
+        decoded,
+        vec![(apple, airplay_target.clone()), (apple, airplay_target)]
+    );
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn decode_events_with_changes_follows_devices() -> Result<(), Box<dyn Error>> {
+    use apple_ble::scan::decode_events_with_changes;
+    use std::sync::Mutex;
+
+    let apple = Address::new([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06]);
+    let other = Address::new([0xd4, 0x11, 0x22, 0x33, 0x44, 0x55]);
+    let manufacturer_data = |company_id: u16, last_octet: u8| {
+        DeviceEvent::PropertyChanged(DeviceProperty::ManufacturerData(HashMap::from([(
+            company_id,
+            vec![0x09, 0x06, 0x03, 0x07, 192, 168, 1, last_octet],
+        )])))
+    };
+    let events = stream::iter([
+        AdapterEvent::DeviceAdded(other),
+        AdapterEvent::DeviceAdded(apple),
+        AdapterEvent::DeviceRemoved(apple),
+        AdapterEvent::DeviceAdded(apple),
+    ]);
+    let followed = Mutex::new(vec![]);
+    let decoded: Vec<_> = decode_events_with_changes(
+        events,
+        0x4c,
+        |address| {
+            let advertisement = (address == apple).then(|| airplay_target(1));
+            async move { advertisement }
+        },
+        |address| {
+            followed.lock().unwrap().push(address);
+            let device_events = if address == apple {
+                vec![
+                    manufacturer_data(0x4c, 2),
+                    DeviceEvent::PropertyChanged(DeviceProperty::Rssi(-60)),
+                    manufacturer_data(0x4c, 3),
+                ]
+            } else {
+                vec![manufacturer_data(0xffff, 4)]
+            };
+            async move { Some(stream::iter(device_events)) }
+        },
+    )
+    .collect()
+    .await;
+
+    // Adding the device again decodes it again, but doesn't follow it twice.
+    assert_eq!(*followed.lock().unwrap(), vec![other, apple]);
+    assert_eq!(
+        decoded
+            .iter()
+            .filter(|decoded| **decoded == (apple, airplay_target(1)))
+            .count(),
+        2
+    );
+    let changes: Vec<_> = decoded
+        .into_iter()
+        .filter(|decoded| *decoded != (apple, airplay_target(1)))
+        .collect();
+    assert_eq!(
+        changes,
+        vec![(apple, airplay_target(2)), (apple, airplay_target(3))]
+    );
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn unknown_messages_are_recorded_once() -> Result<(), Box<dyn Error>> {
+    let unknown = AdvertisementType::Unknown {
+        message_type: 0x42,
+        data: vec![0x42, 0x02, 0xde, 0xad],
+    };
+    let record = |address: [u8; 6], advertisement: AdvertisementType| ScanRecord {
+        address: Address::new(address),
+        advertisement,
+        rssi: Some(-60),
+        timestamp: SystemTime::UNIX_EPOCH + Duration::from_millis(1000),
+    };
+    let known = AdvertisementType::AirPlayTarget(AirPlayTargetAdvertisementData::new(
+        Ipv4Addr::new(192, 168, 1, 2),
+    ));
+    let records = stream::iter([
+        record([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06], unknown.clone()),
+        record([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06], known),
+        record([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06], unknown.clone()),
+        record([0xd4, 0x02, 0x03, 0x04, 0x05, 0x06], unknown.clone()),
+    ]);
+    let mut log = Vec::new();
+    let mut seen = HashSet::new();
+    assert_eq!(
+        write_unknown_messages(records, &mut log, &mut seen).await?,
+        1
+    );
+    assert_eq!(
+        String::from_utf8(log.clone())?,
+        "1000\tC1:02:03:04:05:06\t-60\t4202dead\n"
+    );
+
+    // A later run seeded from the file doesn't record the message again.
+    let mut seen = read_unknown_messages(log.as_slice())?;
+    let records = stream::iter([record([0xc1, 0x02, 0x03, 0x04, 0x05, 0x06], unknown)]);
+    assert_eq!(
+        write_unknown_messages(records, &mut log, &mut seen).await?,
+        0
+    );
+    Ok(())
+}
@@ -1,9 +1,643 @@
+use apple_ble::session::SelfTestReport;
 use std::error::Error;
 use tokio::test;
 
 #[test(flavor = "multi_thread", worker_threads = 1)]
-async fn creates_session() -> Result<(), Box<dyn Error>>{
+async fn creates_session() -> Result<(), Box<dyn Error>> {
     let session = apple_ble::session::Session::new().await;
     assert!(session.is_ok());
     Ok(())
-}
\ No newline at end of file
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn self_test_reports_problems() -> Result<(), Box<dyn Error>> {
+    let report = SelfTestReport {
+        adapter_present: true,
+        adapter_powered: false,
+        advertising_supported: true,
+        bdaddr_available: false,
+        btmgmt_available: true,
+        can_escalate: true,
+    };
+    assert!(!report.is_ok());
+    assert_eq!(
+        report.problems(),
+        vec!["The adapter isn't powered.", "bdaddr isn't on PATH."]
+    );
+
+    let session = apple_ble::session::Session::new().await?;
+    let report = session.self_test().await;
+    assert!(report.adapter_present);
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn loopback_verify_between_adapters() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        AdvertisementType, AirPlayTargetAdvertisement, AirPlayTargetAdvertisementData,
+    };
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    let names = bluer::Session::new().await?.adapter_names().await?;
+    if names.len() < 2 {
+        return Ok(());
+    }
+    let mut session = apple_ble::session::Session::with_adapter(&names[0]).await?;
+    let other = apple_ble::session::Session::with_adapter(&names[1]).await?;
+    let user_data = AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 0, 10));
+    let received = session
+        .loopback_verify::<_, AirPlayTargetAdvertisement>(
+            &other,
+            &user_data,
+            Duration::from_secs(10),
+        )
+        .await?;
+    assert_eq!(received, AdvertisementType::AirPlayTarget(user_data));
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn advertiser_limit_rejects_and_queues() -> Result<(), Box<dyn Error>> {
+    use apple_ble::error::AppleBleError;
+    use apple_ble::session::AdvertiserLimit;
+    use std::time::Duration;
+
+    let limit = AdvertiserLimit::new(2);
+    let first = limit.try_acquire()?;
+    let _second = limit.try_acquire()?;
+    assert_eq!(limit.available(), 0);
+    assert_eq!(
+        limit.try_acquire().unwrap_err(),
+        AppleBleError::TooManyAdvertisers { max: 2 }
+    );
+
+    let queued = tokio::spawn({
+        let limit = limit.clone();
+        async move { limit.acquire().await }
+    });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(!queued.is_finished());
+    drop(first);
+    let _third = tokio::time::timeout(Duration::from_secs(1), queued).await??;
+    assert_eq!(limit.available(), 0);
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn advertised_identity_matches_alias() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        Advertisable, AdvertisementParams, AirPlayTargetAdvertisement,
+        AirPlayTargetAdvertisementData,
+    };
+    use std::net::Ipv4Addr;
+
+    let mut session = apple_ble::session::Session::new().await?;
+    let original_alias = session.adapter.alias().await?;
+    session.set_advertised_identity("Living Room").await?;
+    let advertisement = AirPlayTargetAdvertisement::assemble_with_params(
+        &mut session,
+        &AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 1, 2)),
+        &AdvertisementParams::default(),
+    )?;
+    let alias = session.adapter.alias().await?;
+    session.adapter.set_alias(original_alias).await?;
+    assert_eq!(alias, "Living Room");
+    assert_eq!(advertisement.local_name, Some(alias));
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn registers_on_all_adapters() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{AirPlayTargetAdvertisement, AirPlayTargetAdvertisementData};
+    use apple_ble::session::register_on_all;
+    use std::net::Ipv4Addr;
+
+    let mut sessions = apple_ble::session::Session::new_all().await?;
+    if sessions.len() < 2 {
+        return Ok(());
+    }
+    let names: Vec<String> = sessions
+        .iter()
+        .map(|session| session.adapter.name().to_string())
+        .collect();
+    let registration = register_on_all::<_, AirPlayTargetAdvertisement>(
+        &mut sessions,
+        &AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 1, 2)),
+    )
+    .await;
+    assert!(registration.failures.is_empty());
+    assert_eq!(registration.succeeded(), names);
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn discovery_stops_with_scan() -> Result<(), Box<dyn Error>> {
+    use std::time::Duration;
+
+    let session = apple_ble::session::Session::new().await?;
+    let scan = session.scan().await?;
+    assert!(session.adapter.is_discovering().await?);
+    drop(scan);
+    // BlueZ stops discovering asynchronously.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(!session.adapter.is_discovering().await?);
+
+    let scan = session.scan().await?;
+    assert!(session.adapter.is_discovering().await?);
+    scan.stop_discovery();
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    assert!(!session.adapter.is_discovering().await?);
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn stats_count_registrations_and_decodes() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        Advertisable, AdvertisableData, AirPlayTargetAdvertisement, AirPlayTargetAdvertisementData,
+    };
+    use bluer::Address;
+    use std::net::Ipv4Addr;
+
+    let mut session = apple_ble::session::Session::new().await?;
+    let data = AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 1, 2));
+    let _handle = AirPlayTargetAdvertisement::register(&mut session, &data).await?;
+    assert_eq!(session.stats().registrations, 1);
+
+    let address = Address::new([0x11, 0x22, 0x33, 0x44, 0x55, 0x66]);
+    let octets = data.octets();
+    assert!(session.decode(address, &octets).is_some());
+    // The decoder rejects every truncated message, which is counted as a failure.
+    for len in 1..octets.len() {
+        assert!(session.decode(address, &octets[..len]).is_none());
+    }
+    let stats = session.clone().stats();
+    assert_eq!(stats.decoded.get(&0x09), Some(&1));
+    assert_eq!(stats.decode_failures.get(&0x09), Some(&(octets.len() - 1)));
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn restart_strategy_is_invoked() -> Result<(), Box<dyn Error>> {
+    use apple_ble::session::Restart;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static RESTARTS: AtomicUsize = AtomicUsize::new(0);
+    fn count_restart() -> Result<(), Box<dyn Error>> {
+        RESTARTS.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+
+    let mut session = apple_ble::session::Session::new().await?;
+    assert!(matches!(session.restart, Restart::Systemd));
+    session.restart = Restart::Custom(count_restart);
+    session.restart.restart()?;
+    assert_eq!(RESTARTS.load(Ordering::SeqCst), 1);
+    Restart::None.restart()?;
+    assert_eq!(RESTARTS.load(Ordering::SeqCst), 1);
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn address_progress_fires_in_order() -> Result<(), Box<dyn Error>> {
+    use apple_ble::error::AppleBleError;
+    use apple_ble::session::{
+        set_device_addr_with_progress, AddressProgress, CommandRunner, Restart,
+    };
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Mutex;
+
+    struct MockRunner<'a> {
+        commands: Mutex<Vec<String>>,
+        cancel_on_bdaddr: Option<&'a AtomicBool>,
+    }
+    impl CommandRunner for MockRunner<'_> {
+        fn escalate(&self) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+        fn run(&self, program: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
+            self.commands
+                .lock()
+                .unwrap()
+                .push(format!("{program} {}", args.join(" ")));
+            if let Some(cancel) = self.cancel_on_bdaddr {
+                cancel.store(true, Ordering::SeqCst);
+            }
+            Ok(())
+        }
+    }
+
+    let mut session = apple_ble::session::Session::new().await?;
+    session.restart = Restart::None;
+    let adapter = session.adapter.name().to_string();
+    let original_address = session.adapter.address().await?;
+
+    let cancel = AtomicBool::new(false);
+    let runner = MockRunner {
+        commands: Mutex::new(Vec::new()),
+        cancel_on_bdaddr: Some(&cancel),
+    };
+    let mut events = Vec::new();
+    let result = set_device_addr_with_progress(
+        &mut session,
+        &[0xc1, 0x02, 0x03, 0x04, 0x05, 0x06],
+        &runner,
+        |event| events.push(event),
+        &cancel,
+    )
+    .await;
+    assert_eq!(
+        result
+            .err()
+            .and_then(|err| err.downcast_ref::<AppleBleError>().cloned()),
+        Some(AppleBleError::Cancelled)
+    );
+    assert_eq!(
+        events,
+        vec![AddressProgress::Escalating, AddressProgress::SettingAddress]
+    );
+    // The previous address is written again after cancelling.
+    assert_eq!(
+        *runner.commands.lock().unwrap(),
+        vec![
+            format!("bdaddr -i {adapter} c1:02:03:04:05:06"),
+            format!(
+                "bdaddr -i {adapter} {}",
+                original_address.to_string().to_lowercase()
+            )
+        ]
+    );
+    assert!(!session.address_state.is_changed());
+
+    let cancel = AtomicBool::new(false);
+    let runner = MockRunner {
+        commands: Mutex::new(Vec::new()),
+        cancel_on_bdaddr: None,
+    };
+    let mut events = Vec::new();
+    set_device_addr_with_progress(
+        &mut session,
+        &[0xc1, 0x02, 0x03, 0x04, 0x05, 0x06],
+        &runner,
+        |event| events.push(event),
+        &cancel,
+    )
+    .await?;
+    assert_eq!(
+        events,
+        vec![
+            AddressProgress::Escalating,
+            AddressProgress::SettingAddress,
+            AddressProgress::RestartingBluetooth,
+            AddressProgress::Done
+        ]
+    );
+    assert_eq!(runner.commands.lock().unwrap().len(), 1);
+    assert!(session.address_state.is_changed());
+    // Clones share the state, so changing the address again through one fails.
+    assert_eq!(
+        set_device_addr_with_progress(
+            &mut session.clone(),
+            &[0xc1, 0x02, 0x03, 0x04, 0x05, 0x07],
+            &runner,
+            |_| {},
+            &cancel,
+        )
+        .await
+        .err()
+        .and_then(|err| err.downcast_ref::<AppleBleError>().cloned()),
+        Some(AppleBleError::AddressAlreadyChanged)
+    );
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn verifies_advertising() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        Advertisable, AirPlayTargetAdvertisement, AirPlayTargetAdvertisementData,
+    };
+    use apple_ble::session::advertising_live;
+    use std::net::Ipv4Addr;
+
+    assert!(advertising_live(Some(1)));
+    assert!(!advertising_live(Some(0)));
+    assert!(!advertising_live(None));
+
+    let mut session = apple_ble::session::Session::new().await?;
+    let handle = AirPlayTargetAdvertisement::register(
+        &mut session,
+        &AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 1, 2)),
+    )
+    .await?;
+    assert!(session.verify_advertising(&handle).await);
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn configures_discovery_filter() -> Result<(), Box<dyn Error>> {
+    use apple_ble::scan::le_discovery_filter;
+    use bluer::{DiscoveryFilter, DiscoveryTransport};
+
+    let mut session = apple_ble::session::Session::new().await?;
+    assert_eq!(session.discovery_filter, le_discovery_filter());
+    assert_eq!(session.discovery_filter.transport, DiscoveryTransport::Le);
+    assert!(session.discovery_filter.duplicate_data);
+
+    let filter = DiscoveryFilter {
+        rssi: Some(-70),
+        ..le_discovery_filter()
+    };
+    session.configure_discovery(filter.clone()).await?;
+    assert_eq!(session.discovery_filter, filter);
+    // The filter is applied again before scanning.
+    let scan = session.scan().await?;
+    scan.stop_discovery();
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn address_changes_are_rate_limited() -> Result<(), Box<dyn Error>> {
+    use apple_ble::error::AppleBleError;
+    use apple_ble::session::AddressChangeLimit;
+    use apple_ble::session::{set_device_addr_with_progress, CommandRunner, Restart};
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    let limit = AddressChangeLimit::new(Duration::from_secs(5));
+    let now = Instant::now();
+    assert_eq!(limit.check(now), Ok(()));
+    limit.record(now);
+    assert_eq!(
+        limit.check(now + Duration::from_secs(2)),
+        Err(AppleBleError::RateLimited {
+            retry_after: Duration::from_secs(3)
+        })
+    );
+    assert_eq!(limit.check(now + Duration::from_secs(5)), Ok(()));
+
+    struct MockRunner(Mutex<Vec<String>>);
+    impl CommandRunner for MockRunner {
+        fn escalate(&self) -> Result<(), Box<dyn Error>> {
+            Ok(())
+        }
+        fn run(&self, program: &str, args: &[&str]) -> Result<(), Box<dyn Error>> {
+            self.0
+                .lock()
+                .unwrap()
+                .push(format!("{program} {}", args.join(" ")));
+            Ok(())
+        }
+    }
+
+    let mut session = apple_ble::session::Session::new().await?;
+    session.restart = Restart::None;
+    session.address_change_limit = AddressChangeLimit::new(Duration::from_secs(60));
+    let runner = MockRunner(Mutex::new(Vec::new()));
+    let cancel = AtomicBool::new(false);
+    set_device_addr_with_progress(
+        &mut session,
+        &[0xc1, 0x02, 0x03, 0x04, 0x05, 0x06],
+        &runner,
+        |_| {},
+        &cancel,
+    )
+    .await?;
+    // Pretend the address was restored, so only the limit stands in the way.
+    session.address_state.record_restore();
+    let result = set_device_addr_with_progress(
+        &mut session,
+        &[0xc1, 0x02, 0x03, 0x04, 0x05, 0x07],
+        &runner,
+        |_| {},
+        &cancel,
+    )
+    .await;
+    assert!(matches!(
+        result.err().and_then(|err| err.downcast_ref::<AppleBleError>().cloned()),
+        Some(AppleBleError::RateLimited { retry_after }) if retry_after <= Duration::from_secs(60)
+    ));
+    assert_eq!(runner.0.lock().unwrap().len(), 1);
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn invalid_address_length() -> Result<(), Box<dyn Error>> {
+    use apple_ble::error::AppleBleError;
+    use apple_ble::session::{set_device_addr_with_progress, CommandRunner, Restart};
+    use std::sync::atomic::AtomicBool;
+
+    struct FailingRunner;
+    impl CommandRunner for FailingRunner {
+        fn escalate(&self) -> Result<(), Box<dyn Error>> {
+            panic!("An invalid address is rejected before escalating.")
+        }
+        fn run(&self, program: &str, _args: &[&str]) -> Result<(), Box<dyn Error>> {
+            panic!("An invalid address is rejected before running {program}.")
+        }
+    }
+
+    let mut session = apple_ble::session::Session::new().await?;
+    session.restart = Restart::None;
+    let cancel = AtomicBool::new(false);
+    for len in [5, 7] {
+        let result = set_device_addr_with_progress(
+            &mut session,
+            &vec![0x00; len],
+            &FailingRunner,
+            |_| {},
+            &cancel,
+        )
+        .await;
+        assert_eq!(
+            result
+                .err()
+                .and_then(|err| err.downcast_ref::<AppleBleError>().cloned()),
+            Some(AppleBleError::InvalidAddress { len })
+        );
+    }
+    assert!(!session.address_state.is_changed());
+    Ok(())
+}
+
+#[cfg(feature = "airplay")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn reads_back_advertised_data() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        Advertisable, AdvertisableData, AirPlayTargetAdvertisement, AirPlayTargetAdvertisementData,
+    };
+    use std::net::Ipv4Addr;
+    use std::time::Duration;
+
+    let names = bluer::Session::new().await?.adapter_names().await?;
+    if names.len() < 2 {
+        return Ok(());
+    }
+    let mut session = apple_ble::session::Session::with_adapter(&names[0]).await?;
+    let other = apple_ble::session::Session::with_adapter(&names[1]).await?;
+    let user_data = AirPlayTargetAdvertisementData::new(Ipv4Addr::new(192, 168, 0, 11));
+    let _handle = AirPlayTargetAdvertisement::register(&mut session, &user_data).await?;
+    let manufacturer_data = session
+        .read_back_manufacturer_data(&other, Duration::from_secs(10))
+        .await?;
+    assert_eq!(
+        manufacturer_data.get(&session.company_id),
+        Some(&user_data.octets())
+    );
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn advertises_raw_manufacturer_data() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        Advertisable, AdvertisementType, RawAppleAdvertisement, RawAppleAdvertisementData,
+    };
+    use std::time::Duration;
+
+    let names = bluer::Session::new().await?.adapter_names().await?;
+    if names.len() < 2 {
+        return Ok(());
+    }
+    let mut session = apple_ble::session::Session::with_adapter(&names[0]).await?;
+    let other = apple_ble::session::Session::with_adapter(&names[1]).await?;
+    // An unassigned message type, which is only decoded as unknown.
+    let raw = RawAppleAdvertisementData(vec![0x42, 0x03, 0xde, 0xad, 0x01]);
+    let _handle = RawAppleAdvertisement::register(&mut session, &raw).await?;
+    let manufacturer_data = session
+        .read_back_manufacturer_data(&other, Duration::from_secs(10))
+        .await?;
+    let received = manufacturer_data
+        .get(&session.company_id)
+        .ok_or("No manufacturer data received.")?;
+    assert_eq!(received, &raw.0);
+    assert_eq!(
+        AdvertisementType::from_manufacturer_data(session.adapter.address().await?, received),
+        Some(AdvertisementType::Unknown {
+            message_type: 0x42,
+            data: raw.0.clone()
+        })
+    );
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn adapter_is_powered_after_reset() -> Result<(), Box<dyn Error>> {
+    let session = apple_ble::session::Session::new().await?;
+    session.reset_adapter(false).await?;
+    assert!(session.adapter.is_powered().await?);
+    Ok(())
+}
+
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn connecting_times_out() -> Result<(), Box<dyn Error>> {
+    use apple_ble::error::AppleBleError;
+    use apple_ble::session::connect_timeout;
+    use std::time::Duration;
+
+    let timeout = Duration::from_millis(50);
+    let stalled = std::future::pending::<Result<(), AppleBleError>>();
+    assert_eq!(
+        connect_timeout(stalled, timeout).await,
+        Err(AppleBleError::Timeout { timeout })
+    );
+    assert_eq!(connect_timeout(async { Ok(1) }, timeout).await, Ok(1));
+    Ok(())
+}
+
+#[cfg(all(feature = "airprint", feature = "gatt"))]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn connects_to_connectable_airprint() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        AirPrintAdvertisement, AirPrintAdvertisementData, AirPrintSecurity, AIRPRINT_ADDRESS_TYPE,
+        AIRPRINT_RESOURCE_PATH,
+    };
+    use apple_ble::scan::wait_for_device;
+    use std::net::Ipv6Addr;
+    use std::time::Duration;
+
+    let names = bluer::Session::new().await?.adapter_names().await?;
+    if names.len() < 2 {
+        return Ok(());
+    }
+    let mut session = apple_ble::session::Session::with_adapter(&names[0]).await?;
+    let other = apple_ble::session::Session::with_adapter(&names[1]).await?;
+    let user_data = AirPrintAdvertisementData {
+        address_type: AIRPRINT_ADDRESS_TYPE,
+        resource_path: AIRPRINT_RESOURCE_PATH,
+        security: AirPrintSecurity::Ipps,
+        port: 631,
+        ip_addr: Ipv6Addr::LOCALHOST,
+        power: 100,
+    };
+    let _handle = AirPrintAdvertisement::register_connectable(&mut session, &user_data).await?;
+    let address = session.adapter.address().await?;
+    let _ = other.adapter.remove_device(address).await;
+    let events = other.adapter.discover_devices().await?;
+    let rssi = |address| {
+        let adapter = other.adapter.clone();
+        async move { adapter.device(address).ok()?.rssi().await.ok()? }
+    };
+    wait_for_device(address, events, rssi, Duration::from_secs(10))
+        .await
+        .ok_or("The printer wasn't discovered.")?;
+    let device = other.adapter.device(address)?;
+    device.connect().await?;
+    assert!(device.is_connected().await?);
+    device.disconnect().await?;
+    Ok(())
+}
+
+#[cfg(feature = "airdrop")]
+#[test(flavor = "multi_thread", worker_threads = 1)]
+async fn dynamic_advertisement_changes_payload() -> Result<(), Box<dyn Error>> {
+    use apple_ble::advertisement::{
+        Advertisable, AdvertisableData, AirDropAdvertisement, AirDropAdvertisementData, AirDropMode,
+    };
+    use std::sync::atomic::{AtomicU8, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    fn airdrop(count: u8) -> AirDropAdvertisementData {
+        AirDropAdvertisementData {
+            apple_id: [count, count],
+            phone: [0x00, 0x00],
+            email: [0x00, 0x00],
+            email2: None,
+            mode: AirDropMode::ContactsOnly,
+        }
+    }
+
+    let names = bluer::Session::new().await?.adapter_names().await?;
+    if names.len() < 2 {
+        return Ok(());
+    }
+    let mut session = apple_ble::session::Session::with_adapter(&names[0]).await?;
+    let other = apple_ble::session::Session::with_adapter(&names[1]).await?;
+    let counter = Arc::new(AtomicU8::new(0));
+    let update_counter = counter.clone();
+    let _handle = AirDropAdvertisement::register_dynamic(
+        &mut session,
+        &airdrop(0),
+        move || airdrop(update_counter.fetch_add(1, Ordering::SeqCst) + 1),
+        Duration::from_millis(200),
+    )
+    .await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    let manufacturer_data = session
+        .read_back_manufacturer_data(&other, Duration::from_secs(10))
+        .await?;
+    let received = manufacturer_data
+        .get(&session.company_id)
+        .ok_or("No manufacturer data received.")?;
+    assert_ne!(received, &airdrop(0).octets());
+    // The Apple ID hash follows the type, the length, the padding and the version.
+    let count = received[11];
+    assert!((1..=counter.load(Ordering::SeqCst)).contains(&count));
+    assert_eq!(received, &airdrop(count).octets());
+    Ok(())
+}